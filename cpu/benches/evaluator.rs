@@ -1,10 +1,11 @@
 #![feature(test)]
 extern crate test;
-use ghoti_cpu::evaluator::Evaluator;
-use puyoai::{decision::Decision, field::CoreField, plan::Plan};
+use ghoti_cpu::evaluator::{Evaluator, FeatureCache};
+use puyoai::{
+    color::PuyoColor, decision::Decision, field::CoreField, kumipuyo::Kumipuyo, plan::Plan,
+};
 
-#[bench]
-fn bench_evaluator(b: &mut test::Bencher) {
+fn sample_plan() -> Plan {
     let mut cf = CoreField::from_str(concat!(
         ".GY..G", // 11
         ".GGYBG", // 10
@@ -20,7 +21,7 @@ fn bench_evaluator(b: &mut test::Bencher) {
     ));
     let decisions = vec![Decision::new(2, 2), Decision::new(1, 1)];
     let rensa_result = cf.simulate();
-    let plan = Plan::new(
+    Plan::new(
         cf,
         decisions,
         rensa_result,
@@ -32,8 +33,139 @@ fn bench_evaluator(b: &mut test::Bencher) {
         30,
         40,
         false,
-    );
+    )
+}
 
+#[bench]
+fn bench_evaluator(b: &mut test::Bencher) {
+    let plan = sample_plan();
     let evaluator = Evaluator::default();
     b.iter(|| test::black_box(evaluator.clone().evaluate(&plan.clone())));
 }
+
+/// 一度評価した盤面をもう一度評価する場合（毎回キャッシュに当たる）
+#[bench]
+fn bench_evaluator_cached(b: &mut test::Bencher) {
+    let plan = sample_plan();
+    let evaluator = Evaluator::default();
+    let mut cache = FeatureCache::new();
+    b.iter(|| test::black_box(evaluator.evaluate_cached(&plan, &mut cache)));
+}
+
+fn bench_two_moves(b: &mut test::Bencher, cached: bool) {
+    let cf = CoreField::from_str(concat!(
+        "......", // 4
+        "GRB...", // 3
+        "GGRB..", // 2
+        "RRBB.."  // 1
+    ));
+    // 2 手ぶんのツモの組み合わせ全部（1 回に 1 つずつ、順番に評価する）
+    let colors = [
+        PuyoColor::RED,
+        PuyoColor::BLUE,
+        PuyoColor::YELLOW,
+        PuyoColor::GREEN,
+    ];
+    let kumipuyos: Vec<Kumipuyo> = colors
+        .iter()
+        .flat_map(|&axis| colors.iter().map(move |&child| Kumipuyo::new(axis, child)))
+        .collect();
+    let seqs: Vec<Vec<Kumipuyo>> = kumipuyos
+        .iter()
+        .flat_map(|first| {
+            kumipuyos
+                .iter()
+                .map(move |second| vec![first.clone(), second.clone()])
+        })
+        .collect();
+    let evaluator = Evaluator::default();
+
+    // キャッシュは使い回して、何周もしたあとの（定常状態の）当たり具合で測る
+    // 手順違いやツモ違いで同じ盤面になる分だけ、キャッシュで計算を省ける
+    let mut cache = FeatureCache::new();
+    cache.set_parent(&cf);
+    let mut i = 0;
+    b.iter(|| {
+        let seq = &seqs[i % seqs.len()];
+        i += 1;
+        Plan::iterate_available_plans(&cf, seq, 2, &mut |plan: &Plan| {
+            let score = if cached {
+                evaluator.evaluate_cached(plan, &mut cache)
+            } else {
+                evaluator.evaluate(plan)
+            };
+            test::black_box(score);
+        });
+    });
+    if cached {
+        eprintln!(
+            "feature cache: {} hits, {} misses ({:.1}% hit)",
+            cache.hits(),
+            cache.misses(),
+            100.0 * cache.hits() as f64 / (cache.hits() + cache.misses()).max(1) as f64
+        );
+    }
+}
+
+/// 2 手先までのすべての手を評価する場合
+#[bench]
+fn bench_evaluator_two_moves(b: &mut test::Bencher) {
+    bench_two_moves(b, false);
+}
+
+#[bench]
+fn bench_evaluator_two_moves_cached(b: &mut test::Bencher) {
+    bench_two_moves(b, true);
+}
+
+fn bench_unseen(b: &mut test::Bencher, cached: bool) {
+    let cf = CoreField::from_str(concat!(
+        "......", // 4
+        "GRB...", // 3
+        "GGRB..", // 2
+        "RRBB.."  // 1
+    ));
+    let seq = vec![
+        Kumipuyo::new(PuyoColor::RED, PuyoColor::BLUE),
+        Kumipuyo::new(PuyoColor::YELLOW, PuyoColor::GREEN),
+    ];
+    let mut parents = vec![];
+    Plan::iterate_available_plans(&cf, &seq[..1], 1, &mut |plan: &Plan| {
+        parents.push(plan.clone());
+    });
+    let evaluator = Evaluator::default();
+
+    // ビームサーチと同じように、1 手目の盤面を評価してから、それを 1 手前の盤面にして 2 手目の盤面を評価する
+    // キャッシュは毎回作り直すので、どの盤面も初めて見る盤面になる
+    b.iter(|| {
+        let mut cache = FeatureCache::new();
+        for parent in &parents {
+            if cached {
+                cache.set_parent(&cf);
+                test::black_box(evaluator.evaluate_cached(parent, &mut cache));
+                cache.set_parent(parent.field());
+            } else {
+                test::black_box(evaluator.evaluate(parent));
+            }
+            Plan::iterate_available_plans(parent.field(), &seq[1..], 1, &mut |plan: &Plan| {
+                let score = if cached {
+                    evaluator.evaluate_cached(plan, &mut cache)
+                } else {
+                    evaluator.evaluate(plan)
+                };
+                test::black_box(score);
+            });
+        }
+    });
+}
+
+/// キャッシュが見たことのない盤面だけを評価する場合（差分で計算できる分だけ速くなる）
+#[bench]
+fn bench_evaluator_unseen(b: &mut test::Bencher) {
+    bench_unseen(b, false);
+}
+
+#[bench]
+fn bench_evaluator_unseen_cached(b: &mut test::Bencher) {
+    bench_unseen(b, true);
+}
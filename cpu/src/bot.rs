@@ -4,15 +4,21 @@ pub mod beam_search_ai;
 pub mod chain_focused_ai;
 pub mod chain_potential_ai;
 pub mod hybrid_ai;
+pub mod monte_carlo;
 pub mod random_ai;
+pub mod registry;
+pub mod rules;
 pub mod stable_ai;
 pub mod takapt_ai;
+pub mod worker_pool;
 
-pub use ai::{AIDecision, PlayerState, AI};
+pub use ai::{elapsed_frames, think_deadline, AIDecision, AIDiagnostics, MoveKind, PlayerState, AI};
 pub use beam_search_ai::beam_search_ai::BeamSearchAI;
 pub use chain_focused_ai::chain_focused_ai::ChainFocusedAI;
 pub use chain_potential_ai::ChainPotentialAI;
 pub use hybrid_ai::hybrid_ai::HybridAI;
 pub use random_ai::random_ai::RandomAI;
+pub use registry::{ai_names, create_ai, AISpec};
+pub use rules::{MarginTime, Rules};
 pub use stable_ai::stable_ai::StableAI;
 pub use takapt_ai::takapt_ai::TakaptAI;
@@ -1,6 +1,19 @@
-use std::{time::Duration, vec::Vec};
+use std::{
+    time::{Duration, Instant},
+    vec::Vec,
+};
 
-use puyoai::{decision::Decision, field::core_field::CoreField, kumipuyo::kumipuyo::Kumipuyo};
+use puyoai::{
+    decision::Decision,
+    es_field::EsCoreField,
+    es_frame,
+    field::core_field::CoreField,
+    kumipuyo::kumipuyo::Kumipuyo,
+    plan::{OjamaStatus, Plan},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::bot::{monte_carlo::Aggregation, rules::Rules};
 
 pub trait AI {
     fn new() -> Self
@@ -13,6 +26,64 @@ pub trait AI {
         player_state_2p: Option<PlayerState>,
         think_frame: Option<usize>,
     ) -> AIDecision;
+    /// モンテカルロで使う乱数のシードを固定する（対応していない AI では何もしない）
+    fn set_seed(&mut self, _seed: u64) {}
+    /// モンテカルロの各試行の結果から初手を選ぶ方法を変える（対応していない AI では何もしない）
+    fn set_aggregation(&mut self, _aggregation: Aggregation) {}
+}
+
+/// 思考結果の手の種類
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MoveKind {
+    /// 連鎖を組む
+    #[default]
+    Build,
+    /// 連鎖を発火する
+    Fire,
+    /// 定石（テンプレ）通りに置く
+    Opening,
+    /// 置ける場所がなく、死ぬしかない
+    Suicide,
+}
+
+/// 思考結果の詳細（`log_output` の文字列を解析しなくても読めるように）
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AIDiagnostics {
+    /// 手の種類
+    pub kind: MoveKind,
+    /// 見込んでいる連鎖数（発火しないなら 0）
+    pub chain: usize,
+    /// 見込んでいる連鎖の得点（発火しないなら 0）
+    pub score: usize,
+    /// 選んだ手の評価値
+    pub eval: Option<i32>,
+    /// 探索した深さ
+    pub depth: usize,
+    /// 展開したノード数
+    pub nodes: usize,
+}
+
+impl AIDiagnostics {
+    pub fn new(kind: MoveKind) -> Self {
+        AIDiagnostics {
+            kind,
+            ..Default::default()
+        }
+    }
+    /// `Plan` を選んだときの詳細（連鎖するなら発火とみなす）
+    pub fn from_plan(plan: &Plan, eval: i32) -> Self {
+        AIDiagnostics {
+            kind: if plan.chain() > 0 {
+                MoveKind::Fire
+            } else {
+                MoveKind::Build
+            },
+            chain: plan.chain(),
+            score: plan.score(),
+            eval: Some(eval),
+            ..Default::default()
+        }
+    }
 }
 
 #[derive(Clone)]
@@ -20,6 +91,7 @@ pub struct AIDecision {
     pub decisions: Vec<Decision>,
     pub log_output: String,
     pub elapsed: Duration,
+    pub diagnostics: AIDiagnostics,
 }
 
 impl AIDecision {
@@ -28,6 +100,7 @@ impl AIDecision {
             decisions,
             log_output,
             elapsed,
+            diagnostics: AIDiagnostics::default(),
         }
     }
     pub fn from_decision(decision: &Decision, log_output: String, elapsed: Duration) -> Self {
@@ -35,6 +108,7 @@ impl AIDecision {
             decisions: vec![decision.clone()],
             log_output,
             elapsed,
+            diagnostics: AIDiagnostics::default(),
         }
     }
     pub fn zero() -> Self {
@@ -42,8 +116,26 @@ impl AIDecision {
             decisions: vec![Decision::new(0, 0)],
             log_output: "".to_string(),
             elapsed: Duration::ZERO,
+            diagnostics: AIDiagnostics::default(),
         }
     }
+    pub fn with_diagnostics(mut self, diagnostics: AIDiagnostics) -> Self {
+        self.diagnostics = diagnostics;
+        self
+    }
+    pub fn is_fire(&self) -> bool {
+        self.diagnostics.kind == MoveKind::Fire
+    }
+}
+
+/// `think_frame`（思考に使えるフレーム数）を、思考の締め切り時刻に変換する
+pub fn think_deadline(start: Instant, think_frame: Option<usize>) -> Option<Instant> {
+    think_frame.map(|frame| start + Duration::from_secs_f64(frame as f64 / es_frame::FPS as f64))
+}
+
+/// 思考にかかった時間を、フレーム数に換算する（端数は切り上げ）
+pub fn elapsed_frames(elapsed: Duration) -> usize {
+    (elapsed.as_secs_f64() * es_frame::FPS as f64).ceil() as usize
 }
 
 #[derive(Clone)]
@@ -62,12 +154,16 @@ pub struct PlayerState {
     pub fixed_ojama: usize,
     /// 予告おじゃまぷよ
     pub pending_ojama: usize,
+    /// 全消しボーナスを持っているか（次の連鎖でおじゃまを上乗せして送る）
+    pub zenkeshi: bool,
     /// 現在の連鎖数
     pub current_chain: usize,
     /// ツモ番号
     pub tumo_index: usize,
     /// 配ぷよ全体
     haipuyo: Option<Vec<Kumipuyo>>,
+    /// 対戦のルール
+    pub rules: Rules,
 }
 
 impl PlayerState {
@@ -79,6 +175,7 @@ impl PlayerState {
         carry_over: usize,
         fixed_ojama: usize,
         pending_ojama: usize,
+        zenkeshi: bool,
         current_chain: usize,
         tumo_index: usize,
         haipuyo: Option<Vec<Kumipuyo>>,
@@ -91,9 +188,11 @@ impl PlayerState {
             carry_over,
             fixed_ojama,
             pending_ojama,
+            zenkeshi,
             current_chain,
             tumo_index,
             haipuyo,
+            rules: Rules::default(),
         }
     }
     pub fn initial_state(seq: Vec<Kumipuyo>, haipuyo: Option<Vec<Kumipuyo>>) -> Self {
@@ -105,9 +204,11 @@ impl PlayerState {
             carry_over: 0,
             fixed_ojama: 0,
             pending_ojama: 0,
+            zenkeshi: false,
             current_chain: 0,
             tumo_index: 0,
             haipuyo,
+            rules: Rules::default(),
         }
     }
     pub fn zero() -> Self {
@@ -119,11 +220,17 @@ impl PlayerState {
             carry_over: 0,
             fixed_ojama: 0,
             pending_ojama: 0,
+            zenkeshi: false,
             current_chain: 0,
             tumo_index: 0,
             haipuyo: None,
+            rules: Rules::default(),
         }
     }
+    pub fn with_rules(mut self, rules: Rules) -> Self {
+        self.rules = rules;
+        self
+    }
 
     pub fn set_seq(&mut self, visible_tumos: usize) {
         debug_assert!(self.haipuyo.is_some());
@@ -148,4 +255,20 @@ impl PlayerState {
     pub fn drop_kumipuyo(&mut self, decision: &Decision) {
         self.field.drop_kumipuyo(decision, &self.seq[0]);
     }
+    /// プランの列挙に渡す、自分に降るおじゃまぷよの状態
+    /// - 予告おじゃまぷよは相手の連鎖が終わったときに確定する（相手が分からなければすぐに確定するとみなす）
+    pub fn ojama_status(&self, player_state_2p: Option<&PlayerState>) -> OjamaStatus {
+        let ojama_committing_frame_id = player_state_2p.map_or(0, |player_state_2p| {
+            let mut field = player_state_2p.field.clone();
+            let rensa_result = field.es_simulate_from_middle(player_state_2p.current_chain);
+            (player_state_2p.frame + rensa_result.frame).saturating_sub(self.frame)
+        });
+        OjamaStatus::new(
+            self.fixed_ojama,
+            self.pending_ojama,
+            ojama_committing_frame_id,
+            self.zenkeshi,
+        )
+        .with_ojama_per_drop(self.rules.ojama_per_drop)
+    }
 }
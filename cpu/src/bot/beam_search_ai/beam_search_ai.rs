@@ -1,4 +1,4 @@
-use std::{sync::mpsc, thread, time::Instant, vec::Vec};
+use std::{collections::HashMap, sync::Mutex, time::Instant, vec::Vec};
 
 use puyoai::{
     color::Color,
@@ -6,19 +6,62 @@ use puyoai::{
     decision::Decision,
     es_field::EsCoreField,
     field::{self, CoreField},
-    kumipuyo::{kumipuyo_seq::generate_random_puyocolor_sequence, Kumipuyo},
-    plan::Plan,
+    kumipuyo::Kumipuyo,
+    plan::{OjamaStatus, Plan},
     rensa_detector::{detector::detect_by_drop, PurposeForFindingRensa},
     rensa_result::RensaResult,
 };
+use rand::rngs::StdRng;
+use rayon::prelude::*;
+
+use crate::{
+    bot::{
+        monte_carlo::{generate_random_tumos, monte_carlo_rng, Aggregation},
+        worker_pool::worker_pool,
+        *,
+    },
+    evaluator::{
+        feature_cache::{field_key, FieldKey},
+        Evaluator, FeatureCache,
+    },
+    opening_matcher::OpeningMatcher,
+};
 
-use crate::{bot::*, evaluator::Evaluator, opening_matcher::OpeningMatcher};
+/// 思考フレーム数が指定された場合に、締め切りまで順に試す (深さ, ビーム幅)
+/// - 先頭から `search_for_think_frame` の結果までは、思考フレーム数に応じて飛ばす
+const ANYTIME_SCHEDULE: &[(usize, usize)] = &[(20, 20), (30, 60), (40, 140), (40, 400)];
 
 pub struct BeamSearchAI {
     /// 盤面の評価器
     evaluator: Evaluator,
     /// 序盤のテンプレ
     opening_matcher: OpeningMatcher,
+    /// 各スレッドの探索結果（次の思考で再利用する）
+    /// - キーは (次に思考するときのツモ番号, 打つ手を置いた後の盤面)
+    search_trees: Mutex<HashMap<(usize, FieldKey), Vec<SearchTree>>>,
+    /// モンテカルロの乱数のシード（`None` なら毎回ランダム）
+    seed: Option<u64>,
+    /// モンテカルロの各試行の結果から初手を選ぶ方法
+    aggregation: Aggregation,
+    /// 思考フレーム数が指定されなかったときの (深さ, ビーム幅, モンテカルロの試行回数)
+    /// - 既定値は `search_for_think_frame(0)` と同じ
+    search: (usize, usize, usize),
+}
+
+/// 次の思考のために覚えておく探索結果の数の上限
+/// （1 つの AI が両方のプレイヤーを担当することもあるので、複数覚えておく）
+const MAX_STORED_SEARCH_TREES: usize = 4;
+
+/// 思考フレーム数に応じた、最初に試す (深さ, ビーム幅)
+fn search_for_think_frame(think_frame: usize) -> (usize, usize) {
+    // TODO: チューニングする
+    if think_frame <= 2 {
+        (20, 20)
+    } else if think_frame <= 8 {
+        (30, 60)
+    } else {
+        (40, 140)
+    }
 }
 
 impl BeamSearchAI {
@@ -27,8 +70,26 @@ impl BeamSearchAI {
         BeamSearchAI {
             evaluator,
             opening_matcher,
+            search_trees: Mutex::new(HashMap::new()),
+            seed: None,
+            aggregation: Aggregation::default(),
+            search: (20, 20, 20),
         }
     }
+
+    /// 思考フレーム数が指定されなかったときの (深さ, ビーム幅, モンテカルロの試行回数)
+    pub fn search(&self) -> (usize, usize, usize) {
+        self.search
+    }
+
+    pub fn with_search(mut self, depth: usize, width: usize, parallel: usize) -> Self {
+        self.search = (depth, width, parallel);
+        self
+    }
+
+    pub fn evaluator(&self) -> &Evaluator {
+        &self.evaluator
+    }
 }
 
 impl AI for BeamSearchAI {
@@ -36,6 +97,10 @@ impl AI for BeamSearchAI {
         BeamSearchAI {
             evaluator: Evaluator::default(),
             opening_matcher: OpeningMatcher::new("opening_vis2.json").unwrap(),
+            search_trees: Mutex::new(HashMap::new()),
+            seed: None,
+            aggregation: Aggregation::default(),
+            search: (20, 20, 20),
         }
     }
 
@@ -49,20 +114,96 @@ impl AI for BeamSearchAI {
         player_state_2p: Option<PlayerState>,
         think_frame: Option<usize>,
     ) -> AIDecision {
-        let think_frame = think_frame.unwrap_or(0);
-        // TODO: チューニングする
-        let (depth, width) = if think_frame <= 2 {
-            (20, 20)
-        } else if think_frame <= 8 {
-            (30, 60)
-        } else {
-            (40, 140)
-        };
-        self.think_internal(player_state_1p, player_state_2p, depth, width, 20)
+        let start = Instant::now();
+        let seeds = self.take_search_trees(&player_state_1p);
+        match (think_frame, think_deadline(start, think_frame)) {
+            (Some(think_frame), Some(deadline)) => self.think_with_deadline(
+                player_state_1p,
+                player_state_2p,
+                &seeds,
+                think_frame,
+                deadline,
+            ),
+            _ => {
+                let (depth, width, parallel) = self.search;
+                self.think_internal(
+                    player_state_1p,
+                    player_state_2p,
+                    depth,
+                    width,
+                    parallel,
+                    &seeds,
+                    None,
+                )
+            }
+        }
+    }
+
+    fn set_seed(&mut self, seed: u64) {
+        self.seed = Some(seed);
+    }
+
+    fn set_aggregation(&mut self, aggregation: Aggregation) {
+        self.aggregation = aggregation;
     }
 }
 
 impl BeamSearchAI {
+    /// 前回の思考の探索結果を、実際に打たれた手で根を付け替えて取り出す
+    fn take_search_trees(&self, player_state_1p: &PlayerState) -> Vec<SearchTree> {
+        let mut stored = self.search_trees.lock().unwrap();
+
+        // 新しい試合では、前の試合の探索結果を使わない
+        if player_state_1p.tumo_index == 0 {
+            stored.clear();
+            return vec![];
+        }
+
+        let key = (
+            player_state_1p.tumo_index,
+            field_key(&player_state_1p.field),
+        );
+        match stored.remove(&key) {
+            Some(search_trees) => search_trees
+                .iter()
+                .filter_map(|search_tree| {
+                    search_tree.reroot(&player_state_1p.field, &player_state_1p.seq)
+                })
+                .collect(),
+            None => vec![],
+        }
+    }
+
+    /// ツモ番号 `tumo_index` で `decision` を打つときに、探索結果を次の思考のために覚えておく
+    fn store_search_trees(
+        &self,
+        tumo_index: usize,
+        decision: &Decision,
+        search_trees: Vec<SearchTree>,
+    ) {
+        // 次の思考で使うのは、実際に打つ手の後の State だけ
+        let search_trees: Vec<SearchTree> = search_trees
+            .iter()
+            .filter_map(|search_tree| search_tree.pruned(decision))
+            .collect();
+        let Some(field) = search_trees
+            .first()
+            .map(|search_tree| field_key(&search_tree.layers[0][0].field))
+        else {
+            return;
+        };
+
+        let mut stored = self.search_trees.lock().unwrap();
+        let key = (tumo_index + 1, field);
+        if stored.len() >= MAX_STORED_SEARCH_TREES && !stored.contains_key(&key) {
+            // 一番古いツモ番号のものを忘れる
+            if let Some(oldest) = stored.keys().min().cloned() {
+                stored.remove(&oldest);
+            }
+        }
+        stored.insert(key, search_trees);
+    }
+
     /// ビームサーチの結果から候補手と評価値のリストを返す（サジェスト用）
     pub fn get_suggestions(
         &self,
@@ -76,22 +217,29 @@ impl BeamSearchAI {
 
         // ツモを伸ばす（必要に応じてランダムツモを生成）
         let visible_tumos = seq.len();
+        let mut rng = monte_carlo_rng(self.seed, player_state_1p.tumo_index, 0);
         let seq: Vec<Kumipuyo> = seq
             .iter()
             .cloned()
-            .chain(generate_random_puyocolor_sequence(
+            .chain(generate_random_tumos(
                 if depth > visible_tumos {
                     depth - visible_tumos
                 } else {
                     0
                 },
+                player_state_1p.rules.num_colors,
+                &mut rng,
             ))
             .collect();
 
-        let mut state_v: Vec<State> = vec![State::from_field(cf)];
+        let mut state_v: Vec<State> =
+            vec![State::from_field(cf, player_state_1p.ojama_status(None))];
         let mut fired_v: Vec<State> =
             Vec::with_capacity(width * Decision::all_valid_decisions().len() * depth);
 
+        let mut transposition_table = TranspositionTable::new();
+        let mut feature_cache = FeatureCache::new();
+
         // ビームサーチを実行
         for cur_depth in 0..depth.min(seq.len()) {
             // 次の状態を列挙
@@ -102,9 +250,13 @@ impl BeamSearchAI {
                     &cur_state,
                     &mut next_state_v,
                     &mut fired_v,
+                    &mut transposition_table,
+                    cur_depth,
                     &seq[cur_depth],
                     cur_depth < visible_tumos,
                     &self.evaluator,
+                    &mut feature_cache,
+                    None,
                 );
             }
             if next_state_v.is_empty() {
@@ -179,6 +331,49 @@ impl BeamSearchAI {
         suggestions
     }
 
+    /// 思考フレーム数に応じた深さとビーム幅から始めて、締め切りまで広げながら探索する
+    fn think_with_deadline(
+        &self,
+        player_state_1p: PlayerState,
+        player_state_2p: Option<PlayerState>,
+        seeds: &[SearchTree],
+        think_frame: usize,
+        deadline: Instant,
+    ) -> AIDecision {
+        let start = Instant::now();
+        let first = search_for_think_frame(think_frame);
+        let schedule = ANYTIME_SCHEDULE
+            .iter()
+            .skip_while(|&&search| search != first);
+
+        let mut best_ai_decision: Option<AIDecision> = None;
+        for &(depth, width) in schedule {
+            let ai_decision = self.think_internal(
+                player_state_1p.clone(),
+                player_state_2p.clone(),
+                depth,
+                width,
+                20,
+                seeds,
+                Some(deadline),
+            );
+
+            // 締め切りまでに探索しきれなかった結果は、他に結果がない場合にのみ使う
+            if Instant::now() >= deadline {
+                if best_ai_decision.is_none() {
+                    best_ai_decision = Some(ai_decision);
+                }
+                break;
+            }
+            best_ai_decision = Some(ai_decision);
+        }
+
+        AIDecision {
+            elapsed: start.elapsed(),
+            ..best_ai_decision.unwrap()
+        }
+    }
+
     fn think_internal(
         &self,
         player_state_1p: PlayerState,
@@ -186,8 +381,11 @@ impl BeamSearchAI {
         depth: usize,
         width: usize,
         parallel: usize,
+        seeds: &[SearchTree],
+        deadline: Option<Instant>,
     ) -> AIDecision {
         let start = Instant::now();
+        let tumo_index = player_state_1p.tumo_index;
 
         // 最序盤のみテンプレを使う
         if player_state_1p.tumo_index < 5 {
@@ -200,7 +398,8 @@ impl BeamSearchAI {
                     &decision,
                     format!("OpeningMatcher"),
                     start.elapsed(),
-                );
+                )
+                .with_diagnostics(AIDiagnostics::new(MoveKind::Opening));
             }
         }
 
@@ -237,7 +436,16 @@ impl BeamSearchAI {
         let third_row_height_1p = player_state_1p.field.height(3);
         let fire_condition = move |state: &State, player_state_2p: &Option<PlayerState>| -> bool {
             let plan = state.clone().plan.unwrap();
-            let ojama_from_1p_chain = (plan.score() + player_state_1p.carry_over) / 70;
+            let rules = &player_state_1p.rules;
+            let ojama_rate = rules.ojama_rate_at(player_state_1p.frame);
+            // 全消しボーナスを持っていたら、その分を上乗せして送れる
+            let zenkeshi_bonus = if player_state_1p.zenkeshi {
+                rules.zenkeshi_bonus
+            } else {
+                0
+            };
+            let ojama_from_1p_chain =
+                (plan.score() + player_state_1p.carry_over + zenkeshi_bonus) / ojama_rate;
 
             // 序盤（最初の12秒）の全消し
             if player_state_1p.frame <= 60 * 12 && plan.chain() <= 3 && plan.field().is_zenkeshi() {
@@ -317,7 +525,7 @@ impl BeamSearchAI {
                     let ojama_sum_1p = player_state_1p.fixed_ojama + player_state_1p.pending_ojama;
                     let ojama_sum_2p = player_state_2p.fixed_ojama + player_state_2p.pending_ojama;
                     let ojama_from_2p_chain = if player_state_2p.current_chain > 0 {
-                        (rensa_result_2p.score + player_state_2p.carry_over) / 70
+                        (rensa_result_2p.score + player_state_2p.carry_over) / ojama_rate
                     } else {
                         0
                     };
@@ -385,9 +593,6 @@ impl BeamSearchAI {
             return plan.score() >= 80000;
         };
 
-        // 各スレッドの結果をまとめる
-        let (tx, rx): (mpsc::Sender<AIDecision>, mpsc::Receiver<AIDecision>) = mpsc::channel();
-
         // ツモが十分に渡されてたら、モンテカルロをする必要がない
         let parallel = if player_state_1p.seq.len() < depth {
             parallel
@@ -395,72 +600,90 @@ impl BeamSearchAI {
             1
         };
 
-        for _ in 0..parallel {
-            let depth_c = depth;
-            let width_c = width;
-            let tx_c = tx.clone();
-            let player_state_1p_c = player_state_1p.clone();
-            let player_state_2p_c = player_state_2p.clone();
-            let fire_condition_c = fire_condition.clone();
-            let evaluator_c = self.evaluator.clone();
-
-            thread::spawn(move || {
-                tx_c.send(think_single_thread(
-                    depth_c,
-                    width_c,
-                    &player_state_1p_c,
-                    &player_state_2p_c,
-                    fire_condition_c,
-                    &evaluator_c,
-                ))
-                .ok();
-            });
-        }
+        // 各試行を共有のワーカープールで走らせる
+        // （終わった順ではなく試行の番号順に並ぶので、シードを固定したときに結果が再現する）
+        let results: Vec<(AIDecision, SearchTree)> = worker_pool().install(|| {
+            (0..parallel)
+                .into_par_iter()
+                .map(|i| {
+                    think_single_thread(
+                        depth,
+                        width,
+                        &player_state_1p,
+                        &player_state_2p,
+                        &fire_condition,
+                        &self.evaluator,
+                        seeds.get(i).cloned(),
+                        &mut monte_carlo_rng(self.seed, tumo_index, i),
+                        deadline,
+                    )
+                })
+                .collect()
+        });
 
-        // scores[x][r] := 解として選ばれた回数
-        let mut scores = [[0_i32; 4]; 7];
-        let mut ai_decisions = Vec::with_capacity(parallel);
+        // 全スレッドで展開したノード数
+        let nodes: usize = results
+            .iter()
+            .map(|(ai_decision, _)| ai_decision.diagnostics.nodes)
+            .sum();
 
-        for _ in 0..parallel {
-            if let Ok(ai_decision) = rx.recv() {
-                // 打てるものがあったらすぐにそれを打つ（全部のスレッドでそういう結果なはずなので）
-                // TODO: 判定方法が ad-hoc すぎるのでもうちょっといい方法を考える
-                if ai_decision.log_output.contains("fire") {
-                    return AIDecision::new(
-                        ai_decision.decisions.clone(),
-                        ai_decision.log_output.clone(),
-                        start.elapsed(),
-                    );
-                }
+        // samples[i] := (i 番目の試行で選ばれた初手, その評価値)
+        let mut samples = Vec::with_capacity(parallel);
+        let mut ai_decisions = Vec::with_capacity(parallel);
+        let mut search_trees = Vec::with_capacity(parallel);
+
+        for (ai_decision, search_tree) in results {
+            search_trees.push(search_tree);
+
+            // 打てるものがあったらすぐにそれを打つ（全部のスレッドでそういう結果なはずなので）
+            if ai_decision.is_fire() {
+                self.store_search_trees(tumo_index, &ai_decision.decisions[0], search_trees);
+                return AIDecision {
+                    elapsed: start.elapsed(),
+                    diagnostics: AIDiagnostics {
+                        nodes,
+                        ..ai_decision.diagnostics
+                    },
+                    ..ai_decision
+                };
+            }
 
-                let first_decision = &ai_decision.decisions[0];
-                let x = first_decision.axis_x();
-                let r = first_decision.rot();
-                scores[x][r] += 1;
-                ai_decisions.push(ai_decision);
-            } else {
-                break;
+            // 評価値が無い（自殺するしかなかった）試行は、集計に入れない
+            if let Some(eval) = ai_decision.diagnostics.eval {
+                samples.push((ai_decision.decisions[0].clone(), eval as f64));
             }
+            ai_decisions.push(ai_decision);
         }
 
-        let best_decision = Decision::all_valid_decisions()
-            .iter()
-            .max_by(|d1, d2| scores[d1.axis_x()][d1.rot()].cmp(&scores[d2.axis_x()][d2.rot()]))
-            .unwrap();
-
-        if let Some(ai_decision) = ai_decisions
-            .iter()
-            .find(|&ai_decision| &ai_decision.decisions[0] == best_decision)
-        {
-            return AIDecision::new(
-                ai_decision.decisions.clone(),
-                ai_decision.log_output.clone(),
-                start.elapsed(),
-            );
+        // 試行が 1 つもなければ選べない
+        if let Some(best_decision) = self.aggregation.select(&samples) {
+            self.store_search_trees(tumo_index, &best_decision, search_trees);
+
+            if let Some(ai_decision) = ai_decisions
+                .into_iter()
+                .find(|ai_decision| ai_decision.decisions[0] == best_decision)
+            {
+                return AIDecision {
+                    elapsed: start.elapsed(),
+                    diagnostics: AIDiagnostics {
+                        nodes,
+                        ..ai_decision.diagnostics
+                    },
+                    ..ai_decision
+                };
+            }
         }
 
-        // 死ぬしかない状態でも "muri..." が入っているはずなので
-        unreachable!()
+        // どうしようもないので自殺
+        AIDecision::new(
+            vec![Decision::new(3, 0)],
+            format!("muri..."),
+            start.elapsed(),
+        )
+        .with_diagnostics(AIDiagnostics {
+            nodes,
+            ..AIDiagnostics::new(MoveKind::Suicide)
+        })
     }
 }
 
@@ -474,6 +697,8 @@ struct State {
     frame_control: usize,
     /// そのツモを置くまで or 連鎖が終わり、相手にお邪魔が降るまでのフレーム数
     frame_chain: usize,
+    /// この盤面から先で自分に降るおじゃまぷよ
+    ojama: OjamaStatus,
 }
 
 impl State {
@@ -485,6 +710,7 @@ impl State {
             frame_control: 0_usize,
             frame_chain: 0_usize,
             plan: None,
+            ojama: OjamaStatus::default(),
         }
     }
 
@@ -494,6 +720,8 @@ impl State {
         eval_score: i32,
         // それまでの操作に必要なフレーム数の総和
         frame_margin: usize,
+        // 親の State のおじゃまぷよ
+        ojama: &OjamaStatus,
     ) -> Self {
         State {
             field: plan.field().clone(),
@@ -502,6 +730,7 @@ impl State {
             frame_control: frame_margin + plan.frames_to_ignite() + plan.last_drop_frames(),
             frame_chain: frame_margin + plan.total_frames(),
             plan: Some(plan.clone()),
+            ojama: ojama_after(plan, ojama),
         }
     }
 
@@ -513,6 +742,8 @@ impl State {
         eval_score: i32,
         // 発火時のツモ以外の操作に必要なフレーム数の総和（本来の定義とは異なることに注意）
         frame_control: usize,
+        // 親の State のおじゃまぷよ
+        ojama: &OjamaStatus,
     ) -> Self {
         State {
             field: plan.field().clone(),
@@ -521,11 +752,12 @@ impl State {
             frame_control,
             frame_chain: frame_control + plan.total_frames(),
             plan: Some(plan.clone()),
+            ojama: ojama_after(plan, ojama),
         }
     }
 
     // NOTE: 初期化にしか使っていない
-    fn from_field(field: &CoreField) -> Self {
+    fn from_field(field: &CoreField, ojama: OjamaStatus) -> Self {
         State {
             field: field.clone(),
             decisions: vec![],
@@ -533,49 +765,220 @@ impl State {
             frame_control: 0_usize,
             frame_chain: 0_usize,
             plan: None,
+            ojama,
         }
     }
 
     fn first_decision(&self) -> Option<&Decision> {
         self.decisions.first()
     }
+
+    /// 1手目を打った後の盤面を根とした State にする
+    fn rerooted(&self, frame_margin: usize) -> Self {
+        State {
+            field: self.field.clone(),
+            decisions: self.decisions[1..].to_vec(),
+            eval_score: self.eval_score,
+            frame_control: self.frame_control.saturating_sub(frame_margin),
+            frame_chain: self.frame_chain.saturating_sub(frame_margin),
+            plan: self.plan.clone(),
+            ojama: self.ojama.clone(),
+        }
+    }
+}
+
+/// `plan` を打った後のおじゃまぷよの状態（予告おじゃまぷよが確定するまでのフレーム数は、`plan` の分だけ進める）
+fn ojama_after(plan: &Plan, ojama: &OjamaStatus) -> OjamaStatus {
+    OjamaStatus {
+        fallen_ojama: 0,
+        fixed_ojama: plan.fixed_ojama(),
+        pending_ojama: plan.pending_ojama(),
+        ojama_committing_frame_id: plan
+            .ojama_committing_frame_id()
+            .saturating_sub(plan.total_frames()),
+        has_zenkeshi: plan.has_zenkeshi(),
+        ojama_per_drop: ojama.ojama_per_drop,
+    }
+}
+
+/// ビームサーチの探索結果（次の思考で再利用する）
+#[derive(Clone)]
+struct SearchTree {
+    /// 探索に使ったツモ（見えていない部分はモンテカルロで生成したもの）
+    seq: Vec<Kumipuyo>,
+    /// 各手数でビームに残った State
+    layers: Vec<Vec<State>>,
+}
+
+impl SearchTree {
+    /// 1手目が `decision` の State だけを残す
+    fn pruned(&self, decision: &Decision) -> Option<SearchTree> {
+        let layers: Vec<Vec<State>> = self
+            .layers
+            .iter()
+            .map(|layer| {
+                layer
+                    .iter()
+                    .filter(|state| state.first_decision() == Some(decision))
+                    .cloned()
+                    .collect::<Vec<State>>()
+            })
+            .take_while(|layer| !layer.is_empty())
+            .collect();
+        if layers.is_empty() {
+            return None;
+        }
+
+        Some(SearchTree {
+            seq: self.seq.clone(),
+            layers,
+        })
+    }
+
+    /// 実際に1手打った後の盤面 `field` と見えているツモ `seq` に合わせて、根を付け替える
+    fn reroot(&self, field: &CoreField, seq: &[Kumipuyo]) -> Option<SearchTree> {
+        // 実際に打たれた手は、盤面が一致する1手目の State から分かる
+        // （おじゃまが降っていたりして一致するものがなければ再利用しない）
+        let key = field_key(field);
+        let root = self
+            .layers
+            .first()?
+            .iter()
+            .find(|state| field_key(&state.field) == key)?;
+        let decision = root.first_decision()?;
+
+        // 見えているツモの後ろには、前回の探索で使ったツモを引き継ぐ
+        let mut next_seq = seq.to_vec();
+        if self.seq.len() > seq.len() + 1 {
+            next_seq.extend_from_slice(&self.seq[seq.len() + 1..]);
+        }
+
+        // 前回の探索で使ったツモと食い違わない手数分だけ再利用する
+        let consistent_tumos = self
+            .seq
+            .get(1..)?
+            .iter()
+            .zip(next_seq.iter())
+            .take_while(|(prev, next)| prev == next)
+            .count();
+        let layers = self
+            .layers
+            .iter()
+            .skip(1)
+            .take(consistent_tumos)
+            .map(|layer| {
+                layer
+                    .iter()
+                    .filter(|state| state.first_decision() == Some(decision))
+                    .map(|state| state.rerooted(root.frame_control))
+                    .collect::<Vec<State>>()
+            })
+            .take_while(|layer| !layer.is_empty())
+            .collect();
+
+        Some(SearchTree {
+            seq: next_seq,
+            layers,
+        })
+    }
+}
+
+/// 手順が違っても同じ盤面になった State を1つにまとめる（置換表）
+struct TranspositionTable {
+    /// (盤面, 手数) -> その手数の `next_states` 内での位置
+    table: HashMap<(FieldKey, usize), usize>,
+}
+
+impl TranspositionTable {
+    fn new() -> Self {
+        TranspositionTable {
+            table: HashMap::new(),
+        }
+    }
+
+    /// 同一局面が既にあれば、評価値が高い方を残す
+    fn push(&mut self, states: &mut Vec<State>, state: State, depth: usize) {
+        let key = (field_key(&state.field), depth);
+        match self.table.get(&key) {
+            Some(&index) => {
+                if state.eval_score > states[index].eval_score {
+                    states[index] = state;
+                }
+            }
+            None => {
+                self.table.insert(key, states.len());
+                states.push(state);
+            }
+        }
+    }
 }
 
 fn generate_next_states(
     cur_state: &State,
     next_states: &mut Vec<State>,
     fired: &mut Vec<State>,
+    transposition_table: &mut TranspositionTable,
+    depth: usize,
     kumipuyo: &Kumipuyo,
     append_fired: bool,
     evaluator: &Evaluator,
-) {
+    feature_cache: &mut FeatureCache,
+    deadline: Option<Instant>,
+) -> bool {
     let decisions = &cur_state.decisions;
     let seq = vec![kumipuyo.clone()];
+    // 子の盤面は親から 1 手置いただけなので、変わった列の周りだけ計算し直す
+    feature_cache.set_parent(&cur_state.field);
+
+    // 締め切りを過ぎて、全部の手を展開しきれなかったか（1手目は締め切りによらず全部展開する）
+    let mut expired = false;
+
+    // 降ってくるおじゃまぷよは、置いた後の盤面に落としてから評価する
+    Plan::iterate_available_plans_with_ojama(
+        &cur_state.field,
+        &seq,
+        1,
+        &cur_state.ojama,
+        &mut |plan: &Plan| {
+            // 2手目以降は、締め切りを過ぎたら残りの手は評価しない
+            if expired || (depth > 0 && deadline.is_some_and(|deadline| Instant::now() >= deadline))
+            {
+                expired = true;
+                return;
+            }
 
-    Plan::iterate_available_plans(&cur_state.field, &seq, 1, &mut |plan: &Plan| {
-        // TODO: どうにかできそう
-        let ds = {
-            let mut ds = decisions.clone();
-            ds.append(&mut vec![plan.first_decision().clone()]);
-            ds
-        };
+            // TODO: どうにかできそう
+            let ds = {
+                let mut ds = decisions.clone();
+                ds.append(&mut vec![plan.first_decision().clone()]);
+                ds
+            };
 
-        if append_fired && plan.chain() > 0 {
-            fired.push(State::from_plan_for_fire(
-                plan,
-                ds.clone(),
-                plan.score() as i32,
-                cur_state.frame_control,
-            ))
-        }
+            if append_fired && plan.chain() > 0 {
+                fired.push(State::from_plan_for_fire(
+                    plan,
+                    ds.clone(),
+                    plan.score() as i32,
+                    cur_state.frame_control,
+                    &cur_state.ojama,
+                ))
+            }
+
+            transposition_table.push(
+                next_states,
+                State::from_plan(
+                    plan,
+                    ds.clone(),
+                    evaluator.evaluate_cached(plan, feature_cache),
+                    cur_state.frame_control,
+                    &cur_state.ojama,
+                ),
+                depth,
+            );
+        },
+    );
 
-        next_states.push(State::from_plan(
-            plan,
-            ds.clone(),
-            evaluator.evaluate(plan),
-            cur_state.frame_control,
-        ));
-    });
+    !expired
 }
 
 fn think_single_thread<F>(
@@ -585,34 +988,56 @@ fn think_single_thread<F>(
     player_state_2p: &Option<PlayerState>,
     fire_condition: F,
     evaluator: &Evaluator,
-) -> AIDecision
+    seed: Option<SearchTree>,
+    rng: &mut StdRng,
+    deadline: Option<Instant>,
+) -> (AIDecision, SearchTree)
 where
     F: Fn(&State, &Option<PlayerState>) -> bool,
 {
     let start = Instant::now();
 
     let cf = &player_state_1p.field;
-    let seq = &player_state_1p.seq;
+    let visible_tumos = player_state_1p.seq.len();
+
+    // 前回の探索結果を再利用する場合は、そのときのツモを引き継ぐ
+    let seq = match &seed {
+        Some(seed) => &seed.seq,
+        None => &player_state_1p.seq,
+    };
 
     // ツモを伸ばす（モンテカルロ）
-    let visible_tumos = seq.len();
     let seq: Vec<Kumipuyo> = seq
         .iter()
         .cloned()
-        .chain(generate_random_puyocolor_sequence(
-            if depth > visible_tumos {
-                depth - visible_tumos
-            } else {
-                0
-            },
+        .chain(generate_random_tumos(
+            depth.saturating_sub(seq.len()),
+            player_state_1p.rules.num_colors,
+            rng,
         ))
         .collect();
+    // 各手数のビーム（最後のものが次に展開するビーム）
+    let mut layers: Vec<Vec<State>> = Vec::with_capacity(depth);
 
-    let mut state_v: Vec<State> = vec![State::from_field(cf)];
+    let root = vec![State::from_field(
+        cf,
+        player_state_1p.ojama_status(player_state_2p.as_ref()),
+    )];
     let mut fired_v: Vec<State> =
         Vec::with_capacity(width * Decision::all_valid_decisions().len() * depth);
+    let mut transposition_table = TranspositionTable::new();
+    let mut feature_cache = FeatureCache::new();
+    // 展開したノード数
+    let mut nodes = 0;
 
     for depth in 0..depth {
+        // 締め切りを過ぎたら、そこまでの結果を返す（最低でも1手は読む）
+        if depth > 0 && deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            break;
+        }
+
+        let state_v = layers.last().unwrap_or(&root);
+
         // ビーム内の初手がすべて同じなら終わり
         if depth > 0
             && state_v
@@ -625,19 +1050,38 @@ where
         // 次の状態を列挙
         let mut next_state_v: Vec<State> =
             Vec::with_capacity(width * Decision::all_valid_decisions().len());
-        for cur_state in &state_v {
-            generate_next_states(
+        let mut expanded = true;
+        for cur_state in state_v {
+            expanded = generate_next_states(
                 &cur_state,
                 &mut next_state_v,
                 &mut fired_v,
+                &mut transposition_table,
+                depth,
                 &seq[depth],
                 depth < visible_tumos,
                 evaluator,
+                &mut feature_cache,
+                deadline,
             );
+            if !expanded {
+                break;
+            }
+        }
+        // 途中で締め切りを過ぎたら、展開しきれなかった層は捨てる（1手目は必ず展開しきる）
+        if !expanded {
+            break;
+        }
+        // 前回の探索でこの手数まで読んでいた State も候補に入れる
+        if let Some(layer) = seed.as_ref().and_then(|seed| seed.layers.get(depth)) {
+            for state in layer {
+                transposition_table.push(&mut next_state_v, state.clone(), depth);
+            }
         }
         if next_state_v.is_empty() {
             break;
         }
+        nodes += next_state_v.len();
 
         // 良い方からビーム幅分だけ残す
         next_state_v
@@ -645,8 +1089,22 @@ where
         if next_state_v.len() > width {
             next_state_v.resize(width, State::empty());
         }
-        state_v = next_state_v;
+        layers.push(next_state_v);
     }
+    let depth_reached = layers.len();
+    let nodes = nodes + fired_v.len();
+    let diagnostics = |kind: MoveKind| AIDiagnostics {
+        depth: depth_reached,
+        nodes,
+        ..AIDiagnostics::new(kind)
+    };
+    // 最後のビームの先頭が、組む場合に選ぶ State
+    let best = layers
+        .last()
+        .and_then(|layer| layer.first())
+        .filter(|state| state.first_decision().is_some())
+        .map(|state| (state.decisions.clone(), state.eval_score));
+    let search_tree = SearchTree { seq, layers };
 
     // 発火すべきものがあれば、その中で一番点数が高いものを選んでいる
     // TODO: 本線なら点数が最大のものでよいが、副砲ならそうではないはず？
@@ -655,28 +1113,286 @@ where
         .filter(|f| fire_condition(f, player_state_2p))
         .max_by(|f1, f2| f1.eval_score.cmp(&f2.eval_score))
     {
-        return AIDecision::new(
-            fire.decisions.clone(),
-            format!(
-                "fire: {:6}\n{:4} F -> {:4} F",
-                fire.eval_score, fire.frame_control, fire.frame_chain,
-            ),
-            start.elapsed(),
+        return (
+            AIDecision::new(
+                fire.decisions.clone(),
+                format!(
+                    "fire: {:6}\n{:4} F -> {:4} F",
+                    fire.eval_score, fire.frame_control, fire.frame_chain,
+                ),
+                start.elapsed(),
+            )
+            .with_diagnostics(AIDiagnostics {
+                chain: fire.plan.as_ref().map_or(0, |plan| plan.chain()),
+                score: fire.plan.as_ref().map_or(0, |plan| plan.score()),
+                eval: Some(fire.eval_score),
+                ..diagnostics(MoveKind::Fire)
+            }),
+            search_tree,
         );
     }
 
-    if state_v[0].first_decision().is_some() {
-        return AIDecision::new(
-            state_v[0].decisions.clone(),
-            format!("eval: {:6}", state_v[0].eval_score),
-            start.elapsed(),
+    if let Some((decisions, eval_score)) = best {
+        return (
+            AIDecision::new(
+                decisions,
+                format!("eval: {:6}", eval_score),
+                start.elapsed(),
+            )
+            .with_diagnostics(AIDiagnostics {
+                eval: Some(eval_score),
+                ..diagnostics(MoveKind::Build)
+            }),
+            search_tree,
         );
     }
 
     // どうしようもないので自殺
-    return AIDecision::new(
-        vec![Decision::new(3, 0)],
-        format!("muri..."),
-        start.elapsed(),
+    return (
+        AIDecision::new(
+            vec![Decision::new(3, 0)],
+            format!("muri..."),
+            start.elapsed(),
+        )
+        .with_diagnostics(diagnostics(MoveKind::Suicide)),
+        search_tree,
     );
 }
+
+#[cfg(test)]
+mod tests {
+    use puyoai::color::PuyoColor;
+
+    use super::*;
+
+    #[test]
+    fn test_transposition_table() {
+        let field_1 = CoreField::from_str("RB    ");
+        let field_2 = CoreField::from_str("BR    ");
+        let state = |field: &CoreField, eval_score: i32| State {
+            field: field.clone(),
+            eval_score,
+            ..State::empty()
+        };
+
+        let mut states = vec![];
+        let mut transposition_table = TranspositionTable::new();
+        transposition_table.push(&mut states, state(&field_1, 10), 1);
+        transposition_table.push(&mut states, state(&field_2, 20), 1);
+        transposition_table.push(&mut states, state(&field_1, 30), 1);
+        transposition_table.push(&mut states, state(&field_2, 0), 1);
+
+        assert_eq!(states.len(), 2);
+        assert_eq!(states[0].eval_score, 30);
+        assert_eq!(states[1].eval_score, 20);
+
+        // 手数が違えば別の局面として扱う
+        transposition_table.push(&mut states, state(&field_1, 0), 2);
+        assert_eq!(states.len(), 3);
+    }
+
+    #[test]
+    fn test_search_tree_reroot() {
+        let seq = vec![
+            Kumipuyo::new(PuyoColor::RED, PuyoColor::BLUE),
+            Kumipuyo::new(PuyoColor::YELLOW, PuyoColor::GREEN),
+        ];
+        let player_state = PlayerState::initial_state(seq.clone(), None);
+        let (_, search_tree) = think_single_thread(
+            3,
+            20,
+            &player_state,
+            &None,
+            |_: &State, _: &Option<PlayerState>| false,
+            &Evaluator::default(),
+            None,
+            &mut monte_carlo_rng(Some(0), 0, 0),
+            None,
+        );
+        assert_eq!(search_tree.layers.len(), 3);
+
+        // 1手目を打った後の盤面で根を付け替える
+        let played = &search_tree.layers[0][0];
+        let next_seq = vec![
+            seq[1].clone(),
+            Kumipuyo::new(PuyoColor::RED, PuyoColor::RED),
+        ];
+        let rerooted = search_tree.reroot(&played.field, &next_seq).unwrap();
+        assert_eq!(rerooted.seq[..2], next_seq[..]);
+        assert!(!rerooted.layers.is_empty());
+        for (depth, layer) in rerooted.layers.iter().enumerate() {
+            assert!(layer.iter().all(|state| state.decisions.len() == depth + 1));
+        }
+
+        // 前回の探索に無い盤面なら再利用しない
+        assert!(search_tree
+            .reroot(&CoreField::from_str("OOOOOO"), &next_seq)
+            .is_none());
+    }
+
+    #[test]
+    fn test_store_search_trees() {
+        let seq = vec![
+            Kumipuyo::new(PuyoColor::RED, PuyoColor::BLUE),
+            Kumipuyo::new(PuyoColor::YELLOW, PuyoColor::GREEN),
+        ];
+        let player_state = PlayerState::initial_state(seq.clone(), None);
+        let (_, search_tree) = think_single_thread(
+            3,
+            20,
+            &player_state,
+            &None,
+            |_: &State, _: &Option<PlayerState>| false,
+            &Evaluator::default(),
+            None,
+            &mut monte_carlo_rng(Some(0), 0, 0),
+            None,
+        );
+        let played = search_tree.layers[0][0].clone();
+        let decision = played.first_decision().unwrap().clone();
+        let next_state = |field: &CoreField, tumo_index: usize| {
+            PlayerState::new(
+                0,
+                field.clone(),
+                seq[1..].to_vec(),
+                0,
+                0,
+                0,
+                0,
+                false,
+                0,
+                tumo_index,
+                None,
+            )
+        };
+
+        // 打つ手の子だけを覚える
+        let ai = BeamSearchAI::new();
+        ai.store_search_trees(0, &decision, vec![search_tree.clone()]);
+        {
+            let stored = ai.search_trees.lock().unwrap();
+            let search_trees = &stored[&(1, field_key(&played.field))];
+            for layer in &search_trees[0].layers {
+                assert!(layer
+                    .iter()
+                    .all(|state| state.first_decision() == Some(&decision)));
+            }
+        }
+
+        // 別の盤面（もう一方のプレイヤーなど）には使わない
+        let other = CoreField::from_str("OOOOOO");
+        assert!(ai.take_search_trees(&next_state(&other, 1)).is_empty());
+        assert_eq!(ai.take_search_trees(&next_state(&played.field, 1)).len(), 1);
+        assert!(ai
+            .take_search_trees(&next_state(&played.field, 1))
+            .is_empty());
+
+        // 新しい試合が始まったら忘れる
+        ai.store_search_trees(0, &decision, vec![search_tree]);
+        assert!(ai.take_search_trees(&player_state).is_empty());
+        assert!(ai.search_trees.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_think_single_thread_with_seed() {
+        let seq = vec![
+            Kumipuyo::new(PuyoColor::RED, PuyoColor::BLUE),
+            Kumipuyo::new(PuyoColor::YELLOW, PuyoColor::GREEN),
+        ];
+        let player_state = PlayerState::initial_state(seq, None);
+        let think = |seed: u64| {
+            think_single_thread(
+                6,
+                20,
+                &player_state,
+                &None,
+                |_: &State, _: &Option<PlayerState>| false,
+                &Evaluator::default(),
+                None,
+                &mut monte_carlo_rng(Some(seed), 0, 0),
+                None,
+            )
+        };
+
+        // 同じシードなら、読むツモも選ぶ手も同じ
+        let (ai_decision_1, search_tree_1) = think(7);
+        let (ai_decision_2, search_tree_2) = think(7);
+        assert_eq!(search_tree_1.seq, search_tree_2.seq);
+        assert_eq!(ai_decision_1.decisions, ai_decision_2.decisions);
+    }
+
+    #[test]
+    fn test_think_single_thread_diagnostics() {
+        let seq = vec![
+            Kumipuyo::new(PuyoColor::RED, PuyoColor::BLUE),
+            Kumipuyo::new(PuyoColor::YELLOW, PuyoColor::GREEN),
+        ];
+        let player_state = PlayerState::initial_state(seq, None);
+        let (ai_decision, search_tree) = think_single_thread(
+            3,
+            20,
+            &player_state,
+            &None,
+            |_: &State, _: &Option<PlayerState>| false,
+            &Evaluator::default(),
+            None,
+            &mut monte_carlo_rng(Some(0), 0, 0),
+            None,
+        );
+
+        // 発火しないので組む手になり、読んだ深さとノード数が入る
+        assert!(!ai_decision.is_fire());
+        assert_eq!(ai_decision.diagnostics.kind, MoveKind::Build);
+        assert_eq!(ai_decision.diagnostics.depth, search_tree.layers.len());
+        assert!(ai_decision.diagnostics.nodes >= search_tree.layers[0].len());
+        assert!(ai_decision.diagnostics.eval.is_some());
+    }
+
+    #[test]
+    fn test_think_single_thread_expired_deadline() {
+        let seq = vec![
+            Kumipuyo::new(PuyoColor::RED, PuyoColor::BLUE),
+            Kumipuyo::new(PuyoColor::YELLOW, PuyoColor::GREEN),
+        ];
+        let player_state = PlayerState::initial_state(seq.clone(), None);
+        let evaluator = Evaluator::default();
+        let (ai_decision, search_tree) = think_single_thread(
+            3,
+            20,
+            &player_state,
+            &None,
+            |_: &State, _: &Option<PlayerState>| false,
+            &evaluator,
+            None,
+            &mut monte_carlo_rng(Some(0), 0, 0),
+            Some(Instant::now()),
+        );
+
+        // 1手目の評価値
+        let mut evals: Vec<(Decision, i32)> = vec![];
+        Plan::iterate_available_plans(&player_state.field, &seq, 1, &mut |plan: &Plan| {
+            evals.push((plan.first_decision().clone(), evaluator.evaluate(plan)));
+        });
+        let best_eval = evals.iter().map(|&(_, eval)| eval).max().unwrap();
+
+        // 締め切りを過ぎていても 1手目は全部評価して、一番評価値の高い手を返す
+        assert_eq!(search_tree.layers.len(), 1);
+        assert_eq!(search_tree.layers[0].len(), evals.len());
+        assert_eq!(ai_decision.decisions.len(), 1);
+        assert_eq!(ai_decision.diagnostics.kind, MoveKind::Build);
+        assert_eq!(ai_decision.diagnostics.eval, Some(best_eval));
+        assert!(evals.contains(&(ai_decision.decisions[0].clone(), best_eval)));
+    }
+
+    #[test]
+    fn test_search_for_think_frame() {
+        // 思考フレーム数ごとの深さとビーム幅は、締め切りまで広げる順番の途中から始まる
+        for think_frame in [0, 2, 3, 8, 9, 60] {
+            let search = search_for_think_frame(think_frame);
+            assert!(ANYTIME_SCHEDULE.contains(&search));
+        }
+        assert_eq!(search_for_think_frame(0), (20, 20));
+        assert_eq!(search_for_think_frame(8), (30, 60));
+        assert_eq!(search_for_think_frame(9), (40, 140));
+    }
+}
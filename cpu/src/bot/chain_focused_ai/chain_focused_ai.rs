@@ -87,7 +87,9 @@ impl AI for ChainFocusedAI {
         let mut best_score = i32::MIN;
         let mut best_decisions = vec![];
 
-        Plan::iterate_available_plans(&cf, &seq, depth, &mut |plan: &Plan| {
+        // 降ってくるおじゃまぷよも考慮する
+        let ojama = player_state_1p.ojama_status(player_state_2p.as_ref());
+        Plan::iterate_available_plans_with_ojama(&cf, &seq, depth, &ojama, &mut |plan: &Plan| {
             let mut score = self.evaluator.evaluate(plan);
 
             // 連鎖が発生する手には追加ボーナス
@@ -108,6 +110,10 @@ impl AI for ChainFocusedAI {
             best_decisions = vec![Decision::new(3, 0)];
         }
 
+        let diagnostics = match &best_plan {
+            Some(plan) => AIDiagnostics::from_plan(plan, best_score),
+            None => AIDiagnostics::new(MoveKind::Suicide),
+        };
         let log_output = if let Some(plan) = best_plan {
             if plan.chain() > 0 {
                 format!("Chain: {} ({}pts) Score: {}", plan.chain(), plan.score(), best_score)
@@ -118,6 +124,6 @@ impl AI for ChainFocusedAI {
             "No valid move".to_string()
         };
 
-        AIDecision::new(best_decisions, log_output, start.elapsed())
+        AIDecision::new(best_decisions, log_output, start.elapsed()).with_diagnostics(diagnostics)
     }
 }
\ No newline at end of file
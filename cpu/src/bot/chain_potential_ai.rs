@@ -1,19 +1,70 @@
-use std::{sync::mpsc, thread, time::Instant};
+use std::time::Instant;
 
 use puyoai::{
     color::PuyoColor,
     decision::Decision,
     es_field::EsCoreField,
     field::CoreField,
-    kumipuyo::{kumipuyo_seq::generate_random_puyocolor_sequence, Kumipuyo},
+    kumipuyo::Kumipuyo,
     plan::Plan,
 };
+use rand::rngs::StdRng;
+use rayon::prelude::*;
+
+use crate::{
+    bot::{
+        monte_carlo::{generate_random_tumos, monte_carlo_rng, Aggregation},
+        worker_pool::worker_pool,
+        *,
+    },
+    evaluator::Evaluator,
+    opening_matcher::OpeningMatcher,
+};
 
-use crate::{bot::*, evaluator::Evaluator, opening_matcher::OpeningMatcher};
+/// 思考フレーム数が指定された場合に、締め切りまで順に試す (深さ, ビーム幅)
+/// - 先頭から `search_for_think_frame` の結果までは、思考フレーム数に応じて飛ばす
+const ANYTIME_SCHEDULE: &[(usize, usize)] = &[(20, 100), (30, 200), (40, 400)];
+
+/// 思考フレーム数に応じた、最初に試す (深さ, ビーム幅)
+fn search_for_think_frame(think_frame: usize) -> (usize, usize) {
+    if think_frame <= 2 {
+        (20, 100)
+    } else if think_frame <= 8 {
+        (30, 200)
+    } else {
+        (40, 400)
+    }
+}
 
 pub struct ChainPotentialAI {
     evaluator: Evaluator,
     opening_matcher: OpeningMatcher,
+    /// モンテカルロの乱数のシード（`None` なら毎回ランダム）
+    seed: Option<u64>,
+    /// モンテカルロの各試行の結果から初手を選ぶ方法
+    aggregation: Aggregation,
+    /// 思考フレーム数が指定されなかったときの (深さ, ビーム幅, モンテカルロの試行回数)
+    /// - 既定値は `search_for_think_frame(0)` と同じ
+    search: (usize, usize, usize),
+}
+
+impl ChainPotentialAI {
+    pub fn new_customize(evaluator: Evaluator) -> Self {
+        ChainPotentialAI {
+            evaluator,
+            ..ChainPotentialAI::new()
+        }
+    }
+
+    /// 思考フレーム数が指定されなかったときの (深さ, ビーム幅, モンテカルロの試行回数)
+    pub fn search(&self) -> (usize, usize, usize) {
+        self.search
+    }
+
+    pub fn with_search(mut self, depth: usize, width: usize, parallel: usize) -> Self {
+        self.search = (depth, width, parallel);
+        self
+    }
 }
 
 impl AI for ChainPotentialAI {
@@ -21,6 +72,9 @@ impl AI for ChainPotentialAI {
         ChainPotentialAI {
             evaluator: Evaluator::default(),
             opening_matcher: OpeningMatcher::new("opening_vis2.json").unwrap(),
+            seed: None,
+            aggregation: Aggregation::default(),
+            search: (20, 100, 10),
         }
     }
 
@@ -34,21 +88,76 @@ impl AI for ChainPotentialAI {
         player_state_2p: Option<PlayerState>,
         think_frame: Option<usize>,
     ) -> AIDecision {
-        let think_frame = think_frame.unwrap_or(0);
-        let (depth, width) = if think_frame <= 2 {
-            (20, 100)
-        } else if think_frame <= 8 {
-            (30, 200)
-        } else {
-            (40, 400)  // ビーム幅を400に拡大
-        };
+        let start = Instant::now();
+        match (think_frame, think_deadline(start, think_frame)) {
+            (Some(think_frame), Some(deadline)) => {
+                self.think_with_deadline(player_state_1p, player_state_2p, think_frame, deadline)
+            }
+            _ => {
+                let (depth, width, parallel) = self.search;
+                self.think_with_monte_carlo(
+                    player_state_1p,
+                    player_state_2p,
+                    depth,
+                    width,
+                    parallel,
+                    None,
+                )
+            }
+        }
+    }
+
+    fn set_seed(&mut self, seed: u64) {
+        self.seed = Some(seed);
+    }
 
-        // モンテカルロシミュレーション10回
-        self.think_with_monte_carlo(player_state_1p, player_state_2p, depth, width, 10)
+    fn set_aggregation(&mut self, aggregation: Aggregation) {
+        self.aggregation = aggregation;
     }
 }
 
 impl ChainPotentialAI {
+    /// 思考フレーム数に応じた深さとビーム幅から始めて、締め切りまで広げながら探索する
+    fn think_with_deadline(
+        &self,
+        player_state_1p: PlayerState,
+        player_state_2p: Option<PlayerState>,
+        think_frame: usize,
+        deadline: Instant,
+    ) -> AIDecision {
+        let start = Instant::now();
+        let first = search_for_think_frame(think_frame);
+        let schedule = ANYTIME_SCHEDULE
+            .iter()
+            .skip_while(|&&search| search != first);
+
+        let mut best_ai_decision: Option<AIDecision> = None;
+        for &(depth, width) in schedule {
+            let ai_decision = self.think_with_monte_carlo(
+                player_state_1p.clone(),
+                player_state_2p.clone(),
+                depth,
+                width,
+                10,
+                Some(deadline),
+            );
+
+            // 締め切りまでに探索しきれなかった結果は、他に結果がない場合にのみ使う
+            if Instant::now() >= deadline {
+                if best_ai_decision.is_none() {
+                    best_ai_decision = Some(ai_decision);
+                }
+                break;
+            }
+            best_ai_decision = Some(ai_decision);
+        }
+
+        AIDecision {
+            elapsed: start.elapsed(),
+            ..best_ai_decision.unwrap()
+        }
+    }
+
     fn think_with_monte_carlo(
         &self,
         player_state_1p: PlayerState,
@@ -56,6 +165,7 @@ impl ChainPotentialAI {
         depth: usize,
         width: usize,
         parallel: usize,
+        deadline: Option<Instant>,
     ) -> AIDecision {
         let start = Instant::now();
 
@@ -70,7 +180,8 @@ impl ChainPotentialAI {
                     &decision,
                     format!("OpeningMatcher"),
                     start.elapsed(),
-                );
+                )
+                .with_diagnostics(AIDiagnostics::new(MoveKind::Opening));
             }
         }
 
@@ -81,78 +192,76 @@ impl ChainPotentialAI {
             1
         };
 
-        // 各スレッドの結果をまとめる
-        let (tx, rx): (mpsc::Sender<AIDecision>, mpsc::Receiver<AIDecision>) = mpsc::channel();
-
-        for _ in 0..parallel {
-            let depth_c = depth;
-            let width_c = width;
-            let tx_c = tx.clone();
-            let player_state_1p_c = player_state_1p.clone();
-            let player_state_2p_c = player_state_2p.clone();
-            let evaluator_c = self.evaluator.clone();
-            let opening_matcher_c = self.opening_matcher.clone();
-
-            thread::spawn(move || {
-                let ai = ChainPotentialAI {
-                    evaluator: evaluator_c,
-                    opening_matcher: opening_matcher_c,
-                };
-                tx_c.send(ai.think_single_thread(
-                    &player_state_1p_c,
-                    &player_state_2p_c,
-                    depth_c,
-                    width_c,
-                ))
-                .ok();
-            });
-        }
+        // 各試行を共有のワーカープールで走らせる
+        // （終わった順ではなく試行の番号順に並ぶので、シードを固定したときに結果が再現する）
+        let results: Vec<AIDecision> = worker_pool().install(|| {
+            (0..parallel)
+                .into_par_iter()
+                .map(|i| {
+                    self.think_single_thread(
+                        &player_state_1p,
+                        &player_state_2p,
+                        depth,
+                        width,
+                        &mut monte_carlo_rng(self.seed, player_state_1p.tumo_index, i),
+                        deadline,
+                    )
+                })
+                .collect()
+        });
 
-        // scores[x][r] := 解として選ばれた回数
-        let mut scores = [[0_i32; 4]; 7];
+        // 全スレッドで展開したノード数
+        let nodes: usize = results
+            .iter()
+            .map(|ai_decision| ai_decision.diagnostics.nodes)
+            .sum();
+
+        // samples[i] := (i 番目の試行で選ばれた初手, その評価値)
+        let mut samples = Vec::with_capacity(parallel);
         let mut ai_decisions = Vec::with_capacity(parallel);
 
-        for _ in 0..parallel {
-            if let Ok(ai_decision) = rx.recv() {
-                // 発火判定があったらすぐにそれを打つ
-                if ai_decision.log_output.contains("FIRE") {
-                    return AIDecision::new(
-                        ai_decision.decisions.clone(),
-                        ai_decision.log_output.clone(),
-                        start.elapsed(),
-                    );
-                }
+        for ai_decision in results {
+            // 発火判定があったらすぐにそれを打つ
+            if ai_decision.is_fire() {
+                return AIDecision {
+                    elapsed: start.elapsed(),
+                    diagnostics: AIDiagnostics {
+                        nodes,
+                        ..ai_decision.diagnostics
+                    },
+                    ..ai_decision
+                };
+            }
 
-                if !ai_decision.decisions.is_empty() {
-                    let first_decision = &ai_decision.decisions[0];
-                    let x = first_decision.axis_x();
-                    let r = first_decision.rot();
-                    scores[x][r] += 1;
-                    ai_decisions.push(ai_decision);
-                }
-            } else {
-                break;
+            // 評価値が無い（自殺するしかなかった）試行は、集計に入れない
+            if let (Some(decision), Some(eval)) =
+                (ai_decision.decisions.first(), ai_decision.diagnostics.eval)
+            {
+                samples.push((decision.clone(), eval as f64));
+                ai_decisions.push(ai_decision);
             }
         }
 
-        // 最も多く選ばれた手を選択
-        let best_decision = Decision::all_valid_decisions()
-            .iter()
-            .max_by(|d1, d2| scores[d1.axis_x()][d1.rot()].cmp(&scores[d2.axis_x()][d2.rot()]))
-            .unwrap();
+        // 各試行の結果をまとめて手を選択
+        let best_decision = self.aggregation.select(&samples);
 
         if let Some(ai_decision) = ai_decisions
             .iter()
-            .find(|&ai_decision| &ai_decision.decisions[0] == best_decision)
+            .find(|&ai_decision| Some(&ai_decision.decisions[0]) == best_decision.as_ref())
         {
+            let votes = samples
+                .iter()
+                .filter(|(decision, _)| decision == &ai_decision.decisions[0])
+                .count();
             return AIDecision::new(
                 ai_decision.decisions.clone(),
-                format!("{} (votes: {}/{})",
-                    ai_decision.log_output,
-                    scores[best_decision.axis_x()][best_decision.rot()],
-                    parallel),
+                format!("{} (votes: {}/{})", ai_decision.log_output, votes, parallel),
                 start.elapsed(),
-            );
+            )
+            .with_diagnostics(AIDiagnostics {
+                nodes,
+                ..ai_decision.diagnostics.clone()
+            });
         }
 
         // どうしようもないので自殺
@@ -161,6 +270,10 @@ impl ChainPotentialAI {
             format!("muri..."),
             start.elapsed(),
         )
+        .with_diagnostics(AIDiagnostics {
+            nodes,
+            ..AIDiagnostics::new(MoveKind::Suicide)
+        })
     }
 
     fn think_single_thread(
@@ -169,6 +282,8 @@ impl ChainPotentialAI {
         _player_state_2p: &Option<PlayerState>,
         depth: usize,
         width: usize,
+        rng: &mut StdRng,
+        deadline: Option<Instant>,
     ) -> AIDecision {
         let start = Instant::now();
 
@@ -180,19 +295,29 @@ impl ChainPotentialAI {
         let seq: Vec<Kumipuyo> = seq
             .iter()
             .cloned()
-            .chain(generate_random_puyocolor_sequence(
+            .chain(generate_random_tumos(
                 if depth > visible_tumos {
                     depth - visible_tumos
                 } else {
                     0
                 },
+                player_state_1p.rules.num_colors,
+                rng,
             ))
             .collect();
 
         let mut state_v: Vec<State> = vec![State::from_field(cf)];
         let mut fired_states: Vec<State> = Vec::new();
+        // 探索した深さと、展開したノード数
+        let mut depth_reached = 0;
+        let mut nodes = 0;
 
         for cur_depth in 0..depth.min(seq.len()) {
+            // 締め切りを過ぎたら、そこまでの結果を返す（最低でも1手は読む）
+            if cur_depth > 0 && deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                break;
+            }
+
             // ビーム内の初手がすべて同じなら終わり
             if cur_depth > 0
                 && state_v
@@ -204,15 +329,27 @@ impl ChainPotentialAI {
 
             // 次の状態を列挙
             let mut next_state_v: Vec<State> = Vec::with_capacity(width * 22);
+            let mut expanded = true;
             for cur_state in &state_v {
-                self.generate_next_states(
+                expanded = self.generate_next_states(
                     &cur_state,
                     &mut next_state_v,
                     &mut fired_states,
                     &seq[cur_depth],
                     cur_depth < visible_tumos,
+                    // 1手目は締め切りによらず全部展開して、評価値で選べるようにする
+                    deadline.filter(|_| cur_depth > 0),
                 );
+                if !expanded {
+                    break;
+                }
+            }
+            // 途中で締め切りを過ぎたら、展開しきれなかった層は捨てる
+            if !expanded {
+                break;
             }
+            depth_reached = cur_depth + 1;
+            nodes += next_state_v.len();
 
             // 8万点以上の発火可能な手があれば即座に選択
             if let Some(fire_state) = fired_states.iter()
@@ -223,7 +360,15 @@ impl ChainPotentialAI {
                     fire_state.decisions.clone(),
                     format!("FIRE: {} points, {} chain!", fire_state.chain_score, fire_state.chain_count),
                     start.elapsed(),
-                );
+                )
+                .with_diagnostics(AIDiagnostics {
+                    kind: MoveKind::Fire,
+                    chain: fire_state.chain_count,
+                    score: fire_state.chain_score,
+                    eval: Some(fire_state.eval_score),
+                    depth: depth_reached,
+                    nodes,
+                });
             }
 
             if next_state_v.is_empty() {
@@ -238,12 +383,24 @@ impl ChainPotentialAI {
             state_v = next_state_v;
         }
 
-        if state_v[0].first_decision().is_some() {
+        if let Some(best) = state_v
+            .first()
+            .filter(|state| state.first_decision().is_some())
+        {
             return AIDecision::new(
-                state_v[0].decisions.clone(),
-                format!("eval: {}, potential: {}", state_v[0].eval_score, state_v[0].chain_potential),
+                best.decisions.clone(),
+                format!(
+                    "eval: {}, potential: {}",
+                    best.eval_score, best.chain_potential
+                ),
                 start.elapsed(),
-            );
+            )
+            .with_diagnostics(AIDiagnostics {
+                eval: Some(best.eval_score),
+                depth: depth_reached,
+                nodes,
+                ..AIDiagnostics::new(MoveKind::Build)
+            });
         }
 
         // どうしようもないので自殺
@@ -252,6 +409,11 @@ impl ChainPotentialAI {
             format!("muri..."),
             start.elapsed(),
         )
+        .with_diagnostics(AIDiagnostics {
+            depth: depth_reached,
+            nodes,
+            ..AIDiagnostics::new(MoveKind::Suicide)
+        })
     }
 
     fn generate_next_states(
@@ -261,10 +423,19 @@ impl ChainPotentialAI {
         fired_states: &mut Vec<State>,
         kumipuyo: &Kumipuyo,
         track_fired: bool,
-    ) {
+        deadline: Option<Instant>,
+    ) -> bool {
         let seq = vec![kumipuyo.clone()];
+        // 締め切りを過ぎて、全部の手を展開しきれなかったか
+        let mut expired = false;
 
         Plan::iterate_available_plans(&cur_state.field, &seq, 1, &mut |plan: &Plan| {
+            // 締め切りを過ぎたら、残りの手は評価しない
+            if expired || deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                expired = true;
+                return;
+            }
+
             let mut decisions = cur_state.decisions.clone();
             decisions.push(plan.first_decision().clone());
 
@@ -298,6 +469,8 @@ impl ChainPotentialAI {
                 chain_count: 0,
             });
         });
+
+        !expired
     }
 
     fn calculate_chain_potential(&self, field: &CoreField) -> i32 {
@@ -144,7 +144,9 @@ impl AI for HybridAI {
         let mut best_score = i32::MIN;
         let mut best_decisions = vec![];
 
-        Plan::iterate_available_plans(&cf, &seq, depth, &mut |plan: &Plan| {
+        // 降ってくるおじゃまぷよも考慮する
+        let ojama = player_state_1p.ojama_status(player_state_2p.as_ref());
+        Plan::iterate_available_plans_with_ojama(&cf, &seq, depth, &ojama, &mut |plan: &Plan| {
             let mut score = evaluator.evaluate(plan);
 
             // フェーズ固有の追加評価
@@ -202,6 +204,10 @@ impl AI for HybridAI {
             best_decisions = vec![Decision::new(3, 0)];
         }
 
+        let diagnostics = match &best_plan {
+            Some(plan) => AIDiagnostics::from_plan(plan, best_score),
+            None => AIDiagnostics::new(MoveKind::Suicide),
+        };
         let log_output = if let Some(plan) = best_plan {
             let phase_str = match phase {
                 Phase::Opening => "Open",
@@ -217,6 +223,6 @@ impl AI for HybridAI {
             "No valid move".to_string()
         };
 
-        AIDecision::new(best_decisions, log_output, start.elapsed())
+        AIDecision::new(best_decisions, log_output, start.elapsed()).with_diagnostics(diagnostics)
     }
 }
\ No newline at end of file
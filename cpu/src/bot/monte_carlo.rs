@@ -0,0 +1,206 @@
+use puyoai::{color::PuyoColor, decision::Decision, kumipuyo::Kumipuyo};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+const NORMAL_COLORS: [PuyoColor; 4] = [
+    PuyoColor::RED,
+    PuyoColor::BLUE,
+    PuyoColor::YELLOW,
+    PuyoColor::GREEN,
+];
+
+/// モンテカルロの各試行でツモを伸ばすための乱数生成器を作る
+///
+/// シードが指定されていれば (シード, ツモ番号, 試行番号) だけで乱数列が決まるので、
+/// 同じ局面を同じシードで思考させれば同じ未来が読まれる（締め切りがある場合を除く）
+pub fn monte_carlo_rng(seed: Option<u64>, tumo_index: usize, trial: usize) -> StdRng {
+    match seed {
+        Some(seed) => StdRng::seed_from_u64(seed ^ ((tumo_index as u64) << 32) ^ trial as u64),
+        None => StdRng::from_entropy(),
+    }
+}
+
+/// `generate_random_puyocolor_sequence` の乱数生成器と色数（`Rules::num_colors`）を指定できる版
+///
+/// シミュレータの配ぷよと同じく、`num_colors` 色のときは赤・青・黄・緑の先頭から使う。
+pub fn generate_random_tumos<R: Rng>(size: usize, num_colors: usize, rng: &mut R) -> Vec<Kumipuyo> {
+    let colors = &NORMAL_COLORS[..num_colors.clamp(1, NORMAL_COLORS.len())];
+    (0..size)
+        .map(|_| {
+            Kumipuyo::new(
+                colors[rng.gen_range(0..colors.len())],
+                colors[rng.gen_range(0..colors.len())],
+            )
+        })
+        .collect()
+}
+
+/// UCB のボーナスの係数
+const UCB_C: f64 = std::f64::consts::SQRT_2;
+
+/// モンテカルロの各試行の結果から、初手を選ぶ方法
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Aggregation {
+    /// 選ばれた回数が最も多い手
+    #[default]
+    Votes,
+    /// 評価値の総和が最も大きい手
+    Sum,
+    /// 評価値の平均が最も大きい手
+    Mean,
+    /// 評価値の中央値が最も大きい手（外れ値に強い）
+    Median,
+    /// 評価値の平均に、選ばれた回数が少ないほど大きいボーナスを足したもの（UCB1）
+    Ucb,
+}
+
+impl Aggregation {
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "votes" | "vote" => Some(Aggregation::Votes),
+            "sum" => Some(Aggregation::Sum),
+            "mean" => Some(Aggregation::Mean),
+            "median" => Some(Aggregation::Median),
+            "ucb" => Some(Aggregation::Ucb),
+            _ => None,
+        }
+    }
+
+    /// 各試行の (初手, 評価値) から初手を選ぶ（同点なら `Decision::all_valid_decisions` で前の手）
+    ///
+    /// 試行が 1 つもなければ `None` を返す。
+    pub fn select(&self, samples: &[(Decision, f64)]) -> Option<Decision> {
+        if samples.is_empty() {
+            return None;
+        }
+
+        let total = samples.len() as f64;
+        // UCB のボーナスを評価値のスケールに合わせるため、全試行の評価値の標準偏差を掛ける
+        let spread = {
+            let mean = samples.iter().map(|(_, v)| v).sum::<f64>() / total;
+            (samples.iter().map(|(_, v)| (v - mean).powi(2)).sum::<f64>() / total).sqrt()
+        };
+
+        Decision::all_valid_decisions()
+            .iter()
+            .filter_map(|decision| {
+                let mut values: Vec<f64> = samples
+                    .iter()
+                    .filter(|(d, _)| d == decision)
+                    .map(|&(_, v)| v)
+                    .collect();
+                if values.is_empty() {
+                    return None;
+                }
+
+                let n = values.len() as f64;
+                let sum = values.iter().sum::<f64>();
+                let score = match self {
+                    Aggregation::Votes => n,
+                    Aggregation::Sum => sum,
+                    Aggregation::Mean => sum / n,
+                    Aggregation::Median => {
+                        values.sort_by(|a, b| a.partial_cmp(b).unwrap());
+                        let mid = values.len() / 2;
+                        if values.len() % 2 == 0 {
+                            (values[mid - 1] + values[mid]) / 2.0
+                        } else {
+                            values[mid]
+                        }
+                    }
+                    Aggregation::Ucb => sum / n + UCB_C * spread * (total.ln() / n).sqrt(),
+                };
+                Some((decision, score))
+            })
+            // `max_by` だと同点のときに後ろの手になるので、真に大きいときだけ入れ替える
+            .reduce(|best, candidate| {
+                if candidate.1 > best.1 {
+                    candidate
+                } else {
+                    best
+                }
+            })
+            .map(|(decision, _)| decision.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seeded_tumos_are_reproducible() {
+        let tumos = generate_random_tumos(10, 4, &mut monte_carlo_rng(Some(42), 3, 1));
+        assert_eq!(tumos.len(), 10);
+        assert_eq!(
+            tumos,
+            generate_random_tumos(10, 4, &mut monte_carlo_rng(Some(42), 3, 1))
+        );
+        assert_ne!(
+            tumos,
+            generate_random_tumos(10, 4, &mut monte_carlo_rng(Some(42), 3, 2))
+        );
+        assert_ne!(
+            tumos,
+            generate_random_tumos(10, 4, &mut monte_carlo_rng(Some(42), 4, 1))
+        );
+    }
+
+    #[test]
+    fn test_random_tumos_num_colors() {
+        // 3 色なら緑は出てこない
+        let tumos = generate_random_tumos(100, 3, &mut monte_carlo_rng(Some(0), 0, 0));
+        assert!(tumos
+            .iter()
+            .all(|tumo| tumo.axis() != PuyoColor::GREEN && tumo.child() != PuyoColor::GREEN));
+        assert!(tumos.iter().any(|tumo| tumo.axis() == PuyoColor::YELLOW));
+    }
+
+    #[test]
+    fn test_aggregation_select() {
+        let samples = vec![
+            (Decision::new(1, 0), 100.0),
+            (Decision::new(1, 0), 100.0),
+            (Decision::new(1, 0), 100.0),
+            (Decision::new(2, 0), 1000.0),
+            (Decision::new(2, 0), 0.0),
+            (Decision::new(3, 0), 600.0),
+        ];
+        assert_eq!(
+            Aggregation::Votes.select(&samples),
+            Some(Decision::new(1, 0))
+        );
+        assert_eq!(Aggregation::Sum.select(&samples), Some(Decision::new(2, 0)));
+        assert_eq!(
+            Aggregation::Mean.select(&samples),
+            Some(Decision::new(3, 0))
+        );
+        assert_eq!(
+            Aggregation::Median.select(&samples),
+            Some(Decision::new(3, 0))
+        );
+        assert_eq!(Aggregation::Ucb.select(&samples), Some(Decision::new(3, 0)));
+        // 試行がなければ選べない
+        for aggregation in [Aggregation::Votes, Aggregation::Median, Aggregation::Ucb] {
+            assert_eq!(aggregation.select(&[]), None);
+        }
+    }
+
+    #[test]
+    fn test_aggregation_select_tie() {
+        // 同点なら `Decision::all_valid_decisions` で前の手を選ぶ（試行の順番にはよらない）
+        let samples = vec![
+            (Decision::new(4, 0), 100.0),
+            (Decision::new(2, 0), 100.0),
+            (Decision::new(3, 0), 100.0),
+        ];
+        for aggregation in [
+            Aggregation::Votes,
+            Aggregation::Sum,
+            Aggregation::Mean,
+            Aggregation::Median,
+            Aggregation::Ucb,
+        ] {
+            assert_eq!(aggregation.select(&samples), Some(Decision::new(2, 0)));
+        }
+    }
+}
@@ -0,0 +1,338 @@
+//! 名前から AI を作るための一覧
+//!
+//! どのバイナリも `--ai Name[:key=value]*` の形で AI を指定できるようにする。
+//! 例: `BeamSearchAI:evaluator=evaluator.json:depth=30`、`TakaptAI:width=200:seed=1`、
+//! `BeamSearchAI:shapes=shapes.toml`
+//!
+//! 新しい AI を追加したら `ENTRIES` に書き足せば、全部のバイナリから使えるようになる。
+
+use std::{fs::File, io::BufReader};
+
+use crate::{
+    bot::{monte_carlo::Aggregation, *},
+    evaluator::{Evaluator, ShapeSet, ShapeTemplate},
+};
+
+/// `--ai` で指定できるパラメータ
+#[derive(Clone, Debug, Default)]
+pub struct AIParams {
+    /// 評価関数（`evaluator=path.json`）
+    pub evaluator: Option<Evaluator>,
+    /// 評価関数に追加する形のテンプレート（`shapes=path.toml`、JSON でもいい）
+    pub shapes: Option<Vec<ShapeTemplate>>,
+    /// 探索の深さ（`depth=N`）
+    pub depth: Option<usize>,
+    /// ビーム幅（`width=N`）
+    pub width: Option<usize>,
+    /// モンテカルロの試行回数（`parallel=N`）
+    pub parallel: Option<usize>,
+    /// モンテカルロの乱数のシード（`seed=N`）
+    pub seed: Option<u64>,
+    /// モンテカルロの各試行の結果のまとめ方（`aggregation=votes` など）
+    pub aggregation: Option<Aggregation>,
+}
+
+impl AIParams {
+    /// `evaluator=` と `shapes=` から評価関数を作る（どちらも無ければ `None`）
+    pub fn evaluator_with_shapes(&self) -> Option<Evaluator> {
+        match (&self.evaluator, &self.shapes) {
+            (None, None) => None,
+            (evaluator, shapes) => Some(
+                evaluator
+                    .clone()
+                    .unwrap_or_default()
+                    .with_shapes(shapes.clone().unwrap_or_default()),
+            ),
+        }
+    }
+}
+
+/// 登録されている AI
+#[derive(Debug)]
+struct Entry {
+    /// `ai.name()` と同じ名前
+    name: &'static str,
+    /// 名前の代わりに使える短い名前（大文字小文字は区別しない）
+    aliases: &'static [&'static str],
+    /// 指定できるパラメータ
+    keys: &'static [&'static str],
+    create: fn(&AIParams) -> Box<dyn AI>,
+}
+
+const BEAM_SEARCH_KEYS: &[&str] = &[
+    "evaluator",
+    "shapes",
+    "depth",
+    "width",
+    "parallel",
+    "seed",
+    "aggregation",
+];
+
+const ENTRIES: &[Entry] = &[
+    Entry {
+        name: "BeamSearchAI",
+        aliases: &["beamsearch", "beam"],
+        keys: BEAM_SEARCH_KEYS,
+        create: create_beam_search_ai,
+    },
+    Entry {
+        name: "ChainFocusedAI",
+        aliases: &["chainfocused", "chain"],
+        keys: &["evaluator"],
+        create: create_chain_focused_ai,
+    },
+    Entry {
+        name: "ChainPotentialAI",
+        aliases: &["chainpotential", "potential"],
+        keys: BEAM_SEARCH_KEYS,
+        create: create_chain_potential_ai,
+    },
+    Entry {
+        name: "HybridAI",
+        aliases: &["hybrid"],
+        keys: &["evaluator"],
+        create: create_hybrid_ai,
+    },
+    Entry {
+        name: "RandomAI",
+        aliases: &["random"],
+        keys: &[],
+        create: create_random_ai,
+    },
+    Entry {
+        name: "StableAI",
+        aliases: &["stable"],
+        keys: &["evaluator"],
+        create: create_stable_ai,
+    },
+    Entry {
+        name: "TakaptAI",
+        aliases: &["takapt"],
+        keys: &["depth", "width", "parallel", "seed", "aggregation"],
+        create: create_takapt_ai,
+    },
+];
+
+fn create_beam_search_ai(params: &AIParams) -> Box<dyn AI> {
+    let ai = match params.evaluator_with_shapes() {
+        Some(evaluator) => BeamSearchAI::new_customize(evaluator),
+        None => BeamSearchAI::new(),
+    };
+    let (depth, width, parallel) = ai.search();
+    Box::new(ai.with_search(
+        params.depth.unwrap_or(depth),
+        params.width.unwrap_or(width),
+        params.parallel.unwrap_or(parallel),
+    ))
+}
+
+fn create_chain_focused_ai(params: &AIParams) -> Box<dyn AI> {
+    match &params.evaluator {
+        Some(evaluator) => Box::new(ChainFocusedAI::new_customize(evaluator.clone())),
+        None => Box::new(ChainFocusedAI::new()),
+    }
+}
+
+fn create_chain_potential_ai(params: &AIParams) -> Box<dyn AI> {
+    let ai = match params.evaluator_with_shapes() {
+        Some(evaluator) => ChainPotentialAI::new_customize(evaluator),
+        None => ChainPotentialAI::new(),
+    };
+    let (depth, width, parallel) = ai.search();
+    Box::new(ai.with_search(
+        params.depth.unwrap_or(depth),
+        params.width.unwrap_or(width),
+        params.parallel.unwrap_or(parallel),
+    ))
+}
+
+fn create_hybrid_ai(params: &AIParams) -> Box<dyn AI> {
+    match &params.evaluator {
+        Some(evaluator) => Box::new(HybridAI::new_customize(evaluator.clone())),
+        None => Box::new(HybridAI::new()),
+    }
+}
+
+fn create_random_ai(_params: &AIParams) -> Box<dyn AI> {
+    Box::new(RandomAI::new())
+}
+
+fn create_stable_ai(params: &AIParams) -> Box<dyn AI> {
+    match &params.evaluator {
+        Some(evaluator) => Box::new(StableAI::new_customize(evaluator.clone())),
+        None => Box::new(StableAI::new()),
+    }
+}
+
+fn create_takapt_ai(params: &AIParams) -> Box<dyn AI> {
+    let ai = TakaptAI::new();
+    let (depth, width, parallel) = ai.search();
+    Box::new(ai.with_search(
+        params.depth.unwrap_or(depth),
+        params.width.unwrap_or(width),
+        params.parallel.unwrap_or(parallel),
+    ))
+}
+
+/// 登録されている AI の名前の一覧
+pub fn ai_names() -> Vec<&'static str> {
+    ENTRIES.iter().map(|entry| entry.name).collect()
+}
+
+/// `Name[:key=value]*` の形で指定された AI
+#[derive(Clone, Debug)]
+pub struct AISpec {
+    /// 指定された文字列そのまま
+    spec: String,
+    entry: &'static Entry,
+    params: AIParams,
+}
+
+impl AISpec {
+    /// `Name[:key=value]*` を読む（評価関数の JSON もここで読み込む）
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let mut parts = spec.trim().split(':');
+        let name = parts.next().unwrap_or_default();
+        let entry = ENTRIES
+            .iter()
+            .find(|entry| {
+                entry.name.eq_ignore_ascii_case(name)
+                    || entry
+                        .aliases
+                        .iter()
+                        .any(|alias| alias.eq_ignore_ascii_case(name))
+            })
+            .ok_or_else(|| {
+                format!(
+                    "No AI found: {} (available: {})",
+                    name,
+                    ai_names().join(", ")
+                )
+            })?;
+
+        let mut params = AIParams::default();
+        for part in parts {
+            let (key, value) = part
+                .split_once('=')
+                .ok_or_else(|| format!("Parameter must be key=value: {}", part))?;
+            if !entry.keys.contains(&key) {
+                return Err(format!(
+                    "{} does not accept `{}` (available: {})",
+                    entry.name,
+                    key,
+                    entry.keys.join(", ")
+                ));
+            }
+            let invalid = |e: &dyn std::fmt::Display| format!("Invalid {}: {} ({})", key, value, e);
+            // 0 だとビームや試行が空になって手を選べない
+            let positive = || match value.parse::<usize>() {
+                Ok(0) => Err(invalid(&"must be positive")),
+                result => result.map_err(|e| invalid(&e)),
+            };
+            match key {
+                "evaluator" => {
+                    let file = File::open(value).map_err(|e| invalid(&e))?;
+                    params.evaluator = Some(
+                        serde_json::from_reader(BufReader::new(file)).map_err(|e| invalid(&e))?,
+                    );
+                }
+                "shapes" => {
+                    params.shapes =
+                        Some(ShapeSet::from_file(value).map_err(|e| invalid(&e))?.shapes);
+                }
+                "depth" => params.depth = Some(positive()?),
+                "width" => params.width = Some(positive()?),
+                "parallel" => params.parallel = Some(positive()?),
+                "seed" => params.seed = Some(value.parse().map_err(|e| invalid(&e))?),
+                "aggregation" => {
+                    params.aggregation = Some(
+                        Aggregation::from_str(value)
+                            .ok_or_else(|| format!("Invalid aggregation: {}", value))?,
+                    )
+                }
+                _ => unreachable!(),
+            }
+        }
+
+        Ok(AISpec {
+            spec: spec.trim().to_string(),
+            entry,
+            params,
+        })
+    }
+
+    /// AI の名前（`ai.name()` と同じ）
+    pub fn name(&self) -> &'static str {
+        self.entry.name
+    }
+
+    /// 指定された文字列（パラメータも含めて区別したいときの表示用）
+    pub fn label(&self) -> &str {
+        &self.spec
+    }
+
+    pub fn params(&self) -> &AIParams {
+        &self.params
+    }
+
+    /// 指定どおりの AI を作る（呼ぶたびに新しく作る）
+    pub fn create(&self) -> Box<dyn AI> {
+        let mut ai = (self.entry.create)(&self.params);
+        if let Some(seed) = self.params.seed {
+            ai.set_seed(seed);
+        }
+        if let Some(aggregation) = self.params.aggregation {
+            ai.set_aggregation(aggregation);
+        }
+        ai
+    }
+}
+
+/// `Name[:key=value]*` で指定された AI を作る
+pub fn create_ai(spec: &str) -> Result<Box<dyn AI>, String> {
+    Ok(AISpec::parse(spec)?.create())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let spec = AISpec::parse("takapt:width=200:depth=10:seed=3").unwrap();
+        assert_eq!(spec.name(), "TakaptAI");
+        assert_eq!(spec.label(), "takapt:width=200:depth=10:seed=3");
+        assert_eq!(spec.params().width, Some(200));
+        assert_eq!(spec.params().depth, Some(10));
+        assert_eq!(spec.params().seed, Some(3));
+        assert_eq!(spec.params().parallel, None);
+
+        // 短い名前でも、大文字小文字が違っても指定できる
+        assert_eq!(AISpec::parse("beam").unwrap().name(), "BeamSearchAI");
+        assert_eq!(
+            AISpec::parse("beamsearchai").unwrap().name(),
+            "BeamSearchAI"
+        );
+        assert_eq!(
+            AISpec::parse("RandomAI").unwrap().create().name(),
+            "RandomAI"
+        );
+    }
+
+    #[test]
+    fn test_parse_error() {
+        assert!(AISpec::parse("NoSuchAI").is_err());
+        // 受け付けないパラメータ
+        assert!(AISpec::parse("RandomAI:depth=3").is_err());
+        assert!(AISpec::parse("TakaptAI:evaluator=a.json").is_err());
+        // 値がおかしい
+        assert!(AISpec::parse("TakaptAI:width").is_err());
+        assert!(AISpec::parse("TakaptAI:width=wide").is_err());
+        assert!(AISpec::parse("BeamSearchAI:width=0").is_err());
+        assert!(AISpec::parse("BeamSearchAI:depth=0").is_err());
+        assert!(AISpec::parse("ChainPotentialAI:parallel=0").is_err());
+        assert!(AISpec::parse("BeamSearchAI:aggregation=best").is_err());
+        assert!(AISpec::parse("BeamSearchAI:shapes=no_such_file.toml").is_err());
+    }
+}
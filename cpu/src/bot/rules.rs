@@ -0,0 +1,214 @@
+use std::{fs::File, io::BufReader};
+
+use puyoai::es_frame;
+use serde::{Deserialize, Serialize};
+
+/// 一度に降るおじゃまぷよの上限の最大値（`es_drop_ojama` が一度に落とせるのは 30 個まで）
+pub const MAX_OJAMA_PER_DROP: usize = 30;
+
+/// 色数の最大値
+pub const MAX_NUM_COLORS: usize = 4;
+
+/// 対戦のルール（おじゃまぷよの量や色数など）
+///
+/// シミュレータに渡すと、その設定で対戦させる。AI からは `PlayerState` 経由で見える。
+/// JSON に書いておけば、書かれていない項目はぷよぷよ通のルールになる。
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Rules {
+    /// 一度に降るおじゃまぷよの上限（1 から `MAX_OJAMA_PER_DROP` まで）
+    pub ojama_per_drop: usize,
+    /// おじゃまぷよ 1 個あたりの点数（マージンタイムが始まるまで）
+    pub ojama_rate: usize,
+    /// 全消しボーナス（点数）
+    pub zenkeshi_bonus: usize,
+    /// マージンタイム（`None` ならなし）
+    pub margin_time: Option<MarginTime>,
+    /// ぷよの色数（1 から `MAX_NUM_COLORS` まで）
+    pub num_colors: usize,
+}
+
+impl Rules {
+    /// ぷよぷよ通のルール（マージンタイムなし）
+    pub fn tsu() -> Self {
+        Rules {
+            ojama_per_drop: 30,
+            ojama_rate: 70,
+            zenkeshi_bonus: 2100,
+            margin_time: None,
+            num_colors: 4,
+        }
+    }
+
+    /// ぷよぷよ通のルール（マージンタイムあり）
+    pub fn tsu_with_margin_time() -> Self {
+        Rules {
+            margin_time: Some(MarginTime::default()),
+            ..Rules::tsu()
+        }
+    }
+
+    /// JSON ファイルからルールを読む（シミュレータで使えないルールならエラー）
+    pub fn from_json_file(path: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let reader = BufReader::new(File::open(path)?);
+        let rules: Rules = serde_json::from_reader(reader)?;
+        rules.validate()?;
+        Ok(rules)
+    }
+
+    /// シミュレータで使えるルールか確かめる
+    pub fn validate(&self) -> Result<(), String> {
+        if !(1..=MAX_OJAMA_PER_DROP).contains(&self.ojama_per_drop) {
+            return Err(format!(
+                "ojama_per_drop must be between 1 and {}: {}",
+                MAX_OJAMA_PER_DROP, self.ojama_per_drop
+            ));
+        }
+        if self.ojama_rate == 0 {
+            return Err("ojama_rate must be positive".to_string());
+        }
+        if let Some(margin_time) = self.margin_time {
+            if margin_time.interval_frames == 0 {
+                return Err("margin_time.interval_frames must be positive".to_string());
+            }
+        }
+        if !(1..=MAX_NUM_COLORS).contains(&self.num_colors) {
+            return Err(format!(
+                "num_colors must be between 1 and {}: {}",
+                MAX_NUM_COLORS, self.num_colors
+            ));
+        }
+        Ok(())
+    }
+
+    /// `frame` 時点でのおじゃまぷよ 1 個あたりの点数
+    pub fn ojama_rate_at(&self, frame: usize) -> usize {
+        let steps = self
+            .margin_time
+            .map_or(0, |margin_time| margin_time.decay_steps(frame));
+
+        let mut rate = self.ojama_rate.max(1);
+        for _ in 0..steps {
+            if rate <= 1 {
+                break;
+            }
+            rate = (rate * 3 / 4).max(1);
+        }
+        rate
+    }
+}
+
+impl Default for Rules {
+    fn default() -> Self {
+        Rules::tsu()
+    }
+}
+
+/// マージンタイム（長引いた試合を終わらせるため、おじゃまぷよ 1 個あたりの点数を下げていく）
+///
+/// `start_frame` を過ぎると、`interval_frames` ごとにレートが 3/4 倍（最低 1）になる。
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MarginTime {
+    /// マージンタイムが始まるフレーム数
+    pub start_frame: usize,
+    /// レートが下がる間隔（フレーム数）
+    pub interval_frames: usize,
+}
+
+impl MarginTime {
+    /// `interval_frames` が 0 のものは `Rules::validate` で弾く
+    pub fn new(start_frame: usize, interval_frames: usize) -> Self {
+        MarginTime {
+            start_frame,
+            interval_frames,
+        }
+    }
+
+    /// `frame` 時点までに、レートが何回下がったか
+    fn decay_steps(&self, frame: usize) -> usize {
+        if frame < self.start_frame {
+            return 0;
+        }
+        (frame - self.start_frame) / self.interval_frames.max(1) + 1
+    }
+}
+
+/// ぷよぷよ通の対戦ルール（96 秒から 16 秒ごと）
+impl Default for MarginTime {
+    fn default() -> Self {
+        MarginTime::new(96 * es_frame::FPS, 16 * es_frame::FPS)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ojama_rate_at() {
+        // マージンタイムなしなら変わらない
+        assert_eq!(Rules::tsu().ojama_rate_at(100_000), 70);
+
+        let rules = Rules {
+            margin_time: Some(MarginTime::new(100, 10)),
+            ..Rules::tsu()
+        };
+        assert_eq!(rules.ojama_rate_at(0), 70);
+        assert_eq!(rules.ojama_rate_at(99), 70);
+        assert_eq!(rules.ojama_rate_at(100), 52);
+        assert_eq!(rules.ojama_rate_at(109), 52);
+        assert_eq!(rules.ojama_rate_at(110), 39);
+        // 十分に時間が経つと 1 で止まる
+        assert_eq!(rules.ojama_rate_at(100_000), 1);
+    }
+
+    #[test]
+    fn test_deserialize_partial() {
+        // 書かれていない項目はぷよぷよ通のルールになる
+        let rules: Rules = serde_json::from_str(r#"{"ojama_per_drop": 18}"#).unwrap();
+        assert_eq!(
+            rules,
+            Rules {
+                ojama_per_drop: 18,
+                ..Rules::tsu()
+            }
+        );
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(Rules::tsu().validate().is_ok());
+        assert!(Rules::tsu_with_margin_time().validate().is_ok());
+
+        // 0 で割ったり、乱数の範囲が空になったりするものは弾く
+        let invalid = [
+            Rules {
+                ojama_per_drop: 0,
+                ..Rules::tsu()
+            },
+            Rules {
+                ojama_per_drop: 31,
+                ..Rules::tsu()
+            },
+            Rules {
+                ojama_rate: 0,
+                ..Rules::tsu()
+            },
+            Rules {
+                margin_time: Some(MarginTime::new(100, 0)),
+                ..Rules::tsu()
+            },
+            Rules {
+                num_colors: 0,
+                ..Rules::tsu()
+            },
+            Rules {
+                num_colors: 5,
+                ..Rules::tsu()
+            },
+        ];
+        for rules in invalid {
+            assert!(rules.validate().is_err(), "{:?}", rules);
+        }
+    }
+}
@@ -1,14 +1,19 @@
-use std::time::Instant;
+use std::{collections::HashMap, time::Instant};
 
 use puyoai::{
     decision::Decision,
     field::CoreField,
-    kumipuyo::kumipuyo_seq::generate_random_puyocolor_sequence,
-    plan::Plan,
+    kumipuyo::Kumipuyo,
+    plan::{OjamaStatus, Plan},
 };
 
 use crate::{bot::*, evaluator::Evaluator};
 
+/// 先読みする手数（安定重視なので浅めで良い）
+const DEPTH: usize = 2;
+/// 死んでしまう手のペナルティ
+const DEAD_PENALTY: i32 = -100000;
+
 /// 安定重視AI - 盤面の形を整えることを重視し、安定した戦いを目指すAI
 pub struct StableAI {
     evaluator: Evaluator,
@@ -81,6 +86,126 @@ impl StableAI {
 
         score
     }
+
+    fn evaluate_plan(&self, plan: &Plan) -> i32 {
+        let mut score = self.evaluator.evaluate(plan);
+
+        // 安定性の追加評価
+        score += Self::evaluate_stability(plan.field());
+
+        // 死んでしまう手は大きくペナルティ
+        if plan.field().is_dead() {
+            score += DEAD_PENALTY;
+        }
+
+        // 13段目に置く手はペナルティ
+        for x in 1..=6 {
+            if !plan.field().is_empty(x, 13) {
+                score -= 5000;
+            }
+        }
+
+        score
+    }
+
+    /// 見えていないツモはルールの色数の全配色（4 色なら 10 通り）を試し、評価値の期待値が一番高い初手を選ぶ
+    fn think_expectimax(
+        &self,
+        player_state_1p: &PlayerState,
+        ojama: &OjamaStatus,
+        start: Instant,
+    ) -> AIDecision {
+        let visible_tumos = player_state_1p.seq.len();
+
+        // 初手 (x, r) -> 見えているツモだけで決まるプラン（途中で発火したもの）の (評価値, 連鎖数, 得点)
+        let mut fired: HashMap<(usize, usize), (i32, usize, usize)> = HashMap::new();
+        // 初手 (x, r) -> 見えていないツモの組み合わせごとの (ツモ, 確率, 最大の評価値)
+        let mut branches: HashMap<(usize, usize), Vec<(Vec<Kumipuyo>, f64, i32)>> = HashMap::new();
+
+        Plan::iterate_available_plans_with_unknown_tumos(
+            &player_state_1p.field,
+            &player_state_1p.seq,
+            DEPTH,
+            ojama,
+            player_state_1p.rules.num_colors,
+            &mut |plan: &Plan, tumos: &[Kumipuyo], probability: f64| {
+                let decision = plan.first_decision();
+                let key = (decision.axis_x(), decision.rot());
+                let score = self.evaluate_plan(plan);
+
+                if tumos.len() <= visible_tumos {
+                    let best = fired.entry(key).or_insert((i32::MIN, 0, 0));
+                    if score > best.0 {
+                        *best = (score, plan.chain(), plan.score());
+                    }
+                    return;
+                }
+
+                let hidden = &tumos[visible_tumos..];
+                let branches = branches.entry(key).or_default();
+                match branches
+                    .iter_mut()
+                    .find(|(tumos, _, _)| tumos.as_slice() == hidden)
+                {
+                    Some((_, _, best)) => *best = (*best).max(score),
+                    None => branches.push((hidden.to_vec(), probability, score)),
+                }
+            },
+        );
+
+        // 発火する手なら、その後のツモによらずその評価値以上になる
+        // 置ける場所がないツモの組み合わせは死んだものとみなす
+        let expected = |key: &(usize, usize)| -> f64 {
+            let fired_score = fired.get(key).map(|&(score, _, _)| score);
+            let Some(branches) = branches.get(key) else {
+                return fired_score.unwrap_or(DEAD_PENALTY) as f64;
+            };
+            let covered: f64 = branches.iter().map(|(_, probability, _)| probability).sum();
+            let value: f64 = branches
+                .iter()
+                .map(|(_, probability, score)| {
+                    let score = fired_score.map_or(*score, |fired_score| fired_score.max(*score));
+                    probability * score as f64
+                })
+                .sum();
+            value + (1.0 - covered).max(0.0) * fired_score.unwrap_or(DEAD_PENALTY) as f64
+        };
+
+        let best = fired
+            .keys()
+            .chain(branches.keys())
+            .map(|key| (*key, expected(key)))
+            .max_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap());
+
+        match best {
+            Some(((x, r), value)) => {
+                let diagnostics = match fired.get(&(x, r)) {
+                    Some(&(_, chain, score)) => AIDiagnostics {
+                        chain,
+                        score,
+                        ..AIDiagnostics::new(MoveKind::Fire)
+                    },
+                    None => AIDiagnostics::new(MoveKind::Build),
+                };
+                AIDecision::new(
+                    vec![Decision::new(x, r)],
+                    format!("Stable (expectimax) Score: {:.0}", value),
+                    start.elapsed(),
+                )
+                .with_diagnostics(AIDiagnostics {
+                    eval: Some(value as i32),
+                    depth: DEPTH,
+                    ..diagnostics
+                })
+            }
+            None => AIDecision::new(
+                vec![Decision::new(3, 0)],
+                "Emergency placement".to_string(),
+                start.elapsed(),
+            )
+            .with_diagnostics(AIDiagnostics::new(MoveKind::Suicide)),
+        }
+    }
 }
 
 impl AI for StableAI {
@@ -97,7 +222,7 @@ impl AI for StableAI {
     fn think(
         &self,
         player_state_1p: PlayerState,
-        _player_state_2p: Option<PlayerState>,
+        player_state_2p: Option<PlayerState>,
         _think_frame: Option<usize>,
     ) -> AIDecision {
         let start = Instant::now();
@@ -105,45 +230,23 @@ impl AI for StableAI {
         let cf = &player_state_1p.field;
         let seq = &player_state_1p.seq;
 
-        // 見えているツモが少ない場合はランダムツモを追加
-        let visible_tumos = seq.len();
-        let depth = 2.min(visible_tumos);  // 2手先まで見る（安定重視なので浅めで良い）
+        // 降ってくるおじゃまぷよも考慮する
+        let ojama = player_state_1p.ojama_status(player_state_2p.as_ref());
 
-        let seq: Vec<_> = seq
-            .iter()
-            .cloned()
-            .chain(generate_random_puyocolor_sequence(
-                if depth > visible_tumos {
-                    depth - visible_tumos
-                } else {
-                    0
-                },
-            ))
-            .collect();
+        // 見えているツモが足りない場合は、見えていない分の全配色について期待値をとる
+        let visible_tumos = seq.len();
+        if 0 < visible_tumos && visible_tumos < DEPTH {
+            return self.think_expectimax(&player_state_1p, &ojama, start);
+        }
+        let depth = DEPTH.min(visible_tumos);
 
         // 全ての可能な手を評価
         let mut best_plan: Option<Plan> = None;
         let mut best_score = i32::MIN;
         let mut best_decisions = vec![];
 
-        Plan::iterate_available_plans(&cf, &seq, depth, &mut |plan: &Plan| {
-            let mut score = self.evaluator.evaluate(plan);
-
-            // 安定性の追加評価
-            score += Self::evaluate_stability(plan.field());
-
-            // 死んでしまう手は大きくペナルティ
-            if plan.field().is_dead() {
-                score -= 100000;
-            }
-
-            // 13段目に置く手はペナルティ
-            for x in 1..=6 {
-                if !plan.field().is_empty(x, 13) {
-                    score -= 5000;
-                }
-            }
-
+        Plan::iterate_available_plans_with_ojama(&cf, &seq, depth, &ojama, &mut |plan: &Plan| {
+            let score = self.evaluate_plan(plan);
             if score > best_score {
                 best_score = score;
                 best_plan = Some(plan.clone());
@@ -156,6 +259,10 @@ impl AI for StableAI {
             best_decisions = vec![Decision::new(3, 0)];
         }
 
+        let diagnostics = match &best_plan {
+            Some(plan) => AIDiagnostics::from_plan(plan, best_score),
+            None => AIDiagnostics::new(MoveKind::Suicide),
+        };
         let log_output = if let Some(plan) = best_plan {
             let avg_height: f32 = (1..=6)
                 .map(|x| plan.field().height(x) as i16)
@@ -165,6 +272,6 @@ impl AI for StableAI {
             "Emergency placement".to_string()
         };
 
-        AIDecision::new(best_decisions, log_output, start.elapsed())
+        AIDecision::new(best_decisions, log_output, start.elapsed()).with_diagnostics(diagnostics)
     }
 }
\ No newline at end of file
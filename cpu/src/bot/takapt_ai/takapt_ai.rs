@@ -1,22 +1,32 @@
-use std::{cmp::min, sync::mpsc, thread, time::Instant, vec::Vec};
+use std::{cmp::min, time::Instant, vec::Vec};
 
 use puyoai::{
     color::{Color, PuyoColor},
     column_puyo_list::ColumnPuyoList,
     decision::Decision,
     field::CoreField,
-    kumipuyo::{kumipuyo_seq::generate_random_puyocolor_sequence, Kumipuyo},
+    kumipuyo::Kumipuyo,
     plan::Plan,
     rensa_detector::{detector::detect_by_drop, PurposeForFindingRensa},
 };
+use rand::rngs::StdRng;
+use rayon::prelude::*;
 
-use crate::bot::*;
+use crate::bot::{
+    monte_carlo::{generate_random_tumos, monte_carlo_rng, Aggregation},
+    worker_pool::worker_pool,
+    *,
+};
 
 /// Takapt AI - Beam search based AI inspired by takapt's implementation
 pub struct TakaptAI {
     beam_width: usize,
     beam_depth: usize,
     parallel: usize,
+    /// Seed for the random sequences (`None` draws a fresh one on every think)
+    seed: Option<u64>,
+    /// How the simulations' results are combined into a single move
+    aggregation: Aggregation,
 }
 
 impl TakaptAI {
@@ -25,8 +35,22 @@ impl TakaptAI {
             beam_width,
             beam_depth,
             parallel,
+            seed: None,
+            aggregation: Aggregation::Sum,
         }
     }
+
+    /// Search parameters as (depth, width, simulations)
+    pub fn search(&self) -> (usize, usize, usize) {
+        (self.beam_depth, self.beam_width, self.parallel)
+    }
+
+    pub fn with_search(mut self, depth: usize, width: usize, parallel: usize) -> Self {
+        self.beam_depth = depth;
+        self.beam_width = width;
+        self.parallel = parallel;
+        self
+    }
 }
 
 impl AI for TakaptAI {
@@ -35,6 +59,8 @@ impl AI for TakaptAI {
             beam_width: 400,
             beam_depth: 20,
             parallel: 5, // Run 5 simulations with different random sequences (balance speed vs accuracy)
+            seed: None,
+            aggregation: Aggregation::Sum,
         }
     }
 
@@ -46,9 +72,10 @@ impl AI for TakaptAI {
         &self,
         player_state_1p: PlayerState,
         _player_state_2p: Option<PlayerState>,
-        _think_frame: Option<usize>,
+        think_frame: Option<usize>,
     ) -> AIDecision {
         let start = Instant::now();
+        let deadline = think_deadline(start, think_frame);
 
         // If we have enough visible tumos, don't need Monte Carlo
         let parallel = if player_state_1p.seq.len() < self.beam_depth {
@@ -57,77 +84,68 @@ impl AI for TakaptAI {
             1
         };
 
-        // Run multiple simulations with different random sequences
-        let (tx, rx): (mpsc::Sender<SimulationResult>, mpsc::Receiver<SimulationResult>) =
-            mpsc::channel();
-
-        for simulation_id in 0..parallel {
-            let depth = self.beam_depth;
-            let width = self.beam_width;
-            let tx_c = tx.clone();
-            let player_state_c = player_state_1p.clone();
-
-            thread::spawn(move || {
-                tx_c.send(run_single_simulation(
-                    &player_state_c,
-                    depth,
-                    width,
-                    simulation_id,
-                ))
-                .ok();
-            });
-        }
+        // Run multiple simulations with different random sequences on the shared worker pool
+        // (results come back in simulation order, so seeded runs replay exactly)
+        let results: Vec<SimulationResult> = worker_pool().install(|| {
+            (0..parallel)
+                .into_par_iter()
+                .map(|simulation_id| {
+                    run_single_simulation(
+                        &player_state_1p,
+                        self.beam_depth,
+                        self.beam_width,
+                        &mut monte_carlo_rng(self.seed, player_state_1p.tumo_index, simulation_id),
+                        deadline,
+                    )
+                })
+                .collect()
+        });
+
+        // Total nodes expanded over all simulations
+        let nodes: usize = results.iter().map(|result| result.diagnostics.nodes).sum();
 
         // Aggregate results: collect chains for each first decision (like original takapt)
         let mut chains: [[Vec<usize>; 4]; 7] = Default::default(); // [x][rotation] -> vector of chain counts
+        let mut samples: Vec<(Decision, f64)> = Vec::with_capacity(parallel);
         let mut result_map: std::collections::HashMap<(usize, usize), SimulationResult> =
             std::collections::HashMap::new();
 
-        for _ in 0..parallel {
-            if let Ok(result) = rx.recv() {
-                if !result.decisions.is_empty() {
-                    let first_dec = &result.decisions[0];
-                    let x = first_dec.axis_x();
-                    let rot = first_dec.rot();
+        for result in results {
+            if !result.decisions.is_empty() {
+                let first_dec = &result.decisions[0];
+                let x = first_dec.axis_x();
+                let rot = first_dec.rot();
 
-                    // Collect chain count for this decision
-                    chains[x][rot].push(result.max_chains);
+                // Collect chain count for this decision
+                chains[x][rot].push(result.max_chains);
+                samples.push((first_dec.clone(), result.max_chains as f64));
 
-                    // Keep one result for this decision
-                    result_map.insert((x, rot), result);
-                }
+                // Keep one result for this decision
+                result_map.insert((x, rot), result);
             }
         }
 
-        // Select the decision with highest total chains (like original takapt)
-        let mut best_sum_chains = 0;
-        let mut best_decision = Decision::new(3, 0);
-
-        for x in 1..=6 {
-            for rot in 0..4 {
-                if !chains[x][rot].is_empty() {
-                    let sum_chains: usize = chains[x][rot].iter().sum();
-                    if sum_chains > best_sum_chains {
-                        best_sum_chains = sum_chains;
-                        best_decision = Decision::new(x, rot);
-                    }
-                }
-            }
-        }
+        // Select the decision by the configured aggregation
+        // (by default the highest total chains, like original takapt)
+        let best_decision = self
+            .aggregation
+            .select(&samples)
+            .unwrap_or(Decision::new(3, 0));
 
         // Find the result for this decision
         if let Some(result) = result_map.get(&(best_decision.axis_x(), best_decision.rot())) {
-            let avg_chains = if !chains[best_decision.axis_x()][best_decision.rot()].is_empty() {
-                best_sum_chains as f64 / chains[best_decision.axis_x()][best_decision.rot()].len() as f64
-            } else {
-                0.0
-            };
+            let best_chains = &chains[best_decision.axis_x()][best_decision.rot()];
+            let avg_chains = best_chains.iter().sum::<usize>() as f64 / best_chains.len() as f64;
 
             return AIDecision::new(
                 result.decisions.clone(),
                 format!("{} (avg_chains: {:.1})", result.log_output, avg_chains),
                 start.elapsed(),
-            );
+            )
+            .with_diagnostics(AIDiagnostics {
+                nodes,
+                ..result.diagnostics.clone()
+            });
         }
 
         // Fallback
@@ -136,6 +154,18 @@ impl AI for TakaptAI {
             "no valid move".to_string(),
             start.elapsed(),
         )
+        .with_diagnostics(AIDiagnostics {
+            nodes,
+            ..AIDiagnostics::new(MoveKind::Suicide)
+        })
+    }
+
+    fn set_seed(&mut self, seed: u64) {
+        self.seed = Some(seed);
+    }
+
+    fn set_aggregation(&mut self, aggregation: Aggregation) {
+        self.aggregation = aggregation;
     }
 }
 
@@ -144,13 +174,15 @@ struct SimulationResult {
     decisions: Vec<Decision>,
     log_output: String,
     max_chains: usize,
+    diagnostics: AIDiagnostics,
 }
 
 fn run_single_simulation(
     player_state_1p: &PlayerState,
     beam_depth: usize,
     beam_width: usize,
-    simulation_id: usize,
+    rng: &mut StdRng,
+    deadline: Option<Instant>,
 ) -> SimulationResult {
     let cf = &player_state_1p.field;
     let seq = &player_state_1p.seq;
@@ -158,17 +190,19 @@ fn run_single_simulation(
     // Calculate good_chains threshold based on field complexity
     let good_chains = calculate_good_chains(cf);
 
-    // Extend sequence with random puyos if needed (using simulation_id for seed variation)
+    // Extend sequence with random puyos if needed (each simulation has its own rng)
     let visible_tumos = seq.len();
     let seq: Vec<Kumipuyo> = seq
         .iter()
         .cloned()
-        .chain(generate_random_puyocolor_sequence(
+        .chain(generate_random_tumos(
             if beam_depth > visible_tumos {
                 beam_depth - visible_tumos
             } else {
                 0
             },
+            player_state_1p.rules.num_colors,
+            rng,
         ))
         .collect();
 
@@ -176,9 +210,17 @@ fn run_single_simulation(
     let mut fired_states: Vec<State> = Vec::new();
     let mut max_chains = 0;
     let mut first_decision_for_max_chains: Option<Decision> = None;
+    // Depth searched and nodes expanded, for diagnostics
+    let mut depth_reached = 0;
+    let mut nodes = 0;
 
     // Beam search
     for depth in 0..beam_depth.min(seq.len()) {
+        // Stop deepening once the deadline has passed (always search at least one move)
+        if depth > 0 && deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            break;
+        }
+
         let mut next_states: Vec<State> = Vec::new();
         let mut next_fired: Vec<State> = Vec::new();
 
@@ -219,6 +261,8 @@ fn run_single_simulation(
         if next_states.is_empty() {
             break;
         }
+        depth_reached = depth + 1;
+        nodes += next_states.len();
 
         // Sort by score and keep top beam_width states
         next_states.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap());
@@ -261,6 +305,14 @@ fn run_single_simulation(
                         best_fired.chain, best_fired.plan_score, best_fired.score
                     ),
                     max_chains: best_fired.chain,
+                    diagnostics: AIDiagnostics {
+                        kind: MoveKind::Fire,
+                        chain: best_fired.chain,
+                        score: best_fired.plan_score,
+                        eval: Some(best_fired.score as i32),
+                        depth: depth_reached,
+                        nodes,
+                    },
                 };
             }
         }
@@ -273,6 +325,12 @@ fn run_single_simulation(
                 decisions: best_state.decisions.clone(),
                 log_output: format!("BUILD: eval: {:.0}", best_state.score),
                 max_chains: 0,
+                diagnostics: AIDiagnostics {
+                    eval: Some(best_state.score as i32),
+                    depth: depth_reached,
+                    nodes,
+                    ..AIDiagnostics::new(MoveKind::Build)
+                },
             };
         }
     }
@@ -282,6 +340,11 @@ fn run_single_simulation(
         decisions: vec![Decision::new(3, 0)],
         log_output: "no valid move".to_string(),
         max_chains: 0,
+        diagnostics: AIDiagnostics {
+            depth: depth_reached,
+            nodes,
+            ..AIDiagnostics::new(MoveKind::Suicide)
+        },
     }
 }
 
@@ -0,0 +1,35 @@
+use std::sync::OnceLock;
+
+use rayon::{ThreadPool, ThreadPoolBuilder};
+
+static WORKER_POOL: OnceLock<ThreadPool> = OnceLock::new();
+
+/// モンテカルロの各試行や、シミュレータでの試合を走らせる共有のスレッドプール
+///
+/// 全部の AI とハーネスがこのプールを使うので、入れ子に並列化しても
+/// 同時に動くスレッド数はプールのサイズで抑えられる。
+/// `init_worker_pool` が呼ばれていなければ CPU のコア数で作る。
+pub fn worker_pool() -> &'static ThreadPool {
+    WORKER_POOL.get_or_init(|| build_worker_pool(None))
+}
+
+/// 共有のスレッドプールのサイズを決める（`None` なら CPU のコア数）
+///
+/// プールを使い始める前に呼ぶ必要がある。既に作られていたら何もせず `false` を返す。
+pub fn init_worker_pool(num_threads: Option<usize>) -> bool {
+    let mut initialized = false;
+    WORKER_POOL.get_or_init(|| {
+        initialized = true;
+        build_worker_pool(num_threads)
+    });
+    initialized
+}
+
+fn build_worker_pool(num_threads: Option<usize>) -> ThreadPool {
+    ThreadPoolBuilder::new()
+        // 0 なら rayon がコア数を選ぶ
+        .num_threads(num_threads.unwrap_or(0))
+        .thread_name(|i| format!("ghoti-worker-{}", i))
+        .build()
+        .unwrap()
+}
@@ -1,4 +1,10 @@
 pub mod detect_shape;
 pub mod evaluator;
+pub mod feature_cache;
+pub mod shape_template;
 
-pub use evaluator::Evaluator;
+pub use evaluator::{EvaluationTerm, Evaluator, Explanation};
+pub use feature_cache::FeatureCache;
+pub use shape_template::{
+    Orientation, PartialMatch, ShapeFound, ShapeMatch, ShapeSet, ShapeTemplate,
+};
@@ -8,6 +8,11 @@ macro_rules! detect_shape {
         $name:ident
         $([$($color:tt)*])*
     ) => {
+        // 同じ形を `ShapeTemplate` でも作れるように、行を文字列でも持っておく（`gtr_1::ROWS` など）
+        pub mod $name {
+            pub const ROWS: &[&str] = &[$(stringify!($($color)*)),*];
+        }
+
         pub fn $name(cf: &CoreField) -> bool {
             // corr: `A` などと実際の色との対応関係
             let mut corr = [PuyoColor::EMPTY; 5];
@@ -378,4 +383,9 @@ mod tests {
             false
         );
     }
+
+    #[test]
+    fn test_rows() {
+        assert_eq!(gtr_1::ROWS, &["C A B _ _ _", "C C A B _ _", "A A B B _ _"]);
+    }
 }
@@ -1,3 +1,5 @@
+use std::{fmt, sync::OnceLock};
+
 use puyoai::{
     color::Color,
     column_puyo_list::ColumnPuyoList,
@@ -8,7 +10,11 @@ use puyoai::{
 };
 use serde::{Deserialize, Serialize};
 
-use super::detect_shape::*;
+use super::{
+    detect_shape::*,
+    feature_cache::{column_features, columns_around, FeatureCache, FieldFeatures},
+    shape_template::{Orientation, ShapeFound, ShapeMatch, ShapeTemplate},
+};
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Evaluator {
@@ -85,6 +91,15 @@ pub struct Evaluator {
     pub gtr_head_4: i32,
     pub gtr_head_5: i32,
     pub gtr_head_6: i32,
+    // 組み込みの形（`detect_shape`）を探す向き（省略時は書いたとおりの向きだけ）
+    #[serde(default)]
+    pub pattern_orientation: Orientation,
+    // 組み込みの形が途中までできていても、埋まっている割合だけ重みを足すか（省略時は完成したときだけ）
+    #[serde(default)]
+    pub pattern_partial: bool,
+    // 実行時に読み込んだ形のテンプレート（形と重み）
+    #[serde(default)]
+    pub shapes: Vec<ShapeTemplate>,
     // チューニング用
     pub sub_name: Option<String>,
 }
@@ -165,6 +180,9 @@ impl Default for Evaluator {
             gtr_head_4: 30,
             gtr_head_5: 30,
             gtr_head_6: 30,
+            pattern_orientation: Orientation::Left,
+            pattern_partial: false,
+            shapes: vec![],
             // チューニング用
             sub_name: None,
         };
@@ -195,22 +213,177 @@ impl Evaluator {
         }
     }
 
+    /// 形のテンプレートを追加する
+    pub fn with_shapes(mut self, shapes: Vec<ShapeTemplate>) -> Self {
+        self.shapes.extend(shapes);
+        self
+    }
+
     pub fn evaluate(&self, plan: &Plan) -> i32 {
+        self.evaluate_internal(plan, None, None)
+    }
+
+    /// `evaluate` と同じ評価値を、`cache` に覚えた盤面の特徴量を使い回して計算する
+    pub fn evaluate_cached(&self, plan: &Plan, cache: &mut FeatureCache) -> i32 {
+        self.evaluate_internal(plan, None, Some(cache))
+    }
+
+    /// `evaluate` と同じ評価値を、特徴量ごとの内訳付きで返す
+    pub fn evaluate_explained(&self, plan: &Plan) -> Explanation {
+        let mut terms = vec![];
+        let total = self.evaluate_internal(plan, Some(&mut terms), None);
+        Explanation { total, terms }
+    }
+
+    /// 盤面だけから決まる重い特徴量を計算する（`FeatureCache` が覚えておくもの）
+    ///
+    /// `parent` に 1 手前の盤面の特徴量と中身が変わった列（x 列目が `1 << (x - 1)`）を渡すと、
+    /// 変わった列の影響を受けない連結と形の特徴量はそちらを使う。
+    pub(crate) fn field_features(
+        &self,
+        cf: &CoreField,
+        parent: Option<(&FieldFeatures, u32)>,
+    ) -> FieldFeatures {
+        // `columns` の列が変わっていなければ、1 手前の盤面の特徴量を返す
+        let reuse = |columns: u32| {
+            parent
+                .filter(|&(_, changed)| changed & columns == 0)
+                .map(|(parent, _)| parent)
+        };
+
+        // 4 個未満の連結は 3 列までにしか広がらないので、3 列より離れた列が変わっても変わらない
+        let mut column_connectivity = [[0; 4]; field::WIDTH];
+        for x in 1..=field::WIDTH {
+            column_connectivity[x - 1] = match reuse(columns_around(x, 3)) {
+                Some(parent) => parent.column_connectivity[x - 1],
+                None => connectivity(cf, x),
+            };
+        }
+        let mut total_connectivity = [0; 4];
+        for con in &column_connectivity {
+            for (total, cnt) in total_connectivity.iter_mut().zip(con) {
+                *total += cnt;
+            }
+        }
+
+        let (main_chain, sub_chain) = detect_chains(cf);
+        FieldFeatures {
+            connectivity: total_connectivity,
+            column_connectivity,
+            unreachable_space: cf.count_unreachable_spaces() as i32,
+            main_chain,
+            sub_chain,
+            patterns: PATTERNS
+                .iter()
+                .zip(builtin_shapes())
+                .enumerate()
+                .map(|(i, ((_, detect, _, _), shape))| {
+                    match reuse(shape.columns_in(self.pattern_orientation)) {
+                        Some(parent) => parent.patterns[i],
+                        None => self.pattern_feature(cf, *detect, shape),
+                    }
+                })
+                .collect(),
+            shapes: self
+                .shapes
+                .iter()
+                .enumerate()
+                .map(|(i, shape)| match reuse(shape.columns()) {
+                    Some(parent) => parent.shapes[i],
+                    None => shape.feature(cf),
+                })
+                .collect(),
+        }
+    }
+
+    /// 組み込みの形を `pattern_orientation` の向きで探す（`pattern_partial` なら途中までできた形も）
+    fn pattern_feature(
+        &self,
+        cf: &CoreField,
+        detect: fn(&CoreField) -> bool,
+        shape: &ShapeTemplate,
+    ) -> (f32, Option<ShapeFound>) {
+        match (self.pattern_orientation, self.pattern_partial) {
+            // 書いたとおりの向きで完成した形だけなら、`detect_shape!` で作った関数で判定する
+            (Orientation::Left, false) if detect(cf) => (
+                1.0,
+                Some(ShapeFound::Complete(ShapeMatch {
+                    mirrored: false,
+                    offset: 0,
+                })),
+            ),
+            (Orientation::Left, false) => (0.0, None),
+            (orientation, partial) => shape.feature_in(cf, orientation, partial),
+        }
+    }
+
+    /// `terms` が与えられたら、足し合わせた項を 1 つずつ記録する
+    /// `cache` が与えられたら、盤面の特徴量はそこから取り出す
+    fn evaluate_internal(
+        &self,
+        plan: &Plan,
+        mut terms: Option<&mut Vec<EvaluationTerm>>,
+        cache: Option<&mut FeatureCache>,
+    ) -> i32 {
         let cf = plan.field();
         let res = plan.rensa_result();
 
         if cf.is_dead() {
-            return i32::MIN >> 7;
+            let score = i32::MIN >> 7;
+            if let Some(terms) = terms {
+                terms.push(EvaluationTerm {
+                    name: "dead".to_owned(),
+                    value: 1.0,
+                    weight: score,
+                    contribution: score,
+                });
+            }
+            return score;
         }
 
+        let owned_features;
+        let (features, columns) = match cache {
+            Some(cache) => cache.features(self, cf),
+            None => {
+                owned_features = self.field_features(cf, None);
+                (&owned_features, column_features(cf))
+            }
+        };
+
         let mut score = 0_i32;
 
+        // `add!(名前, 重み, 特徴量)` で `重み * 特徴量` を足す（寄与を別に計算するときは 4 つ目に渡す）
+        macro_rules! add {
+            ($name:expr, $weight:expr, $value:expr) => {{
+                let value: i32 = $value;
+                add!($name, $weight, value as f32, $weight * value);
+            }};
+            ($name:expr, $weight:expr, $value:expr, $contribution:expr) => {{
+                let contribution: i32 = $contribution;
+                score += contribution;
+                if let Some(terms) = terms.as_deref_mut() {
+                    terms.push(EvaluationTerm {
+                        name: $name.to_string(),
+                        value: $value,
+                        weight: $weight,
+                        contribution,
+                    });
+                }
+            }};
+        }
+
         {
             // 盤面
-            for x in 1..=field::WIDTH {
-                score += self.valley * cf.valley_depth(x) as i32;
-                score += self.ridge * cf.ridge_height(x) as i32;
-            }
+            add!(
+                "valley",
+                self.valley,
+                columns.iter().map(|column| column.valley).sum()
+            );
+            add!(
+                "ridge",
+                self.ridge,
+                columns.iter().map(|column| column.ridge).sum()
+            );
 
             let average_height = average_height(cf);
             let mut diff_sum = 0.0;
@@ -241,116 +414,269 @@ impl Evaluator {
                 1.0
             };
 
-            score += (self.ideal_height_diff as f32 * diff_sum * coef) as i32;
-            score += (self.ideal_height_diff_sq as f32 * diff_sq_sum * coef) as i32;
+            // 特徴量は平均の高さに応じた係数を掛けたもの
+            add!(
+                "ideal_height_diff",
+                self.ideal_height_diff,
+                diff_sum * coef,
+                (self.ideal_height_diff as f32 * diff_sum * coef) as i32
+            );
+            add!(
+                "ideal_height_diff_sq",
+                self.ideal_height_diff_sq,
+                diff_sq_sum * coef,
+                (self.ideal_height_diff_sq as f32 * diff_sq_sum * coef) as i32
+            );
 
-            score += self.third_column_height * cf.height(3) as i32;
-            score += self.third_column_height_sq * (cf.height(3) * cf.height(3)) as i32;
+            add!(
+                "third_column_height",
+                self.third_column_height,
+                cf.height(3) as i32
+            );
+            add!(
+                "third_column_height_sq",
+                self.third_column_height_sq,
+                (cf.height(3) * cf.height(3)) as i32
+            );
 
-            score += self.unreachable_space * cf.count_unreachable_spaces() as i32;
+            add!(
+                "unreachable_space",
+                self.unreachable_space,
+                features.unreachable_space
+            );
 
             for x in 1..=field::WIDTH {
-                if !cf.is_empty(x, 13) {
-                    score += self.top_row[x - 1];
-                }
+                add!(
+                    format!("top_row[{}]", x),
+                    self.top_row[x - 1],
+                    (!cf.is_empty(x, 13)) as i32
+                );
             }
         }
 
         {
             // 連結
-            let connectivity = connectivity(cf);
-            score += self.connectivity_2 * connectivity[2];
-            score += self.connectivity_3 * connectivity[3];
+            add!(
+                "connectivity_2",
+                self.connectivity_2,
+                features.connectivity[2]
+            );
+            add!(
+                "connectivity_3",
+                self.connectivity_3,
+                features.connectivity[3]
+            );
         }
 
         {
             // 発生した連鎖
-            score += self.chain * res.chain as i32;
-            score += self.chain_sq * (res.chain * res.chain) as i32;
-            score += self.chain_score * (res.score / 1000) as i32;
-            score += self.chain_frame * res.frame as i32;
+            add!("chain", self.chain, res.chain as i32);
+            add!("chain_sq", self.chain_sq, (res.chain * res.chain) as i32);
+            add!("chain_score", self.chain_score, (res.score / 1000) as i32);
+            add!("chain_frame", self.chain_frame, res.frame as i32);
         }
 
         {
-            // 盤面から起こりうる連鎖
-            let (main_chain, sub_chain) = detect_chains(cf);
-
-            if let Some((rensa_result, ignition_y)) = main_chain {
-                score += self.potential_main_chain * rensa_result.chain as i32;
-                score +=
-                    self.potential_main_chain * (rensa_result.chain * rensa_result.chain) as i32;
-                score += self.potential_main_chain_frame * rensa_result.frame as i32;
-                score += self.potential_main_chain_ignition_height * ignition_y as i32;
-            }
+            // 盤面から起こりうる連鎖（見つからなければ特徴量は 0）
+            let chain_features = |chain: &Option<(RensaResult, usize)>| {
+                chain.as_ref().map_or((0, 0, 0), |(rensa_result, y)| {
+                    (rensa_result.chain, rensa_result.frame, *y)
+                })
+            };
 
-            if let Some((rensa_result, ignition_y)) = sub_chain {
-                score += self.potential_sub_chain * rensa_result.chain as i32;
-                score +=
-                    self.potential_sub_chain * (rensa_result.chain * rensa_result.chain) as i32;
-                score += self.potential_sub_chain_frame * rensa_result.frame as i32;
-                score += self.potential_sub_chain_ignition_height * ignition_y as i32;
-            }
+            let (chain, frame, ignition_y) = chain_features(&features.main_chain);
+            add!(
+                "potential_main_chain",
+                self.potential_main_chain,
+                chain as i32
+            );
+            // 2 乗の項にも `potential_main_chain` の重みを使っている
+            add!(
+                "potential_main_chain_sq",
+                self.potential_main_chain,
+                (chain * chain) as i32
+            );
+            add!(
+                "potential_main_chain_frame",
+                self.potential_main_chain_frame,
+                frame as i32
+            );
+            add!(
+                "potential_main_chain_ignition_height",
+                self.potential_main_chain_ignition_height,
+                ignition_y as i32
+            );
+
+            let (chain, frame, ignition_y) = chain_features(&features.sub_chain);
+            add!(
+                "potential_sub_chain",
+                self.potential_sub_chain,
+                chain as i32
+            );
+            // 2 乗の項にも `potential_sub_chain` の重みを使っている
+            add!(
+                "potential_sub_chain_sq",
+                self.potential_sub_chain,
+                (chain * chain) as i32
+            );
+            add!(
+                "potential_sub_chain_frame",
+                self.potential_sub_chain_frame,
+                frame as i32
+            );
+            add!(
+                "potential_sub_chain_ignition_height",
+                self.potential_sub_chain_ignition_height,
+                ignition_y as i32
+            );
         }
 
         {
             // フレーム関係
-            score += self.chigiri * plan.num_chigiri() as i32;
-            score += self.move_frame * plan.frame() as i32;
+            add!("chigiri", self.chigiri, plan.num_chigiri() as i32);
+            add!("move_frame", self.move_frame, plan.frame() as i32);
         }
 
         {
             // パターンマッチング
-            macro_rules! pattern_matching {
-                ($name:ident) => {
-                    score += self.$name * $name(cf) as i32;
-                };
+            // 内訳には見つかった向きと位置も出す（例: `gtr (right+1)`、`gtr (left, 5/8)`）
+            let term_name = |name: &str, detail: Option<ShapeFound>| match detail {
+                Some(detail) => format!("{} ({})", name, detail),
+                None => name.to_owned(),
+            };
+            for ((name, _, _, weight), &(value, detail)) in PATTERNS.iter().zip(&features.patterns)
+            {
+                add!(
+                    term_name(name, detail),
+                    weight(self),
+                    value,
+                    (weight(self) as f32 * value) as i32
+                );
+            }
+            for (shape, &(value, detail)) in self.shapes.iter().zip(&features.shapes) {
+                add!(
+                    term_name(&shape.name, detail),
+                    shape.weight,
+                    value,
+                    (shape.weight as f32 * value) as i32
+                );
             }
-
-            pattern_matching!(gtr_base_1);
-            pattern_matching!(gtr_base_2);
-            pattern_matching!(gtr_base_3);
-            pattern_matching!(gtr_base_4);
-            pattern_matching!(gtr_base_5);
-            pattern_matching!(gtr_base_6);
-            pattern_matching!(gtr_base_7);
-            pattern_matching!(gtr_1);
-            pattern_matching!(gtr_2);
-            pattern_matching!(gtr_3);
-            pattern_matching!(gtr_4);
-            pattern_matching!(gtr_5);
-            pattern_matching!(gtr_6);
-            pattern_matching!(gtr_tail_1_1);
-            pattern_matching!(gtr_tail_1_2);
-            pattern_matching!(gtr_tail_1_3);
-            pattern_matching!(gtr_tail_2_1);
-            pattern_matching!(gtr_tail_2_2);
-            pattern_matching!(gtr_tail_2_3);
-            pattern_matching!(gtr_tail_2_4);
-            pattern_matching!(gtr_tail_2_5);
-            pattern_matching!(gtr_tail_2_6);
-            pattern_matching!(gtr_tail_2_7);
-            pattern_matching!(gtr_tail_3_1);
-            pattern_matching!(gtr_tail_3_2);
-            pattern_matching!(gtr_tail_3_3);
-            pattern_matching!(gtr_tail_3_4);
-            pattern_matching!(gtr_tail_4_1);
-            pattern_matching!(gtr_tail_5_1);
-            pattern_matching!(gtr_tail_5_2);
-            pattern_matching!(gtr_tail_6_1);
-            pattern_matching!(gtr_tail_6_2);
-            pattern_matching!(gtr_tail_6_3);
-            pattern_matching!(gtr_head_1);
-            pattern_matching!(gtr_head_2);
-            pattern_matching!(gtr_head_3);
-            pattern_matching!(gtr_head_4);
-            pattern_matching!(gtr_head_5);
-            pattern_matching!(gtr_head_6);
         }
 
         score
     }
 }
 
+/// 評価値の内訳の 1 項目（`contribution` を全部足すと評価値になる）
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct EvaluationTerm {
+    /// 重みのフィールド名（`top_row` は `top_row[x]` のように列ごと、形のテンプレートはその名前）
+    pub name: String,
+    /// 特徴量の値
+    pub value: f32,
+    pub weight: i32,
+    /// 評価値への寄与（基本的に `weight * value`）
+    pub contribution: i32,
+}
+
+impl fmt::Display for EvaluationTerm {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:<38} {:>8.2} x {:>6} = {:>8}",
+            self.name, self.value, self.weight, self.contribution
+        )
+    }
+}
+
+/// `Evaluator::evaluate_explained` の結果
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Explanation {
+    /// 評価値（`evaluate` の返り値と同じ）
+    pub total: i32,
+    /// 足し合わせた順の内訳
+    pub terms: Vec<EvaluationTerm>,
+}
+
+impl Explanation {
+    /// 寄与が 0 でない項目を、寄与の絶対値が大きい順に返す
+    pub fn nonzero_terms(&self) -> Vec<&EvaluationTerm> {
+        let mut terms: Vec<&EvaluationTerm> = self
+            .terms
+            .iter()
+            .filter(|term| term.contribution != 0)
+            .collect();
+        terms.sort_by_key(|term| std::cmp::Reverse(term.contribution.unsigned_abs()));
+        terms
+    }
+}
+
+/// 組み込みの形を判定する関数と行、その重みを取り出す関数を名前付きで並べる
+macro_rules! patterns {
+    ($($name:ident),* $(,)?) => {
+        &[$((
+            stringify!($name),
+            $name as fn(&CoreField) -> bool,
+            $name::ROWS,
+            (|evaluator: &Evaluator| evaluator.$name) as fn(&Evaluator) -> i32,
+        )),*]
+    };
+}
+
+/// 組み込みの形（`detect_shape`）の一覧（`FieldFeatures::patterns` の順番）
+const PATTERNS: &[(&str, fn(&CoreField) -> bool, &[&str], fn(&Evaluator) -> i32)] = patterns!(
+    gtr_base_1,
+    gtr_base_2,
+    gtr_base_3,
+    gtr_base_4,
+    gtr_base_5,
+    gtr_base_6,
+    gtr_base_7,
+    gtr_1,
+    gtr_2,
+    gtr_3,
+    gtr_4,
+    gtr_5,
+    gtr_6,
+    gtr_tail_1_1,
+    gtr_tail_1_2,
+    gtr_tail_1_3,
+    gtr_tail_2_1,
+    gtr_tail_2_2,
+    gtr_tail_2_3,
+    gtr_tail_2_4,
+    gtr_tail_2_5,
+    gtr_tail_2_6,
+    gtr_tail_2_7,
+    gtr_tail_3_1,
+    gtr_tail_3_2,
+    gtr_tail_3_3,
+    gtr_tail_3_4,
+    gtr_tail_4_1,
+    gtr_tail_5_1,
+    gtr_tail_5_2,
+    gtr_tail_6_1,
+    gtr_tail_6_2,
+    gtr_tail_6_3,
+    gtr_head_1,
+    gtr_head_2,
+    gtr_head_3,
+    gtr_head_4,
+    gtr_head_5,
+    gtr_head_6,
+);
+
+/// 組み込みの形を `ShapeTemplate` にしたもの（`PATTERNS` の順番、重みは `Evaluator` の方を使う）
+fn builtin_shapes() -> &'static [ShapeTemplate] {
+    static BUILTIN_SHAPES: OnceLock<Vec<ShapeTemplate>> = OnceLock::new();
+    BUILTIN_SHAPES.get_or_init(|| {
+        PATTERNS
+            .iter()
+            .map(|(name, _, rows, _)| ShapeTemplate::new(name, 0, rows).unwrap())
+            .collect()
+    })
+}
+
 /// 各列の平均の高さを返す
 fn average_height(cf: &CoreField) -> f32 {
     let mut sum = 0;
@@ -360,20 +686,18 @@ fn average_height(cf: &CoreField) -> f32 {
     sum as f32 / 6.0
 }
 
-/// 連結の数を数える
-fn connectivity(cf: &CoreField) -> [i32; 4] {
+/// x 列目にあるぷよを、連結の数ごとに数える
+fn connectivity(cf: &CoreField, x: usize) -> [i32; 4] {
     let mut con = [0; 4];
-    for x in 1..=field::WIDTH {
-        for y in 1..=cf.height(x) {
-            // おじゃまなどは飛ばす
-            if !cf.color(x, y).is_normal_color() {
-                continue;
-            }
-            // TODO: すでに計算済みなら飛ばす（puyoai の `countConnectedPuyos`）
-            let cnt = cf.count_connected(x, y);
-            if cnt < 4 {
-                con[cnt] += 1;
-            }
+    for y in 1..=cf.height(x) {
+        // おじゃまなどは飛ばす
+        if !cf.color(x, y).is_normal_color() {
+            continue;
+        }
+        // TODO: すでに計算済みなら飛ばす（puyoai の `countConnectedPuyos`）
+        let cnt = cf.count_connected(x, y);
+        if cnt < 4 {
+            con[cnt] += 1;
         }
     }
     con
@@ -426,3 +750,183 @@ fn detect_chains(cf: &CoreField) -> (Option<(RensaResult, usize)>, Option<(Rensa
 
     (main_chain, sub_chain)
 }
+
+#[cfg(test)]
+mod tests {
+    use puyoai::{color::PuyoColor, decision::Decision, kumipuyo::Kumipuyo};
+
+    use super::*;
+
+    fn plan_from_str(field: &str) -> Plan {
+        let mut cf = CoreField::from_str(field);
+        let rensa_result = cf.simulate();
+        Plan::new(
+            cf,
+            vec![Decision::new(2, 2), Decision::new(1, 1)],
+            rensa_result,
+            1,
+            342,
+            1200,
+            10,
+            20,
+            30,
+            40,
+            false,
+        )
+    }
+
+    #[test]
+    fn test_evaluate_explained() {
+        let evaluator = Evaluator::default();
+        for field in [
+            concat!(
+                "......", // 4
+                "GRB...", // 3
+                "GGRB..", // 2
+                "RRBB.."  // 1
+            ),
+            concat!(
+                ".GY..G", // 11
+                ".GGYBG", // 10
+                "YGRYYB", // 9
+                "BRGGGB", // 8
+                "BYRRYB", // 7
+                "YYRYGY", // 6
+                "BBBYGG", // 5
+                "YYYGRR", // 4
+                "GRBRYR", // 3
+                "GGRBBB", // 2
+                "RRBYYY"  // 1
+            ),
+        ] {
+            let plan = plan_from_str(field);
+            let explanation = evaluator.evaluate_explained(&plan);
+
+            // 内訳を足すと `evaluate` と同じ評価値になる
+            assert_eq!(explanation.total, evaluator.evaluate(&plan));
+            assert_eq!(
+                explanation
+                    .terms
+                    .iter()
+                    .map(|term| term.contribution)
+                    .sum::<i32>(),
+                explanation.total
+            );
+            assert!(explanation
+                .terms
+                .iter()
+                .any(|term| term.name.starts_with("gtr_1")));
+            assert!(explanation
+                .terms
+                .iter()
+                .any(|term| term.name == "top_row[6]"));
+
+            let nonzero_terms = explanation.nonzero_terms();
+            assert!(nonzero_terms.iter().all(|term| term.contribution != 0));
+            assert!(nonzero_terms
+                .windows(2)
+                .all(|w| w[0].contribution.abs() >= w[1].contribution.abs()));
+        }
+    }
+
+    #[test]
+    fn test_pattern_orientation() {
+        // 右側に作った GTR
+        let plan = plan_from_str(concat!(
+            "......", // 4
+            "...BRG", // 3
+            "..BRGG", // 2
+            "..BBRR"  // 1
+        ));
+        let gtr_1 = |evaluator: &Evaluator| {
+            evaluator
+                .evaluate_explained(&plan)
+                .terms
+                .into_iter()
+                .find(|term| term.name.starts_with("gtr_1"))
+                .unwrap()
+        };
+
+        // 既定では書いたとおりの左向きしか探さない
+        let term = gtr_1(&Evaluator::default());
+        assert_eq!(term.name, "gtr_1");
+        assert_eq!(term.contribution, 0);
+
+        let evaluator = Evaluator {
+            pattern_orientation: Orientation::Both,
+            ..Evaluator::default()
+        };
+        let term = gtr_1(&evaluator);
+        assert_eq!(term.name, "gtr_1 (right)");
+        assert_eq!(term.contribution, evaluator.gtr_1);
+    }
+
+    #[test]
+    fn test_pattern_partial() {
+        // GTR の形まであと 1 個
+        let plan = plan_from_str(concat!(
+            "......", // 4
+            "GR....", // 3
+            "GGRB..", // 2
+            "RRBB.."  // 1
+        ));
+        let gtr_1 = |evaluator: &Evaluator| {
+            evaluator
+                .evaluate_explained(&plan)
+                .terms
+                .into_iter()
+                .find(|term| term.name.starts_with("gtr_1"))
+                .unwrap()
+        };
+
+        // 既定では完成していなければ何も足さない
+        assert_eq!(gtr_1(&Evaluator::default()).contribution, 0);
+
+        let evaluator = Evaluator {
+            pattern_partial: true,
+            ..Evaluator::default()
+        };
+        let term = gtr_1(&evaluator);
+        assert_eq!(term.name, "gtr_1 (left, 10/11)");
+        assert_eq!(term.value, 10.0 / 11.0);
+        assert_eq!(
+            term.contribution,
+            (evaluator.gtr_1 as f32 * 10.0 / 11.0) as i32
+        );
+        assert!(term.contribution > 0);
+    }
+
+    #[test]
+    fn test_evaluate_cached() {
+        let evaluator = Evaluator::default().with_shapes(vec![ShapeTemplate::new(
+            "gtr_1",
+            50,
+            &["C A B _ _ _", "C C A B _ _", "A A B B _ _"],
+        )
+        .unwrap()
+        .with_orientation(Orientation::Both)
+        .with_partial(true)]);
+        let parent = CoreField::from_str(concat!(
+            "......", // 3
+            "GRB...", // 2
+            "GGRB.."  // 1
+        ));
+        let seq = vec![
+            Kumipuyo::new(PuyoColor::RED, PuyoColor::BLUE),
+            Kumipuyo::new(PuyoColor::YELLOW, PuyoColor::YELLOW),
+        ];
+
+        // 何度評価しても、キャッシュを使わない場合と同じ評価値になる
+        let mut cache = FeatureCache::with_capacity(16);
+        for _ in 0..2 {
+            cache.set_parent(&parent);
+            Plan::iterate_available_plans(&parent, &seq, 2, &mut |plan: &Plan| {
+                assert_eq!(
+                    evaluator.evaluate_cached(plan, &mut cache),
+                    evaluator.evaluate(plan)
+                );
+            });
+        }
+        assert!(cache.hits() > 0);
+    }
+}
@@ -0,0 +1,342 @@
+use std::{collections::HashMap, mem};
+
+use puyoai::{
+    field::{self, CoreField},
+    rensa_result::RensaResult,
+};
+
+use super::{evaluator::Evaluator, shape_template::ShapeFound};
+
+/// 盤面の内容（列ごとに、下から 3 bit ずつ色を詰めたもの）
+pub type FieldKey = [u64; field::WIDTH];
+
+pub fn field_key(cf: &CoreField) -> FieldKey {
+    let mut key = [0_u64; field::WIDTH];
+    for x in 1..=field::WIDTH {
+        for y in 1..=cf.height(x) {
+            key[x - 1] |= (cf.color(x, y) as u64) << (3 * (y - 1));
+        }
+    }
+    key
+}
+
+/// 2 つの盤面で中身が違う列（x 列目が `1 << (x - 1)`）
+fn changed_columns(key: &FieldKey, other: &FieldKey) -> u32 {
+    (1..=field::WIDTH)
+        .filter(|&x| key[x - 1] != other[x - 1])
+        .fold(0, |columns, x| columns | 1 << (x - 1))
+}
+
+/// x 列目から左右 `distance` 列以内の列（x 列目が `1 << (x - 1)`）
+pub(crate) fn columns_around(x: usize, distance: usize) -> u32 {
+    (x.saturating_sub(distance).max(1)..=(x + distance).min(field::WIDTH))
+        .fold(0, |columns, x| columns | 1 << (x - 1))
+}
+
+/// 盤面だけから決まる特徴量のうち、計算が重いもの
+pub(crate) struct FieldFeatures {
+    pub connectivity: [i32; 4],
+    /// `connectivity` の列ごとの内訳（その列に置かれているぷよだけ数える）
+    pub column_connectivity: [[i32; 4]; field::WIDTH],
+    pub unreachable_space: i32,
+    /// 本線と副砲の `(その連鎖の詳細, 発火点の高さ)`
+    pub main_chain: Option<(RensaResult, usize)>,
+    pub sub_chain: Option<(RensaResult, usize)>,
+    /// 組み込みの形（`Evaluator::pattern_orientation` の向きで探す）の特徴量
+    pub patterns: Vec<(f32, Option<ShapeFound>)>,
+    /// `Evaluator::shapes` の各テンプレートの特徴量
+    pub shapes: Vec<(f32, Option<ShapeFound>)>,
+}
+
+/// 列ごとの特徴量（その列と両隣の列の高さだけで決まる）
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) struct ColumnFeatures {
+    pub valley: i32,
+    pub ridge: i32,
+}
+
+pub(crate) fn column_features(cf: &CoreField) -> [ColumnFeatures; field::WIDTH] {
+    let mut columns = [ColumnFeatures::default(); field::WIDTH];
+    for x in 1..=field::WIDTH {
+        columns[x - 1] = column_feature(cf, x);
+    }
+    columns
+}
+
+fn column_feature(cf: &CoreField, x: usize) -> ColumnFeatures {
+    ColumnFeatures {
+        valley: cf.valley_depth(x) as i32,
+        ridge: cf.ridge_height(x) as i32,
+    }
+}
+
+/// 評価した盤面の特徴量を盤面ごとにメモ化して、同じ盤面をもう一度評価するときに使い回す
+/// - 形のテンプレートの特徴量も覚えるので、1 つの `Evaluator` 専用にすること
+/// - `set_parent` で 1 手前の盤面を設定しておくと、初めて見た盤面でも、変わった列の影響を受けない特徴量は
+///   1 手前の盤面のものを使う（列ごとの特徴量・連結・形。連鎖と届かない空間は盤面全体から計算し直す）
+/// - 覚えておく盤面は新旧 2 世代に分け、新しい世代がいっぱいになったら古い世代を捨てる
+///   （古い世代で見つかった盤面は新しい世代に移すので、最近使った盤面ほど残る）
+pub struct FeatureCache {
+    /// 新しい世代
+    fields: HashMap<FieldKey, FieldFeatures>,
+    /// 古い世代
+    old_fields: HashMap<FieldKey, FieldFeatures>,
+    /// 1 世代に覚えておく盤面の数の上限（2 世代で `with_capacity` に渡した数になる）
+    generation_capacity: usize,
+    /// 1 手前の盤面と、その列ごとの特徴量
+    parent: Option<(CoreField, FieldKey, [ColumnFeatures; field::WIDTH])>,
+    hits: usize,
+    misses: usize,
+}
+
+impl Default for FeatureCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FeatureCache {
+    pub fn new() -> Self {
+        Self::with_capacity(1 << 16)
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        FeatureCache {
+            fields: HashMap::new(),
+            old_fields: HashMap::new(),
+            generation_capacity: (capacity / 2).max(1),
+            parent: None,
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    /// これから評価する盤面の 1 手前の盤面を設定する
+    pub fn set_parent(&mut self, cf: &CoreField) {
+        self.parent = Some((cf.clone(), field_key(cf), column_features(cf)));
+    }
+
+    /// 覚えていた盤面を使えた回数
+    pub fn hits(&self) -> usize {
+        self.hits
+    }
+
+    /// 盤面の特徴量を計算し直した回数
+    pub fn misses(&self) -> usize {
+        self.misses
+    }
+
+    pub(crate) fn features(
+        &mut self,
+        evaluator: &Evaluator,
+        cf: &CoreField,
+    ) -> (&FieldFeatures, [ColumnFeatures; field::WIDTH]) {
+        let key = field_key(cf);
+
+        let columns = match &self.parent {
+            Some((_, parent_key, parent_columns)) => {
+                // 中身が変わった列と、その両隣の列だけ計算し直す
+                let changed = changed_columns(&key, parent_key);
+                let mut columns = *parent_columns;
+                for x in 1..=field::WIDTH {
+                    if changed & columns_around(x, 1) != 0 {
+                        columns[x - 1] = column_feature(cf, x);
+                    }
+                }
+                columns
+            }
+            None => column_features(cf),
+        };
+
+        if self.fields.len() >= self.generation_capacity && !self.fields.contains_key(&key) {
+            // 古い世代を捨てて、新しい世代を古い世代にする
+            mem::swap(&mut self.fields, &mut self.old_fields);
+            self.fields.clear();
+        }
+        if self.fields.contains_key(&key) {
+            self.hits += 1;
+            return (&self.fields[&key], columns);
+        }
+        let features = match self.old_fields.remove(&key) {
+            Some(features) => {
+                self.hits += 1;
+                features
+            }
+            None => match &self.parent {
+                Some((parent_cf, parent_key, _)) => {
+                    // 1 手前の盤面の特徴量が無ければ先に計算しておく（兄弟の盤面でも使い回せる）
+                    if !self.fields.contains_key(parent_key)
+                        && !self.old_fields.contains_key(parent_key)
+                    {
+                        self.misses += 1;
+                        self.fields
+                            .insert(*parent_key, evaluator.field_features(parent_cf, None));
+                    }
+                    let parent = self
+                        .fields
+                        .get(parent_key)
+                        .or_else(|| self.old_fields.get(parent_key))
+                        .unwrap();
+                    self.misses += 1;
+                    evaluator.field_features(cf, Some((parent, changed_columns(&key, parent_key))))
+                }
+                None => {
+                    self.misses += 1;
+                    evaluator.field_features(cf, None)
+                }
+            },
+        };
+
+        (self.fields.entry(key).or_insert(features), columns)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evaluator::{Orientation, ShapeTemplate};
+
+    #[test]
+    fn test_field_key() {
+        assert_eq!(field_key(&CoreField::new()), [0; field::WIDTH]);
+        assert_eq!(
+            field_key(&CoreField::from_str("RB    ")),
+            field_key(&CoreField::from_str("RB    "))
+        );
+        assert_ne!(
+            field_key(&CoreField::from_str("RB    ")),
+            field_key(&CoreField::from_str("BR    "))
+        );
+        assert_ne!(
+            field_key(&CoreField::from_str(concat!("R     ", "B     "))),
+            field_key(&CoreField::from_str(concat!("B     ", "R     ")))
+        );
+    }
+
+    #[test]
+    fn test_incremental_columns() {
+        let evaluator = Evaluator::default();
+        let parent = CoreField::from_str(concat!(
+            "G.....", // 3
+            "GR..B.", // 2
+            "RRB.BB"  // 1
+        ));
+        let children = [
+            concat!(
+                "GY....", // 4
+                "GY....", // 3
+                "GR..B.", // 2
+                "RRB.BB"  // 1
+            ),
+            concat!(
+                "G.....", // 3
+                "GR.YB.", // 2
+                "RRBYBB"  // 1
+            ),
+            concat!(
+                "G....R", // 3
+                "GR..BR", // 2
+                "RRB.BB"  // 1
+            ),
+        ];
+
+        let mut cache = FeatureCache::new();
+        cache.set_parent(&parent);
+        for child in children {
+            let cf = CoreField::from_str(child);
+            let (_, columns) = cache.features(&evaluator, &cf);
+            assert_eq!(columns, column_features(&cf));
+        }
+        // 1 手前の盤面の分も数える
+        assert_eq!(cache.misses(), 4);
+
+        // 同じ盤面は計算し直さない
+        cache.features(&evaluator, &CoreField::from_str(children[0]));
+        assert_eq!((cache.hits(), cache.misses()), (1, 4));
+    }
+
+    #[test]
+    fn test_incremental_features() {
+        assert_eq!(columns_around(1, 1), 0b000011);
+        assert_eq!(columns_around(4, 3), 0b111111);
+        assert_eq!(columns_around(6, 3), 0b111000);
+
+        let evaluator = Evaluator {
+            pattern_orientation: Orientation::Both,
+            pattern_partial: true,
+            ..Evaluator::default()
+        }
+        .with_shapes(vec![ShapeTemplate::new(
+            "gtr_1",
+            50,
+            &["C A B _ _ _", "C C A B _ _", "A A B B _ _"],
+        )
+        .unwrap()
+        .with_partial(true)]);
+        let parent = CoreField::from_str(concat!(
+            "......", // 4
+            "GRB...", // 3
+            "GGRB.R", // 2
+            "RRBB.R"  // 1
+        ));
+        let children = [
+            // 右端だけ変わる（左側の形と連結は 1 手前の盤面のものを使う）
+            concat!(
+                "......", // 4
+                "GRB..Y", // 3
+                "GGRB.R", // 2
+                "RRBB.R"  // 1
+            ),
+            // 左側の連結が変わる
+            concat!(
+                "B.....", // 4
+                "GRB...", // 3
+                "GGRB.R", // 2
+                "RRBB.R"  // 1
+            ),
+            // 連鎖で消えて、右側の 2 列が空になる
+            concat!(
+                "......", // 4
+                "GRB...", // 3
+                "GGRB..", // 2
+                "RRBB.."  // 1
+            ),
+        ];
+
+        let mut cache = FeatureCache::new();
+        cache.set_parent(&parent);
+        for child in children {
+            let cf = CoreField::from_str(child);
+            let expected = evaluator.field_features(&cf, None);
+            let (features, _) = cache.features(&evaluator, &cf);
+            assert_eq!(features.connectivity, expected.connectivity);
+            assert_eq!(features.column_connectivity, expected.column_connectivity);
+            assert_eq!(features.unreachable_space, expected.unreachable_space);
+            assert_eq!(features.patterns, expected.patterns);
+            assert_eq!(features.shapes, expected.shapes);
+        }
+    }
+
+    #[test]
+    fn test_eviction() {
+        let evaluator = Evaluator::default();
+        let fields = ["R.....", "B.....", "Y.....", "G....."].map(CoreField::from_str);
+
+        // 1 世代に 2 盤面まで
+        let mut cache = FeatureCache::with_capacity(4);
+        cache.features(&evaluator, &fields[0]);
+        cache.features(&evaluator, &fields[1]);
+        // 新しい世代がいっぱいなので、0, 1 は古い世代に移る
+        cache.features(&evaluator, &fields[2]);
+        assert_eq!((cache.hits(), cache.misses()), (0, 3));
+        // 古い世代で見つかった 0 は、新しい世代に移る
+        cache.features(&evaluator, &fields[0]);
+        assert_eq!((cache.hits(), cache.misses()), (1, 3));
+        // 1 だけが古い世代と一緒に捨てられる
+        cache.features(&evaluator, &fields[3]);
+        cache.features(&evaluator, &fields[0]);
+        assert_eq!((cache.hits(), cache.misses()), (2, 4));
+        cache.features(&evaluator, &fields[1]);
+        assert_eq!((cache.hits(), cache.misses()), (2, 5));
+    }
+}
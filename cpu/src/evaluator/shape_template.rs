@@ -0,0 +1,692 @@
+//! 実行時に読み込める形のテンプレート
+//!
+//! `detect_shape!` と同じ書き方（`A`〜`E` と `_` を並べた行）の形を JSON や TOML から読み込んで、
+//! 重み付きで評価値に足せるようにする。新しい形を試すのに再コンパイルしなくて済む。
+//!
+//! `orientation` で左右反転した形も、`offsets` で中央寄りにずらした形も探せる。
+//! 組み込みの形も同じ仕組みで探す（向きは `Evaluator::pattern_orientation` で決める）。
+//! `partial` を指定すると、完成していなくても矛盾なく埋まっている割合だけ重みを足す。
+//!
+//! ```toml
+//! [[shapes]]
+//! name = "gtr"
+//! weight = 50
+//! rows = [
+//!     "C A B _ _ _",
+//!     "C C A B _ _",
+//!     "A A B B _ _",
+//! ]
+//! # 省略時は "left"（書いたとおり、1 列目から）
+//! orientation = "both"
+//! # 省略時は [0]
+//! offsets = [0]
+//! # 省略時は false（完成したときだけ足す）
+//! partial = true
+//! ```
+
+use std::{cmp::Reverse, error::Error, fmt, fs};
+
+use puyoai::{
+    color::PuyoColor,
+    field::{self, CoreField},
+};
+use serde::{Deserialize, Serialize};
+
+use super::feature_cache::columns_around;
+
+/// なんでもOKを表すトークン（`detect_shape!` の `_` と同じ値）
+const ANY: u8 = 9;
+
+/// どちら向きの形を探すか
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Orientation {
+    /// 書いたとおり（左端から）
+    #[default]
+    Left,
+    /// 左右反転（右端から）
+    Right,
+    /// どちらでもいい
+    Both,
+}
+
+/// 形が見つかった向きと位置
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ShapeMatch {
+    /// 左右反転した形が見つかったか
+    pub mirrored: bool,
+    /// 端から何列ずらした位置で見つかったか
+    pub offset: usize,
+}
+
+impl fmt::Display for ShapeMatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}{}",
+            if self.mirrored { "right" } else { "left" },
+            if self.offset > 0 {
+                format!("+{}", self.offset)
+            } else {
+                "".to_owned()
+            }
+        )
+    }
+}
+
+/// 途中までできている形の、埋まり具合
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PartialMatch {
+    pub shape_match: ShapeMatch,
+    /// 色の対応関係が合っていて、ぷよが置かれているマスの数
+    pub filled: usize,
+    /// `A`〜`E` のマスの数
+    pub total: usize,
+    /// 色の対応関係が合わない・トークンが違うのに隣が同じ色・おじゃまが置かれている、の数
+    pub conflicts: usize,
+}
+
+impl PartialMatch {
+    /// 埋まっている割合（`A`〜`E` のマスが無ければ 1）
+    pub fn ratio(&self) -> f32 {
+        if self.total == 0 {
+            return 1.0;
+        }
+        self.filled as f32 / self.total as f32
+    }
+}
+
+impl fmt::Display for PartialMatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}, {}/{}", self.shape_match, self.filled, self.total)?;
+        if self.conflicts > 0 {
+            write!(f, ", {} conflicts", self.conflicts)?;
+        }
+        Ok(())
+    }
+}
+
+/// `ShapeTemplate::feature` で見つかった形
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ShapeFound {
+    Complete(ShapeMatch),
+    Partial(PartialMatch),
+}
+
+impl fmt::Display for ShapeFound {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ShapeFound::Complete(shape_match) => write!(f, "{}", shape_match),
+            ShapeFound::Partial(partial_match) => write!(f, "{}", partial_match),
+        }
+    }
+}
+
+/// 名前と重みの付いた形のテンプレート
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "RawShapeTemplate", into = "RawShapeTemplate")]
+pub struct ShapeTemplate {
+    pub name: String,
+    pub weight: i32,
+    /// 上の行から順に、各マスのトークン（`A`〜`E` は 0〜4、`_` は `ANY`）
+    rows: Vec<Vec<u8>>,
+    orientation: Orientation,
+    /// 端から何列ずらした位置を探すか
+    offsets: Vec<usize>,
+    /// 完成していなくても、埋まっている割合だけ重みを足すか
+    partial: bool,
+}
+
+/// ファイル上の表現（行は `"C A B _ _ _"` のような文字列、空白は無視する）
+#[derive(Clone, Serialize, Deserialize)]
+struct RawShapeTemplate {
+    name: String,
+    weight: i32,
+    rows: Vec<String>,
+    #[serde(default)]
+    orientation: Orientation,
+    #[serde(default = "default_offsets")]
+    offsets: Vec<usize>,
+    #[serde(default)]
+    partial: bool,
+}
+
+fn default_offsets() -> Vec<usize> {
+    vec![0]
+}
+
+impl ShapeTemplate {
+    /// 上の行から順に並べた行から作る（書いたとおりの向きで、左端だけを探す）
+    pub fn new(name: &str, weight: i32, rows: &[&str]) -> Result<Self, String> {
+        if rows.is_empty() || rows.len() > field::HEIGHT {
+            return Err(format!(
+                "{}: the number of rows must be 1 to {}",
+                name,
+                field::HEIGHT
+            ));
+        }
+
+        let mut tokens = vec![];
+        for row in rows {
+            let row: Vec<u8> = row
+                .chars()
+                .filter(|c| !c.is_whitespace())
+                .map(|c| match c {
+                    'A'..='E' => Ok(c as u8 - b'A'),
+                    '_' => Ok(ANY),
+                    _ => Err(format!("{}: invalid token `{}` (use A-E or _)", name, c)),
+                })
+                .collect::<Result<_, _>>()?;
+            if row.is_empty() || row.len() > field::WIDTH {
+                return Err(format!(
+                    "{}: each row must have 1 to {} tokens",
+                    name,
+                    field::WIDTH
+                ));
+            }
+            tokens.push(row);
+        }
+
+        Ok(ShapeTemplate {
+            name: name.to_owned(),
+            weight,
+            rows: tokens,
+            orientation: Orientation::default(),
+            offsets: default_offsets(),
+            partial: false,
+        })
+    }
+
+    pub fn with_partial(mut self, partial: bool) -> Self {
+        self.partial = partial;
+        self
+    }
+
+    pub fn with_orientation(mut self, orientation: Orientation) -> Self {
+        self.orientation = orientation;
+        self
+    }
+
+    /// 端から何列ずらした位置を探すか（はみ出すならエラー）
+    pub fn with_offsets(mut self, offsets: Vec<usize>) -> Result<Self, String> {
+        let width = self.rows.iter().map(|row| row.len()).max().unwrap_or(0);
+        if offsets.is_empty() || offsets.iter().any(|&offset| offset + width > field::WIDTH) {
+            return Err(format!(
+                "{}: offsets must be non-empty and keep the shape within {} columns",
+                self.name,
+                field::WIDTH
+            ));
+        }
+        self.offsets = offsets;
+        Ok(self)
+    }
+
+    pub fn orientation(&self) -> Orientation {
+        self.orientation
+    }
+
+    pub fn offsets(&self) -> &[usize] {
+        &self.offsets
+    }
+
+    pub fn partial(&self) -> bool {
+        self.partial
+    }
+
+    /// 盤面のどこかがこの形になっているか
+    pub fn matches(&self, cf: &CoreField) -> bool {
+        self.find_match(cf).is_some()
+    }
+
+    /// この形になっている向きと位置を探す（ずらす列の少ない方、同じなら書いたとおりの向きを優先）
+    pub fn find_match(&self, cf: &CoreField) -> Option<ShapeMatch> {
+        self.find_match_in(cf, self.orientation)
+    }
+
+    /// `orientation` の向きで `find_match` する（組み込みの形のように、向きを評価関数の側で決めるとき用）
+    pub fn find_match_in(&self, cf: &CoreField, orientation: Orientation) -> Option<ShapeMatch> {
+        self.candidates(orientation)
+            .find(|&shape_match| self.matches_at(cf, shape_match))
+    }
+
+    /// 一番できあがっている向きと位置での埋まり具合
+    ///
+    /// 矛盾の無いものを優先して、埋まっているマスが多い方、同じなら矛盾の少ない方を選ぶ。
+    pub fn partial_match(&self, cf: &CoreField) -> PartialMatch {
+        self.partial_match_in(cf, self.orientation)
+    }
+
+    /// `orientation` の向きで `partial_match` する
+    pub fn partial_match_in(&self, cf: &CoreField, orientation: Orientation) -> PartialMatch {
+        self.candidates(orientation)
+            .map(|shape_match| self.partial_match_at(cf, shape_match))
+            .min_by_key(|m| (m.conflicts > 0, Reverse(m.filled), m.conflicts))
+            .unwrap()
+    }
+
+    /// 評価値に使う特徴量と、見つかった向きや埋まり具合
+    ///
+    /// 完成していれば 1。`partial` なら、矛盾なく途中までできていればその割合。
+    pub fn feature(&self, cf: &CoreField) -> (f32, Option<ShapeFound>) {
+        self.feature_in(cf, self.orientation, self.partial)
+    }
+
+    /// `orientation` の向きで、`partial` なら途中までできていても `feature` と同じように計算する
+    pub fn feature_in(
+        &self,
+        cf: &CoreField,
+        orientation: Orientation,
+        partial: bool,
+    ) -> (f32, Option<ShapeFound>) {
+        if let Some(shape_match) = self.find_match_in(cf, orientation) {
+            return (1.0, Some(ShapeFound::Complete(shape_match)));
+        }
+        if partial {
+            let partial_match = self.partial_match_in(cf, orientation);
+            if partial_match.conflicts == 0 && partial_match.filled > 0 {
+                return (
+                    partial_match.ratio(),
+                    Some(ShapeFound::Partial(partial_match)),
+                );
+            }
+        }
+        (0.0, None)
+    }
+
+    /// 探すときに読む列（`FeatureCache` が、中身の変わっていない列だけなら計算し直さないのに使う）
+    pub(crate) fn columns(&self) -> u32 {
+        self.columns_in(self.orientation)
+    }
+
+    /// `orientation` の向きで探すときに読む列（x 列目が `1 << (x - 1)`）
+    ///
+    /// `A`〜`E` のマスの列と、隣り合うマスの色を比べるためにその両隣の列も読む。
+    pub(crate) fn columns_in(&self, orientation: Orientation) -> u32 {
+        let mut columns = 0;
+        for shape_match in self.candidates(orientation) {
+            for row in &self.rows {
+                for (j, _) in row.iter().enumerate().filter(|&(_, &token)| token != ANY) {
+                    columns |= columns_around(Self::column(shape_match, j), 1);
+                }
+            }
+        }
+        columns
+    }
+
+    /// 探す向きと位置（ずらす列の少ない方、同じなら書いたとおりの向きが先）
+    fn candidates(&self, orientation: Orientation) -> impl Iterator<Item = ShapeMatch> + '_ {
+        let mirrors: &'static [bool] = match orientation {
+            Orientation::Left => &[false],
+            Orientation::Right => &[true],
+            Orientation::Both => &[false, true],
+        };
+        self.offsets.iter().flat_map(move |&offset| {
+            mirrors
+                .iter()
+                .map(move |&mirrored| ShapeMatch { mirrored, offset })
+        })
+    }
+
+    /// 向きと位置に対応する盤面の列
+    fn column(shape_match: ShapeMatch, j: usize) -> usize {
+        if shape_match.mirrored {
+            field::WIDTH - shape_match.offset - j
+        } else {
+            shape_match.offset + j + 1
+        }
+    }
+
+    /// 指定した向きと位置がこの形になっているか（`detect_shape!` で作った関数と同じ判定）
+    ///
+    /// 左右反転したときは、形の中での「左」が盤面の右になる。形の外側のマスは見ない。
+    fn matches_at(&self, cf: &CoreField, shape_match: ShapeMatch) -> bool {
+        let column = |j: usize| Self::column(shape_match, j);
+
+        // corr: `A` などと実際の色との対応関係
+        let mut corr = [PuyoColor::EMPTY; 5];
+        // 直前（上）のトークン
+        let mut prv_row = [ANY; field::WIDTH];
+
+        for (i, row) in self.rows.iter().enumerate() {
+            let y = self.rows.len() - i;
+            // 直前（形の中で左）のトークン
+            let mut prv = ANY;
+            for (j, &token) in row.iter().enumerate() {
+                let x = column(j);
+                // `_`（なんでもOK）じゃなかったら...
+                if token != ANY {
+                    let color = &mut corr[token as usize];
+                    if *color == PuyoColor::EMPTY {
+                        // まだその色が使われてない
+                        *color = cf.color(x, y);
+                    } else if *color != cf.color(x, y) {
+                        // 色の対応関係が合っていない
+                        return false;
+                    }
+                }
+                if j > 0 && prv != token && cf.color(column(j - 1), y) == cf.color(x, y) {
+                    // トークンが違うのに左右で同じ色
+                    return false;
+                }
+                if prv_row[j] != token && cf.color(x, y + 1) == cf.color(x, y) {
+                    // トークンが違うのに上下で同じ色
+                    return false;
+                }
+
+                prv_row[j] = token;
+                prv = token;
+            }
+        }
+        true
+    }
+
+    /// 指定した向きと位置での埋まり具合（判定は `matches_at` と同じで、空のマスは飛ばす）
+    fn partial_match_at(&self, cf: &CoreField, shape_match: ShapeMatch) -> PartialMatch {
+        let column = |j: usize| Self::column(shape_match, j);
+
+        let mut partial_match = PartialMatch {
+            shape_match,
+            filled: 0,
+            total: 0,
+            conflicts: 0,
+        };
+        let mut corr = [PuyoColor::EMPTY; 5];
+        let mut prv_row = [ANY; field::WIDTH];
+
+        for (i, row) in self.rows.iter().enumerate() {
+            let y = self.rows.len() - i;
+            let mut prv = ANY;
+            for (j, &token) in row.iter().enumerate() {
+                let x = column(j);
+                let color = cf.color(x, y);
+                if token != ANY {
+                    partial_match.total += 1;
+                    if color == PuyoColor::EMPTY {
+                        // まだ置かれていない
+                    } else if !color.is_normal_color() {
+                        partial_match.conflicts += 1;
+                    } else if corr[token as usize] == PuyoColor::EMPTY
+                        || corr[token as usize] == color
+                    {
+                        corr[token as usize] = color;
+                        partial_match.filled += 1;
+                    } else {
+                        partial_match.conflicts += 1;
+                    }
+                }
+                if color != PuyoColor::EMPTY {
+                    if j > 0 && prv != token && cf.color(column(j - 1), y) == color {
+                        partial_match.conflicts += 1;
+                    }
+                    if prv_row[j] != token && cf.color(x, y + 1) == color {
+                        partial_match.conflicts += 1;
+                    }
+                }
+
+                prv_row[j] = token;
+                prv = token;
+            }
+        }
+        partial_match
+    }
+}
+
+impl TryFrom<RawShapeTemplate> for ShapeTemplate {
+    type Error = String;
+
+    fn try_from(raw: RawShapeTemplate) -> Result<Self, Self::Error> {
+        let rows: Vec<&str> = raw.rows.iter().map(|row| row.as_str()).collect();
+        Ok(ShapeTemplate::new(&raw.name, raw.weight, &rows)?
+            .with_orientation(raw.orientation)
+            .with_offsets(raw.offsets)?
+            .with_partial(raw.partial))
+    }
+}
+
+impl From<ShapeTemplate> for RawShapeTemplate {
+    fn from(template: ShapeTemplate) -> Self {
+        let rows = template.rows.iter().map(|row| row_to_string(row)).collect();
+        RawShapeTemplate {
+            name: template.name,
+            weight: template.weight,
+            rows,
+            orientation: template.orientation,
+            offsets: template.offsets,
+            partial: template.partial,
+        }
+    }
+}
+
+fn row_to_string(row: &[u8]) -> String {
+    row.iter()
+        .map(|&token| match token {
+            ANY => "_".to_owned(),
+            _ => ((b'A' + token) as char).to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+impl fmt::Display for ShapeTemplate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{} (weight: {})", self.name, self.weight)?;
+        for row in &self.rows {
+            writeln!(f, "[{}]", row_to_string(row))?;
+        }
+        Ok(())
+    }
+}
+
+/// テンプレートのファイル（`shapes` にテンプレートを並べる）
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ShapeSet {
+    pub shapes: Vec<ShapeTemplate>,
+}
+
+impl ShapeSet {
+    /// JSON か TOML のファイルから読む（拡張子が `.toml` なら TOML、それ以外は JSON）
+    pub fn from_file(path: &str) -> Result<Self, Box<dyn Error>> {
+        let s = fs::read_to_string(path)?;
+        if path.ends_with(".toml") {
+            Ok(toml::from_str(&s)?)
+        } else {
+            Ok(serde_json::from_str(&s)?)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evaluator::detect_shape::*;
+
+    #[test]
+    fn test_matches_like_detect_shape() {
+        let template = ShapeTemplate::new("gtr_1", 50, gtr_1::ROWS).unwrap();
+
+        for field in [
+            concat!(
+                "      ", // 4
+                "GRB   ", // 3
+                "GGRB  ", // 2
+                "RRBB  "  // 1
+            ),
+            concat!(
+                "YYB   ", // 4
+                "GRB   ", // 3
+                "GGRB  ", // 2
+                "RRBB  "  // 1
+            ),
+            concat!(
+                "YYY   ", // 4
+                "GRB   ", // 3
+                "GGRB  ", // 2
+                "RRBBB "  // 1
+            ),
+            concat!(
+                "      ", // 4
+                "GRB   ", // 3
+                "GGRBB ", // 2
+                "RRB   "  // 1
+            ),
+            concat!(
+                "   BRG", // 3
+                "  BRGG", // 2
+                "  BBRR"  // 1
+            ),
+        ] {
+            let cf = CoreField::from_str(field);
+            assert_eq!(template.matches(&cf), gtr_1(&cf));
+        }
+    }
+
+    #[test]
+    fn test_find_match() {
+        let template =
+            ShapeTemplate::new("gtr_1", 50, &["C A B _ _ _", "C C A B _ _", "A A B B _ _"])
+                .unwrap();
+        let right = CoreField::from_str(concat!(
+            "   BRG", // 3
+            "  BRGG", // 2
+            "  BBRR"  // 1
+        ));
+        assert_eq!(template.find_match(&right), None);
+        // 向きは呼び出し側でも決められる
+        assert_eq!(
+            template.find_match_in(&right, Orientation::Both),
+            Some(ShapeMatch {
+                mirrored: true,
+                offset: 0
+            })
+        );
+
+        let template = template.with_orientation(Orientation::Right);
+        assert_eq!(
+            template.find_match(&right),
+            Some(ShapeMatch {
+                mirrored: true,
+                offset: 0
+            })
+        );
+        assert_eq!(template.find_match(&right).unwrap().to_string(), "right");
+
+        // 読む列は `A`〜`E` のマスの列とその両隣だけ
+        assert_eq!(template.columns_in(Orientation::Left), 0b011111);
+        assert_eq!(template.columns(), 0b111110);
+
+        // 1 列ずらした位置は `offsets` に入れたときだけ探す
+        let template = ShapeTemplate::new("gtr_base_4", 20, &["B B A", "A A _"]).unwrap();
+        let shifted = CoreField::from_str(concat!(
+            " BBA  ", // 2
+            " AA   "  // 1
+        ));
+        assert_eq!(template.find_match(&shifted), None);
+        let template = template
+            .with_orientation(Orientation::Both)
+            .with_offsets(vec![0, 1])
+            .unwrap();
+        assert_eq!(
+            template.find_match(&shifted),
+            Some(ShapeMatch {
+                mirrored: false,
+                offset: 1
+            })
+        );
+        assert_eq!(template.find_match(&shifted).unwrap().to_string(), "left+1");
+        assert!(template.clone().with_offsets(vec![4]).is_err());
+        assert!(template.with_offsets(vec![]).is_err());
+    }
+
+    #[test]
+    fn test_partial_match() {
+        let template =
+            ShapeTemplate::new("gtr_1", 50, &["C A B _ _ _", "C C A B _ _", "A A B B _ _"])
+                .unwrap();
+
+        // あと 1 個で完成
+        let almost = CoreField::from_str(concat!(
+            "GRB   ", // 3
+            "GGR   ", // 2
+            "RRBB  "  // 1
+        ));
+        assert!(!template.matches(&almost));
+        let partial_match = template.partial_match(&almost);
+        assert_eq!((partial_match.filled, partial_match.total), (10, 11));
+        assert_eq!(partial_match.conflicts, 0);
+        assert_eq!(partial_match.to_string(), "left, 10/11");
+        // `partial` でなければ完成していないと 0
+        assert_eq!(template.feature(&almost), (0.0, None));
+        let template = template.with_partial(true);
+        assert_eq!(
+            template.feature(&almost),
+            (10.0 / 11.0, Some(ShapeFound::Partial(partial_match)))
+        );
+
+        // 色の対応関係が合わない・トークンが違うのに隣が同じ色なら、途中でも足さない
+        let broken = CoreField::from_str(concat!(
+            "GRB   ", // 3
+            "GGRR  ", // 2
+            "RRBB  "  // 1
+        ));
+        let partial_match = template.partial_match(&broken);
+        assert_eq!(partial_match.conflicts, 2);
+        assert_eq!(partial_match.to_string(), "left, 10/11, 2 conflicts");
+        assert_eq!(template.feature(&broken), (0.0, None));
+
+        assert_eq!(template.partial_match(&CoreField::new()).filled, 0);
+        assert_eq!(template.feature(&CoreField::new()), (0.0, None));
+    }
+
+    #[test]
+    fn test_parse() {
+        // 空白はあってもなくてもいい
+        let template = ShapeTemplate::new("gtr_base_2", 20, &["BB", "A A _"]).unwrap();
+        assert_eq!(
+            template.to_string(),
+            "gtr_base_2 (weight: 20)\n[B B]\n[A A _]\n"
+        );
+
+        assert!(ShapeTemplate::new("empty", 0, &[]).is_err());
+        assert!(ShapeTemplate::new("invalid", 0, &["A F"]).is_err());
+        assert!(ShapeTemplate::new("too_wide", 0, &["A A A A A A A"]).is_err());
+
+        let json =
+            r#"{"shapes": [{"name": "gtr_base_2", "weight": 20, "rows": ["B B", "A A _"]}]}"#;
+        let shape_set: ShapeSet = serde_json::from_str(json).unwrap();
+        assert_eq!(shape_set.shapes, vec![template.clone()]);
+        // 書き出して読み直しても同じ
+        let json = serde_json::to_string(&shape_set).unwrap();
+        assert_eq!(serde_json::from_str::<ShapeSet>(&json).unwrap(), shape_set);
+
+        let toml = r#"
+            [[shapes]]
+            name = "gtr_base_2"
+            weight = 20
+            rows = ["B B", "A A _"]
+        "#;
+        assert_eq!(toml::from_str::<ShapeSet>(toml).unwrap(), shape_set);
+
+        let json = r#"{"shapes": [{"name": "invalid", "weight": 0, "rows": ["X"]}]}"#;
+        assert!(serde_json::from_str::<ShapeSet>(json).is_err());
+
+        let toml = r#"
+            [[shapes]]
+            name = "gtr_base_2"
+            weight = 20
+            rows = ["B B", "A A _"]
+            orientation = "both"
+            offsets = [0, 3]
+        "#;
+        let shape_set = toml::from_str::<ShapeSet>(toml).unwrap();
+        assert_eq!(shape_set.shapes[0].orientation(), Orientation::Both);
+        assert_eq!(shape_set.shapes[0].offsets(), &[0, 3]);
+        let toml = r#"
+            [[shapes]]
+            name = "gtr_base_2"
+            weight = 20
+            rows = ["B B", "A A _"]
+            offsets = [4]
+        "#;
+        assert!(toml::from_str::<ShapeSet>(toml).is_err());
+    }
+}
@@ -412,7 +412,9 @@ export const getStaticProps: GetStaticProps = async (context) => {
       infos: json_data.json_matches.map((game, index) => {
         let last_json_event = game.json_events.slice(-1)[0];
         return {
-          winner: game.won_1p ? '1P' : '2P',
+          winner:
+            (game.draw ? 'Draw' : game.won_1p ? '1P' : '2P') +
+            (game.swapped ? ' (swapped)' : ''),
           match_index: index,
           frames: last_json_event.frame,
           score_1p: last_json_event.json_state_1p.score,
@@ -53,6 +53,7 @@ export type JsonState = {
   ojama_fixed: number;
   ojama_ongoing: number;
   current_chain: number;
+  zenkeshi?: boolean; // 全消しボーナスを持っているか
   board?: Board; // `field` を変換した結果
 };
 export type JsonEvent = {
@@ -62,6 +63,8 @@ export type JsonEvent = {
 };
 export type JsonMatch = {
   won_1p: boolean;
+  draw?: boolean;
+  swapped?: boolean; // 先後を入れ替えた試合（`won_1p` は席から見た勝敗）
   tumos: KumiPuyo[];
   json_events: JsonEvent[];
 };
@@ -69,6 +72,9 @@ export type JsonData2P = {
   date: string;
   win_count_1p: number;
   win_count_2p: number;
+  draw_count?: number;
   visible_tumos: number;
+  pentanomial?: { counts: number[] } | null; // 先後を入れ替えて対戦させたときの、組ごとの結果
+  sprt?: { llr: number; result: string } | null; // SPRT で打ち切ったときの LLR と結果
   json_matches: JsonMatch[];
 };
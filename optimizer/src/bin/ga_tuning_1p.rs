@@ -1,20 +1,20 @@
 use std::{
-    collections::VecDeque,
     fs::create_dir_all,
     io::{stdout, Write},
-    sync::{mpsc::channel, Arc, Mutex},
+    sync::atomic::{AtomicUsize, Ordering},
     time::Instant,
 };
 
 use chrono::Utc;
-use clap::Parser;
+use clap::{error::ErrorKind, CommandFactory, Parser};
 use cpu::{
-    bot::{BeamSearchAI, AI},
-    evaluator::Evaluator,
+    bot::{worker_pool::init_worker_pool, BeamSearchAI, Rules, AI},
+    evaluator::{Evaluator, ShapeSet, ShapeTemplate},
 };
 use ghoti_optimizer::Mutateable;
 use logger::{Logger, NullLogger};
 use rand::prelude::*;
+use rayon::{prelude::*, ThreadPoolBuilder};
 use serde::{Deserialize, Serialize};
 use simulator::{haipuyo_detector::TUMO_PATTERN, simulate_1p, simulate_1p::SimulateResult1P};
 
@@ -50,22 +50,49 @@ struct Opts {
     #[clap(long, default_value = "5")]
     elite_size: usize,
 
-    /// 何スレッドでシミュレーションするか
+    /// 同時に何試合シミュレーションするか
     /// （`visible_tumos` を `depth` と同じにしたなら増やすべき）
     #[clap(long, default_value = "1")]
-    parallel: usize,
+    games: usize,
+
+    /// 何スレッドで AI に思考させるか（モンテカルロの各試行を走らせる共有のプールのサイズ、省略時は CPU のコア数）
+    #[clap(long)]
+    parallel: Option<usize>,
+
+    /// 初期集団の個体に持たせる形のテンプレートのファイル（TOML か JSON、`pop.json` を読み込んだときは使わない）
+    ///
+    /// 重みは個体ごとに乱数で決め、世代を重ねるごとに交叉と突然変異でチューニングする。
+    #[clap(long)]
+    shapes: Option<String>,
 }
 
 fn main() -> Result<(), std::io::Error> {
     let opts = Opts::parse();
     assert!(opts.elite_size < opts.population_size);
+    init_worker_pool(opts.parallel);
+    // 試合ごとに 1 スレッド（AI の思考は共有のワーカープールで動く）
+    let game_pool = ThreadPoolBuilder::new()
+        .num_threads(opts.games.max(1))
+        .thread_name(|i| format!("ghoti-game-{}", i))
+        .build()
+        .unwrap();
+
+    let shapes = match &opts.shapes {
+        Some(path) => match ShapeSet::from_file(path) {
+            Ok(shape_set) => shape_set.shapes,
+            Err(e) => Opts::command()
+                .error(ErrorKind::Io, format!("{}: {}", path, e))
+                .exit(),
+        },
+        None => vec![],
+    };
 
     let mut population = match std::fs::File::open("optimizer/logs/ga_tuning_1p/pop.json") {
         Ok(file) => serde_json::from_reader(file).unwrap_or_else(|e| {
             eprintln!("pop.json contained invalid data: {}", e);
-            new_population(opts.population_size)
+            new_population(opts.population_size, &shapes)
         }),
-        Err(_) => new_population::<Evaluator>(opts.population_size),
+        Err(_) => new_population::<Evaluator>(opts.population_size, &shapes),
     };
 
     // とこぷよのスコアを計算
@@ -73,58 +100,40 @@ fn main() -> Result<(), std::io::Error> {
         ((sim_res.score as f64).powf(1.1f64)) as usize / sim_res.json_decisions.len()
     };
 
-    // マルチスレッドでシミュレーション
-    let matchups = Arc::new(Mutex::new((true, VecDeque::new())));
-    let (send, game_results) = channel();
-    for _ in 0..opts.parallel {
-        let matchups = matchups.clone();
-        let send = send.clone();
-        std::thread::spawn(move || loop {
-            // (AIのindex, AIのEvaluator, 何番の配ぷよから使うか)
-            let (ai_index, ai_eval, haipuyo_margin) = {
-                let (active, ref mut queue) = *matchups.lock().unwrap();
-                if !active {
-                    break;
-                }
-                match queue.pop_front() {
-                    Some(v) => v,
-                    None => continue,
-                }
-            };
-
-            let mut logger: Box<dyn Logger> = Box::new(NullLogger::new("", None).unwrap());
-            let ai: Box<dyn AI> = Box::new(BeamSearchAI::new_customize(ai_eval));
-
-            let mut res = 0;
-            let mut best: Option<SimulateResult1P> = None;
-            for i in 0..opts.simulate_count {
-                let simulate_result_1p = simulate_1p(
-                    &mut logger,
-                    &ai,
-                    opts.visible_tumos,
-                    opts.max_tumos,
-                    // FIXME: 序盤数手が同じになってしまう
-                    Some((haipuyo_margin + i) % TUMO_PATTERN),
-                    Some(opts.required_chain_score),
-                )
-                .unwrap();
+    // 1 個体ぶんのとこぷよを、`haipuyo_margin` 番目の配ぷよから `simulate_count` 回行う
+    let simulate = |ai_eval: Evaluator, haipuyo_margin: usize| {
+        let mut logger: Box<dyn Logger> = Box::new(NullLogger::new("", None).unwrap());
+        let ai: Box<dyn AI> = Box::new(BeamSearchAI::new_customize(ai_eval));
+
+        let mut res = 0;
+        let mut best: Option<SimulateResult1P> = None;
+        for i in 0..opts.simulate_count {
+            let simulate_result_1p = simulate_1p(
+                &mut logger,
+                &ai,
+                opts.visible_tumos,
+                opts.max_tumos,
+                // FIXME: 序盤数手が同じになってしまう
+                Some((haipuyo_margin + i) % TUMO_PATTERN),
+                Some(opts.required_chain_score),
+                Rules::default(),
+            )
+            .unwrap();
 
-                let score = calc_score(&simulate_result_1p);
-                res += score;
+            let score = calc_score(&simulate_result_1p);
+            res += score;
 
-                if let Some(sim) = best.clone() {
-                    if score > calc_score(&sim) {
-                        best = Some(simulate_result_1p);
-                    }
-                } else {
+            if let Some(sim) = best.clone() {
+                if score > calc_score(&sim) {
                     best = Some(simulate_result_1p);
                 }
+            } else {
+                best = Some(simulate_result_1p);
             }
+        }
 
-            send.send(Some((ai_index, res / opts.simulate_count, best)))
-                .ok();
-        });
-    }
+        (res / opts.simulate_count, best)
+    };
 
     loop {
         assert_eq!(population.members.len(), opts.population_size);
@@ -136,17 +145,6 @@ fn main() -> Result<(), std::io::Error> {
 
         let haipuyo_margin =
             ((population.generation / opts.simulate_count) * opts.simulate_count) % TUMO_PATTERN;
-        let mut count = 0;
-        {
-            let mut matchups = matchups.lock().unwrap();
-            for i in 0..opts.population_size {
-                matchups
-                    .1
-                    .push_back((i, population.members[i].clone(), haipuyo_margin));
-                count += 1;
-            }
-        }
-
         println!(
             "> Gen {} (haipuyo-margin: {})",
             population.generation, haipuyo_margin
@@ -157,14 +155,24 @@ fn main() -> Result<(), std::io::Error> {
         for i in 0..opts.population_size {
             results.push((i, 0_i32));
         }
+        // 同時に `games` 試合までシミュレーション（AI のモンテカルロは共有のワーカープールで動く）
         // TODO: プログレスバーのパッケージを使う
-        for i in 0..count {
-            if let Some((ai_index, res, sim_res)) = game_results.recv().unwrap() {
-                results[ai_index].1 += res as i32;
-                simulate_results[ai_index] = sim_res;
-            }
-            print!("{} ", i + 1);
-            stdout().flush().unwrap();
+        let finished = AtomicUsize::new(0);
+        let game_results: Vec<(usize, Option<SimulateResult1P>)> = game_pool.install(|| {
+            population
+                .members
+                .par_iter()
+                .map(|ai_eval| {
+                    let game_result = simulate(ai_eval.clone(), haipuyo_margin);
+                    print!("{} ", finished.fetch_add(1, Ordering::Relaxed) + 1);
+                    stdout().flush().unwrap();
+                    game_result
+                })
+                .collect()
+        });
+        for (ai_index, (res, sim_res)) in game_results.into_iter().enumerate() {
+            results[ai_index].1 += res as i32;
+            simulate_results[ai_index] = sim_res;
         }
         println!();
 
@@ -264,9 +272,6 @@ fn main() -> Result<(), std::io::Error> {
         population = new_population;
     }
 
-    // シミュレーション用のスレッドをcloseする
-    matchups.lock().unwrap().0 = false;
-
     Ok(())
 }
 
@@ -276,11 +281,14 @@ struct Population<E: Mutateable> {
     members: Vec<E>,
 }
 
-fn new_population<E: Mutateable>(population_size: usize) -> Population<E> {
+fn new_population<E: Mutateable>(
+    population_size: usize,
+    shapes: &[ShapeTemplate],
+) -> Population<E> {
     let mut members = vec![];
-    members.push(E::default());
+    members.push(E::default_with_shapes(shapes));
     for num in 0..(population_size - 1) {
-        members.push(E::generate(format!("Gen 0 #{:>02}", num)));
+        members.push(E::generate(format!("Gen 0 #{:>02}", num), shapes));
     }
     Population {
         generation: 0,
@@ -1,22 +1,24 @@
 use std::{
-    collections::VecDeque,
     fs::create_dir_all,
     io::{stdout, Write},
-    sync::{mpsc::channel, Arc, Mutex},
+    sync::atomic::{AtomicUsize, Ordering},
     time::Instant,
 };
 
 use chrono::Utc;
-use clap::Parser;
+use clap::{error::ErrorKind, CommandFactory, Parser};
 use cpu::{
-    bot::{BeamSearchAI, AI},
-    evaluator::Evaluator,
+    bot::{worker_pool::init_worker_pool, BeamSearchAI, MarginTime, Rules, AI},
+    evaluator::{Evaluator, ShapeSet, ShapeTemplate},
 };
 use ghoti_optimizer::Mutateable;
 use logger::{Logger, NullLogger};
 use rand::prelude::*;
+use rayon::{prelude::*, ThreadPoolBuilder};
 use serde::{Deserialize, Serialize};
-use simulator::{haipuyo_detector::TUMO_PATTERN, simulate_2p, simulate_2p::SimulateResult2P};
+use simulator::{
+    haipuyo_detector::TUMO_PATTERN, simulate_2p, simulate_2p::SimulateResult2P, simulate_2p_paired,
+};
 
 #[derive(Parser)]
 #[clap(
@@ -30,6 +32,12 @@ struct Opts {
     #[clap(long, default_value = "50")]
     win_goal: usize,
 
+    /// 指定すると、同じ配ぷよで先後を入れ替えた 2 試合を 1 組として、この組数だけ対戦させる（`win_goal` は使わない）
+    ///
+    /// 配ぷよの運による偏りが打ち消し合うので、先取より少ない試合数で個体の差が分かる。
+    #[clap(long)]
+    pairs: Option<usize>,
+
     /// AI に何手読みさせるか
     #[clap(long, default_value = "10")]
     visible_tumos: usize,
@@ -42,57 +50,106 @@ struct Opts {
     #[clap(long, default_value = "5")]
     elite_size: usize,
 
-    /// 何スレッドでシミュレーションするか
+    /// 同時に何試合シミュレーションするか
     #[clap(long, default_value = "12")]
-    parallel: usize,
+    games: usize,
+
+    /// 何スレッドで AI に思考させるか（モンテカルロの各試行を走らせる共有のプールのサイズ、省略時は CPU のコア数）
+    #[clap(long)]
+    parallel: Option<usize>,
+
+    /// ルールを書いた JSON ファイル（省略時はぷよぷよ通のルール）
+    #[clap(long)]
+    rules: Option<String>,
+
+    /// マージンタイムが始まるフレーム数（指定するとルールのマージンタイムを上書きする）
+    #[clap(long)]
+    margin_time_start: Option<usize>,
+
+    /// マージンタイム中に、おじゃまぷよのレートが下がる間隔（フレーム数）
+    #[clap(long, default_value = "960")]
+    margin_time_interval: usize,
+
+    /// 初期集団の個体に持たせる形のテンプレートのファイル（TOML か JSON、`pop.json` を読み込んだときは使わない）
+    ///
+    /// 重みは個体ごとに乱数で決め、世代を重ねるごとに交叉と突然変異でチューニングする。
+    #[clap(long)]
+    shapes: Option<String>,
 }
 
 fn main() -> Result<(), std::io::Error> {
     let opts = Opts::parse();
     assert!(opts.elite_size < opts.population_size);
+    init_worker_pool(opts.parallel);
+    // 試合ごとに 1 スレッド（AI の思考は共有のワーカープールで動く）
+    let game_pool = ThreadPoolBuilder::new()
+        .num_threads(opts.games.max(1))
+        .thread_name(|i| format!("ghoti-game-{}", i))
+        .build()
+        .unwrap();
+
+    let mut rules = match &opts.rules {
+        Some(path) => match Rules::from_json_file(path) {
+            Ok(rules) => rules,
+            Err(e) => Opts::command()
+                .error(ErrorKind::ValueValidation, format!("{}: {}", path, e))
+                .exit(),
+        },
+        None => Rules::default(),
+    };
+    if let Some(start_frame) = opts.margin_time_start {
+        rules.margin_time = Some(MarginTime::new(start_frame, opts.margin_time_interval));
+    }
+    if let Err(e) = rules.validate() {
+        Opts::command().error(ErrorKind::ValueValidation, e).exit();
+    }
+    let shapes = match &opts.shapes {
+        Some(path) => match ShapeSet::from_file(path) {
+            Ok(shape_set) => shape_set.shapes,
+            Err(e) => Opts::command()
+                .error(ErrorKind::Io, format!("{}: {}", path, e))
+                .exit(),
+        },
+        None => vec![],
+    };
 
     let mut population = match std::fs::File::open("optimizer/logs/ga_tuning_2p/pop.json") {
         Ok(file) => serde_json::from_reader(file).unwrap_or_else(|e| {
             eprintln!("pop.json contained invalid data: {}", e);
-            new_population(opts.population_size)
+            new_population(opts.population_size, &shapes)
         }),
-        Err(_) => new_population::<Evaluator>(opts.population_size),
+        Err(_) => new_population::<Evaluator>(opts.population_size, &shapes),
     };
 
-    // マルチスレッドでシミュレーション
-    let matchups = Arc::new(Mutex::new((true, VecDeque::new())));
-    let (send, game_results) = channel();
-    for _ in 0..opts.parallel {
-        let matchups = matchups.clone();
-        let send = send.clone();
-        std::thread::spawn(move || loop {
-            // (1PのAIのindex, 1PのAIのEvaluator, 2PのAIのindex, 2PのAIのEvaluator, 配ぷよのマージン)
-            let (p1, p1_e, p2, p2_e, haipuyo_margin) = {
-                let (active, ref mut queue) = *matchups.lock().unwrap();
-                if !active {
-                    break;
-                }
-                match queue.pop_front() {
-                    Some(v) => v,
-                    None => continue,
-                }
-            };
-
-            let mut logger: Box<dyn Logger> = Box::new(NullLogger::new("", None).unwrap());
-            let ai_1p: Box<dyn AI> = Box::new(BeamSearchAI::new_customize(p1_e));
-            let ai_2p: Box<dyn AI> = Box::new(BeamSearchAI::new_customize(p2_e));
-            let simulate_result_2p = simulate_2p(
+    // 2 個体を `haipuyo_margin` 番目の配ぷよから対戦させる
+    let simulate = |p1_e: Evaluator, p2_e: Evaluator, haipuyo_margin: usize| {
+        let mut logger: Box<dyn Logger> = Box::new(NullLogger::new("", None).unwrap());
+        let ai_1p: Box<dyn AI> = Box::new(BeamSearchAI::new_customize(p1_e));
+        let ai_2p: Box<dyn AI> = Box::new(BeamSearchAI::new_customize(p2_e));
+        match opts.pairs {
+            Some(pairs) => simulate_2p_paired(
+                &mut logger,
+                &ai_1p,
+                &ai_2p,
+                pairs,
+                None,
+                opts.visible_tumos,
+                Some(haipuyo_margin),
+                rules,
+                None,
+            ),
+            None => simulate_2p(
                 &mut logger,
                 &ai_1p,
                 &ai_2p,
                 opts.win_goal,
                 opts.visible_tumos,
                 Some(haipuyo_margin),
-            );
-
-            send.send(Some((p1, p2, simulate_result_2p))).ok();
-        });
-    }
+                rules,
+                None,
+            ),
+        }
+    };
 
     loop {
         assert_eq!(population.members.len(), opts.population_size);
@@ -108,22 +165,10 @@ fn main() -> Result<(), std::io::Error> {
         }
 
         let haipuyo_margin = ((population.generation / 10) * 200) % TUMO_PATTERN;
-        let mut count = 0;
-        {
-            let mut matchups = matchups.lock().unwrap();
-            for i in 0..opts.population_size {
-                for j in (i + 1)..opts.population_size {
-                    matchups.1.push_back((
-                        i,
-                        population.members[i].clone(),
-                        j,
-                        population.members[j].clone(),
-                        haipuyo_margin,
-                    ));
-                    count += 1;
-                }
-            }
-        }
+        // (1PのAIのindex, 2PのAIのindex)
+        let matchups: Vec<(usize, usize)> = (0..opts.population_size)
+            .flat_map(|i| ((i + 1)..opts.population_size).map(move |j| (i, j)))
+            .collect();
 
         println!(
             "> Gen {} (haipuyo-margin: {})",
@@ -135,16 +180,29 @@ fn main() -> Result<(), std::io::Error> {
         for i in 0..opts.population_size {
             results.push((i, 0_i32));
         }
+        // 同時に `games` 試合までシミュレーション（AI のモンテカルロは共有のワーカープールで動く）
         // TODO: プログレスバーのパッケージを使う
-        for i in 0..count {
-            if let Some((p1, p2, simulate_result_2p)) = game_results.recv().unwrap() {
-                let simulate_result_2p = simulate_result_2p?;
-                simulate_results[p1 * opts.population_size + p2] = Some(simulate_result_2p.clone());
-                results[p1].1 += simulate_result_2p.win_count_1p as i32;
-                results[p2].1 += simulate_result_2p.win_count_2p as i32;
-            }
-            print!("{} ", i + 1);
-            stdout().flush().unwrap();
+        let finished = AtomicUsize::new(0);
+        let game_results: Vec<_> = game_pool.install(|| {
+            matchups
+                .par_iter()
+                .map(|&(p1, p2)| {
+                    let simulate_result_2p = simulate(
+                        population.members[p1].clone(),
+                        population.members[p2].clone(),
+                        haipuyo_margin,
+                    );
+                    print!("{} ", finished.fetch_add(1, Ordering::Relaxed) + 1);
+                    stdout().flush().unwrap();
+                    simulate_result_2p
+                })
+                .collect()
+        });
+        for (&(p1, p2), simulate_result_2p) in matchups.iter().zip(game_results) {
+            let simulate_result_2p = simulate_result_2p?;
+            simulate_results[p1 * opts.population_size + p2] = Some(simulate_result_2p.clone());
+            results[p1].1 += simulate_result_2p.win_count_1p as i32;
+            results[p2].1 += simulate_result_2p.win_count_2p as i32;
         }
         println!();
 
@@ -231,21 +289,12 @@ fn main() -> Result<(), std::io::Error> {
             Err(e) => eprintln!("Error saving best kifu of generation: {}", e),
         }
 
-        // Baselineと `opts.win_goal` 先してみる
+        // Baselineと `opts.win_goal` 先（`opts.pairs` があればその組数）してみる
         if population.members[results[0].0].sub_name.is_some() {
-            let mut logger: Box<dyn Logger> = Box::new(NullLogger::new("", None).unwrap());
-            let ai_strongest: Box<dyn AI> = Box::new(BeamSearchAI::new_customize(
+            let simulate_result_with_baseline = simulate(
                 population.members[results[0].0].clone(),
-            ));
-            let ai_baseline: Box<dyn AI> =
-                Box::new(BeamSearchAI::new_customize(Evaluator::default()));
-            let simulate_result_with_baseline = simulate_2p(
-                &mut logger,
-                &ai_strongest,
-                &ai_baseline,
-                opts.win_goal,
-                opts.visible_tumos,
-                Some(0),
+                Evaluator::default(),
+                0,
             )?;
             println!(
                 "> {:>8} v.s. Baseline => {:3} - {:3}",
@@ -253,6 +302,16 @@ fn main() -> Result<(), std::io::Error> {
                 simulate_result_with_baseline.win_count_1p,
                 simulate_result_with_baseline.win_count_2p
             );
+            if let Some(pentanomial) = simulate_result_with_baseline.pentanomial {
+                let (elo_lower, elo_upper) = pentanomial.elo_interval95();
+                println!(
+                    ">          Pairs: {} (Elo {:+.1} [{:+.1}, {:+.1}])",
+                    pentanomial,
+                    pentanomial.elo(),
+                    elo_lower,
+                    elo_upper
+                );
+            }
         }
 
         let sec = start.elapsed().as_secs();
@@ -277,9 +336,6 @@ fn main() -> Result<(), std::io::Error> {
         population = new_population;
     }
 
-    // シミュレーション用のスレッドをcloseする
-    matchups.lock().unwrap().0 = false;
-
     Ok(())
 }
 
@@ -289,11 +345,14 @@ struct Population<E: Mutateable> {
     members: Vec<E>,
 }
 
-fn new_population<E: Mutateable>(population_size: usize) -> Population<E> {
+fn new_population<E: Mutateable>(
+    population_size: usize,
+    shapes: &[ShapeTemplate],
+) -> Population<E> {
     let mut members = vec![];
-    members.push(E::default());
+    members.push(E::default_with_shapes(shapes));
     for num in 0..(population_size - 1) {
-        members.push(E::generate(format!("Gen 0 #{:>02}", num)));
+        members.push(E::generate(format!("Gen 0 #{:>02}", num), shapes));
     }
     Population {
         generation: 0,
@@ -1,14 +1,17 @@
-use cpu::evaluator::Evaluator;
+use cpu::evaluator::{Evaluator, Orientation, ShapeTemplate};
 use rand::prelude::*;
 
 pub trait Mutateable: Default {
-    fn generate(sub_name: String) -> Self;
+    /// パラメータを乱数で決めた個体を作る（`shapes` のテンプレートは重みだけ乱数で決めて持たせる）
+    fn generate(sub_name: String, shapes: &[ShapeTemplate]) -> Self;
+    /// 既定のパラメータに `shapes` のテンプレートを加えた個体を作る
+    fn default_with_shapes(shapes: &[ShapeTemplate]) -> Self;
     fn crossover(parent1: &Self, parent2: &Self, sub_name: String) -> Self;
     fn name(&self) -> &str;
 }
 
 impl Mutateable for Evaluator {
-    fn generate(sub_name: String) -> Self {
+    fn generate(sub_name: String, shapes: &[ShapeTemplate]) -> Self {
         Evaluator {
             // 盤面
             valley: thread_rng().gen_range(-999..0),
@@ -90,6 +93,10 @@ impl Mutateable for Evaluator {
             gtr_head_4: thread_rng().gen_range(0..1000),
             gtr_head_5: thread_rng().gen_range(0..1000),
             gtr_head_6: thread_rng().gen_range(0..1000),
+            // 形のテンプレート
+            pattern_orientation: Orientation::default(),
+            pattern_partial: false,
+            shapes: generate_shapes(shapes),
             // チューニング用
             sub_name: Some(sub_name),
         }
@@ -222,16 +229,66 @@ impl Mutateable for Evaluator {
             gtr_head_4: crossover_gene(parent1.gtr_head_4, parent2.gtr_head_4),
             gtr_head_5: crossover_gene(parent1.gtr_head_5, parent2.gtr_head_5),
             gtr_head_6: crossover_gene(parent1.gtr_head_6, parent2.gtr_head_6),
+            pattern_orientation: parent1.pattern_orientation,
+            pattern_partial: parent1.pattern_partial,
+            shapes: crossover_shapes(&parent1.shapes, &parent2.shapes),
             // チューニング用
             sub_name: Some(sub_name),
         }
     }
 
+    fn default_with_shapes(shapes: &[ShapeTemplate]) -> Self {
+        Evaluator::default().with_shapes(shapes.to_vec())
+    }
+
     fn name(&self) -> &str {
         self.sub_name.as_ref().map(|s| &**s).unwrap_or("")
     }
 }
 
+/// 読み込んだ形のテンプレートに、乱数で決めた重みを持たせる
+fn generate_shapes(shapes: &[ShapeTemplate]) -> Vec<ShapeTemplate> {
+    shapes
+        .iter()
+        .map(|shape| {
+            let mut shape = shape.clone();
+            shape.weight = thread_rng().gen_range(-999..1000);
+            shape
+        })
+        .collect()
+}
+
+/// 同じ名前の形の重みを交叉させる（片方の親にしか無い形は、重みを突然変異させて引き継ぐ）
+fn crossover_shapes(shapes1: &[ShapeTemplate], shapes2: &[ShapeTemplate]) -> Vec<ShapeTemplate> {
+    let mut shapes: Vec<ShapeTemplate> = shapes1
+        .iter()
+        .map(|shape| {
+            let mut shape = shape.clone();
+            shape.weight = match shapes2.iter().find(|other| other.name == shape.name) {
+                Some(other) => crossover_gene(shape.weight, other.weight),
+                None => mutate_gene(shape.weight),
+            };
+            shape
+        })
+        .collect();
+    shapes.extend(
+        shapes2
+            .iter()
+            .filter(|shape| !shapes1.iter().any(|other| other.name == shape.name))
+            .map(|shape| {
+                let mut shape = shape.clone();
+                shape.weight = mutate_gene(shape.weight);
+                shape
+            }),
+    );
+    shapes
+}
+
+/// 交叉の相手がいない遺伝子に、交叉と同じ割合で乱数やノイズを加える
+fn mutate_gene(v: i32) -> i32 {
+    crossover_gene(v, v)
+}
+
 fn crossover_gene(v1: i32, v2: i32) -> i32 {
     let v = match thread_rng().gen_range(0..100) {
         0..=41 => v1,             // 42%
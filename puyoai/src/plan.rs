@@ -1,8 +1,8 @@
 use std::vec::Vec;
 
 use puyoai_core::{
-    control::PuyoController, decision::Decision, field::CoreField, kumipuyo::Kumipuyo,
-    rensa_result::RensaResult,
+    color::PuyoColor, control::PuyoController, decision::Decision, field::CoreField,
+    kumipuyo::Kumipuyo, rensa_result::RensaResult,
 };
 
 use crate::es_field::EsCoreField;
@@ -118,20 +118,144 @@ impl Plan {
     }
 }
 
+/// プラン列挙時のおじゃまぷよの状態
+#[derive(Clone, Debug)]
+pub struct OjamaStatus {
+    /// プラン中に降ったおじゃまぷよの数
+    pub fallen_ojama: usize,
+    /// 確定おじゃまぷよ（次にぷよを置いた後に降る）
+    pub fixed_ojama: usize,
+    /// 予告おじゃまぷよ（`ojama_committing_frame_id` フレーム後に確定する）
+    pub pending_ojama: usize,
+    /// 予告おじゃまぷよが確定するまでのフレーム数（相手の連鎖が終わるフレーム）
+    pub ojama_committing_frame_id: usize,
+    pub has_zenkeshi: bool,
+    /// 1 回に降るおじゃまぷよの上限（ルールで変わる）
+    pub ojama_per_drop: usize,
+}
+
+impl Default for OjamaStatus {
+    fn default() -> Self {
+        OjamaStatus::new(0, 0, 0, false)
+    }
+}
+
+impl OjamaStatus {
+    pub fn new(
+        fixed_ojama: usize,
+        pending_ojama: usize,
+        ojama_committing_frame_id: usize,
+        has_zenkeshi: bool,
+    ) -> Self {
+        OjamaStatus {
+            fallen_ojama: 0,
+            fixed_ojama,
+            pending_ojama,
+            ojama_committing_frame_id,
+            has_zenkeshi,
+            ojama_per_drop: 30,
+        }
+    }
+
+    pub fn with_ojama_per_drop(mut self, ojama_per_drop: usize) -> Self {
+        self.ojama_per_drop = ojama_per_drop;
+        self
+    }
+
+    /// 確定おじゃまぷよを（最大 `ojama_per_drop` 個）降らせて、それにかかったフレーム数を返す
+    pub fn drop_fixed_ojama(&mut self, field: &mut CoreField) -> usize {
+        let ojama = self.fixed_ojama.min(self.ojama_per_drop);
+        if ojama == 0 {
+            return 0;
+        }
+        self.fixed_ojama -= ojama;
+        self.fallen_ojama += ojama;
+        field.es_drop_ojama(ojama, None)
+    }
+
+    /// `frame` フレーム経過した時点で、確定しているはずの予告おじゃまぷよを確定させる
+    fn commit_pending_ojama(&mut self, frame: usize) {
+        if self.pending_ojama > 0 && frame >= self.ojama_committing_frame_id {
+            self.fixed_ojama += self.pending_ojama;
+            self.pending_ojama = 0;
+        }
+    }
+}
+
+/// ツモに使われる色（色数が少ないルールでは先頭から使う）
+const NORMAL_COLORS: [PuyoColor; 4] = [
+    PuyoColor::RED,
+    PuyoColor::BLUE,
+    PuyoColor::YELLOW,
+    PuyoColor::GREEN,
+];
+
+/// 見えていないツモとしてありうる配色（`num_colors` 色なら n(n+1)/2 通り、4 色なら 10 通り）と、その確率
+fn all_kumipuyo_classes(num_colors: usize) -> Vec<(Kumipuyo, f64)> {
+    let colors = &NORMAL_COLORS[..num_colors.clamp(1, NORMAL_COLORS.len())];
+    let num_pairs = (colors.len() * colors.len()) as f64;
+
+    let mut classes = vec![];
+    for i in 0..colors.len() {
+        for j in i..colors.len() {
+            // ゾロは 1/n^2、それ以外は軸と子を入れ替えたものも含めて 2/n^2
+            let weight = if i == j { 1.0 } else { 2.0 };
+            classes.push((Kumipuyo::new(colors[i], colors[j]), weight / num_pairs));
+        }
+    }
+    classes
+}
+
 impl Plan {
+    /// `seq` に無い手目のツモは `num_colors` 色の全配色を試す（`seq` には試しているツモを積んでいく）
     pub fn iterate_available_plans_internal<Callback>(
         field: &CoreField,
-        seq: &Vec<Kumipuyo>,
+        seq: &mut Vec<Kumipuyo>,
         decisions: &mut Vec<Decision>,
         current_depth: usize,
         max_depth: usize,
         current_num_chigiri: usize,
         total_frames: usize,
+        ojama_status: &OjamaStatus,
+        probability: f64,
+        num_colors: usize,
         callback: &mut Callback,
     ) where
-        Callback: FnMut(&CoreField, &Vec<Decision>, usize, usize, usize, bool),
+        Callback: FnMut(
+            &CoreField,
+            &Vec<Decision>,
+            &[Kumipuyo],
+            usize,
+            usize,
+            usize,
+            bool,
+            &OjamaStatus,
+            f64,
+        ),
     {
-        let kumipuyo = &seq[current_depth];
+        if current_depth >= seq.len() {
+            for (kumipuyo, p) in all_kumipuyo_classes(num_colors) {
+                seq.push(kumipuyo);
+                Plan::iterate_available_plans_internal(
+                    field,
+                    seq,
+                    decisions,
+                    current_depth,
+                    max_depth,
+                    current_num_chigiri,
+                    total_frames,
+                    ojama_status,
+                    probability * p,
+                    num_colors,
+                    callback,
+                );
+                seq.pop();
+            }
+            return;
+        }
+
+        // `seq` は再帰先で書き換えるので、借用せずに複製しておく
+        let kumipuyo = seq[current_depth].clone();
         let controller = PuyoController::new();
 
         for decision in {
@@ -151,7 +275,7 @@ impl Plan {
             decisions.push(decision.clone());
 
             let mut next_field = field.clone();
-            if !next_field.drop_kumipuyo(decision, kumipuyo) {
+            if !next_field.drop_kumipuyo(decision, &kumipuyo) {
                 decisions.pop();
                 continue;
             }
@@ -166,12 +290,25 @@ impl Plan {
                 callback(
                     &next_field,
                     decisions,
+                    &seq[..decisions.len()],
                     current_num_chigiri + (is_chigiri as usize),
                     total_frames,
                     drop_frames,
                     should_fire,
+                    ojama_status,
+                    probability,
                 )
             } else {
+                // 次のぷよを置く前に、確定しているおじゃまぷよを降らせる
+                let mut next_ojama_status = ojama_status.clone();
+                let mut next_total_frames = total_frames + drop_frames;
+                next_ojama_status.commit_pending_ojama(next_total_frames);
+                next_total_frames += next_ojama_status.drop_fixed_ojama(&mut next_field);
+                if next_field.is_dead() {
+                    decisions.pop();
+                    continue;
+                }
+
                 Plan::iterate_available_plans_internal(
                     &next_field,
                     seq,
@@ -179,7 +316,10 @@ impl Plan {
                     current_depth + 1,
                     max_depth,
                     current_num_chigiri + (is_chigiri as usize),
-                    total_frames + drop_frames,
+                    next_total_frames,
+                    &next_ojama_status,
+                    probability,
+                    num_colors,
                     callback,
                 );
             }
@@ -196,66 +336,141 @@ impl Plan {
     ) where
         Callback: FnMut(&Plan),
     {
-        // 元の実装では `seq.len() < max_depth` の場合に全配色を試しているが、ここでは省いている
+        Plan::iterate_available_plans_with_ojama(
+            field,
+            seq,
+            max_depth,
+            &OjamaStatus::default(),
+            callback,
+        );
+    }
+
+    /// おじゃまぷよを考慮してプランを列挙する
+    /// - 確定おじゃまぷよは、連鎖が起きなかった場合にぷよを置いた後（最後の手も含む）に、
+    ///   最大 `ojama_per_drop` 個ずつ降らせる（降った分のフレーム数は `last_drop_frames` に含める）
+    /// - 予告おじゃまぷよは、`ojama_committing_frame_id` フレーム経過した時点で確定させる
+    /// - プランの連鎖による相殺は行わない（`fixed_ojama` / `pending_ojama` は相殺前の値）
+    pub fn iterate_available_plans_with_ojama<Callback>(
+        field: &CoreField,
+        seq: &Vec<Kumipuyo>,
+        max_depth: usize,
+        ojama_status: &OjamaStatus,
+        callback: &mut Callback,
+    ) where
+        Callback: FnMut(&Plan),
+    {
+        // 見えていないツモも試す場合は `iterate_available_plans_with_unknown_tumos` を使う
         assert!(seq.len() >= max_depth);
 
+        Plan::iterate_available_plans_with_unknown_tumos(
+            field,
+            seq,
+            max_depth,
+            ojama_status,
+            NORMAL_COLORS.len(),
+            &mut |plan: &Plan, _: &[Kumipuyo], _: f64| callback(plan),
+        );
+    }
+
+    /// 見えていないツモ（`seq.len()` 手目以降）は `num_colors` 色の全配色（4 色なら 10 通り）を試してプランを列挙する
+    /// コールバックには、プランと各手で置いたツモ、そのツモの組み合わせになる確率を渡す
+    /// （期待値を求める場合は、同じツモの組み合わせのプランの中で最大の評価値を確率で重み付けする）
+    pub fn iterate_available_plans_with_unknown_tumos<Callback>(
+        field: &CoreField,
+        seq: &Vec<Kumipuyo>,
+        max_depth: usize,
+        ojama_status: &OjamaStatus,
+        num_colors: usize,
+        callback: &mut Callback,
+    ) where
+        Callback: FnMut(&Plan, &[Kumipuyo], f64),
+    {
+        let mut seq = seq.clone();
+        seq.truncate(max_depth);
+
         let mut decisions: Vec<Decision> = vec![];
         decisions.reserve(max_depth);
 
         let mut f = |field_before_rensa: &CoreField,
                      decisions: &Vec<Decision>,
+                     tumos: &[Kumipuyo],
                      num_chigiri: usize,
                      frames_to_ignite: usize,
                      last_drop_frames: usize,
-                     should_fire: bool| {
+                     should_fire: bool,
+                     ojama_status: &OjamaStatus,
+                     probability: f64| {
             debug_assert!(!decisions.is_empty());
 
+            let mut ojama_status = ojama_status.clone();
+            ojama_status.commit_pending_ojama(frames_to_ignite + last_drop_frames);
+
             if should_fire {
                 let mut cf = field_before_rensa.clone();
                 let rensa_result = cf.es_simulate();
                 debug_assert!(rensa_result.chain > 0);
                 if !cf.is_dead() {
-                    callback(&Plan::new(
+                    // 全消しの権利は連鎖を打つと消費され、その連鎖で全消しすると得られる
+                    let has_zenkeshi = cf.is_zenkeshi();
+                    callback(
+                        &Plan::new(
+                            cf,
+                            decisions.clone(),
+                            rensa_result,
+                            num_chigiri,
+                            frames_to_ignite,
+                            last_drop_frames,
+                            ojama_status.fallen_ojama,
+                            ojama_status.fixed_ojama,
+                            ojama_status.pending_ojama,
+                            ojama_status.ojama_committing_frame_id,
+                            has_zenkeshi,
+                        ),
+                        tumos,
+                        probability,
+                    );
+                }
+            } else {
+                debug_assert!(!field_before_rensa.is_dead());
+                // 連鎖しなければ、置いた後に確定おじゃまぷよが降る
+                let mut cf = field_before_rensa.clone();
+                let last_drop_frames = last_drop_frames + ojama_status.drop_fixed_ojama(&mut cf);
+                if cf.is_dead() {
+                    return;
+                }
+
+                let rensa_result = RensaResult::empty();
+                callback(
+                    &Plan::new(
                         cf,
                         decisions.clone(),
                         rensa_result,
                         num_chigiri,
                         frames_to_ignite,
                         last_drop_frames,
-                        0,
-                        0,
-                        0,
-                        0,
-                        false,
-                    ));
-                }
-            } else {
-                debug_assert!(!field_before_rensa.is_dead());
-                let rensa_result = RensaResult::empty();
-                callback(&Plan::new(
-                    field_before_rensa.clone(),
-                    decisions.clone(),
-                    rensa_result,
-                    num_chigiri,
-                    frames_to_ignite,
-                    last_drop_frames,
-                    0,
-                    0,
-                    0,
-                    0,
-                    false,
-                ));
+                        ojama_status.fallen_ojama,
+                        ojama_status.fixed_ojama,
+                        ojama_status.pending_ojama,
+                        ojama_status.ojama_committing_frame_id,
+                        ojama_status.has_zenkeshi,
+                    ),
+                    tumos,
+                    probability,
+                );
             }
         };
 
         Plan::iterate_available_plans_internal(
             field,
-            seq,
+            &mut seq,
             &mut decisions,
             0,
             max_depth,
             0,
             0,
+            ojama_status,
+            1.0,
+            num_colors,
             &mut f,
         );
     }
@@ -376,4 +591,200 @@ mod tests {
 
         assert!(found);
     }
+
+    #[test]
+    fn test_iterate_available_plans_with_ojama() {
+        let field = CoreField::new();
+        let seq = vec![
+            Kumipuyo::new(PuyoColor::RED, PuyoColor::BLUE),
+            Kumipuyo::new(PuyoColor::YELLOW, PuyoColor::GREEN),
+        ];
+
+        let mut found = false;
+        Plan::iterate_available_plans_with_ojama(
+            &field,
+            &seq,
+            2,
+            &OjamaStatus::new(6, 0, 0, false),
+            &mut |plan: &Plan| {
+                assert_eq!(plan.fallen_ojama(), 6);
+                assert_eq!(plan.total_ojama(), 0);
+                if plan.decision(0) == &Decision::new(1, 0)
+                    && plan.decision(1) == &Decision::new(1, 0)
+                {
+                    // 1手目を置いた後に1段降ってから2手目を置く
+                    assert_eq!(plan.field().height(1), 5);
+                    assert_eq!(plan.field().color(1, 3), PuyoColor::OJAMA);
+                    assert!(
+                        plan.frames_to_ignite()
+                            > field.es_frames_to_drop_next(&Decision::new(1, 0))
+                    );
+                    found = true;
+                }
+            },
+        );
+
+        assert!(found);
+    }
+
+    #[test]
+    fn test_iterate_available_plans_with_pending_ojama() {
+        let field = CoreField::new();
+        let seq = vec![
+            Kumipuyo::new(PuyoColor::RED, PuyoColor::BLUE),
+            Kumipuyo::new(PuyoColor::YELLOW, PuyoColor::GREEN),
+        ];
+
+        // 予告おじゃまぷよはまだ確定しないので降らない
+        Plan::iterate_available_plans_with_ojama(
+            &field,
+            &seq,
+            2,
+            &OjamaStatus::new(0, 40, 10000, false),
+            &mut |plan: &Plan| {
+                assert_eq!(plan.fallen_ojama(), 0);
+                assert_eq!(plan.pending_ojama(), 40);
+                assert_eq!(plan.fixed_ojama(), 0);
+            },
+        );
+
+        // 1手目を置いた時点で確定するので、置くたびに30個ずつ降る
+        Plan::iterate_available_plans_with_ojama(
+            &field,
+            &seq,
+            2,
+            &OjamaStatus::new(0, 40, 1, false),
+            &mut |plan: &Plan| {
+                assert_eq!(plan.fallen_ojama(), 40);
+                assert_eq!(plan.pending_ojama(), 0);
+                assert_eq!(plan.fixed_ojama(), 0);
+            },
+        );
+
+        // 1回に降る数の上限はルールに合わせる
+        Plan::iterate_available_plans_with_ojama(
+            &field,
+            &seq,
+            2,
+            &OjamaStatus::new(0, 40, 1, false).with_ojama_per_drop(6),
+            &mut |plan: &Plan| {
+                assert_eq!(plan.fallen_ojama(), 12);
+                assert_eq!(plan.pending_ojama(), 0);
+                assert_eq!(plan.fixed_ojama(), 28);
+            },
+        );
+    }
+
+    #[test]
+    fn test_iterate_available_plans_with_ojama_zenkeshi() {
+        let seq = vec![Kumipuyo::new(PuyoColor::RED, PuyoColor::RED)];
+
+        // 全消しすると権利を得る
+        let mut found = false;
+        Plan::iterate_available_plans_with_ojama(
+            &CoreField::from_str("  RR  "),
+            &seq,
+            1,
+            &OjamaStatus::default(),
+            &mut |plan: &Plan| {
+                assert_eq!(plan.has_zenkeshi(), plan.is_rensa_plan());
+                found |= plan.is_rensa_plan();
+            },
+        );
+        assert!(found);
+
+        // 連鎖を打つと権利を消費する
+        let mut found = false;
+        Plan::iterate_available_plans_with_ojama(
+            &CoreField::from_str("  RR Y"),
+            &seq,
+            1,
+            &OjamaStatus::new(0, 0, 0, true),
+            &mut |plan: &Plan| {
+                assert_eq!(plan.has_zenkeshi(), !plan.is_rensa_plan());
+                found |= plan.is_rensa_plan();
+            },
+        );
+        assert!(found);
+    }
+
+    #[test]
+    fn test_iterate_available_plans_with_unknown_tumos() {
+        let field = CoreField::new();
+
+        // 同じ手について、全配色の確率の合計は 1 になる
+        let mut num_classes = 0;
+        let mut total_probability = 0.0;
+        Plan::iterate_available_plans_with_unknown_tumos(
+            &field,
+            &vec![],
+            1,
+            &OjamaStatus::default(),
+            4,
+            &mut |plan: &Plan, tumos: &[Kumipuyo], probability: f64| {
+                assert_eq!(tumos.len(), 1);
+                if plan.first_decision() == &Decision::new(1, 2) {
+                    num_classes += 1;
+                    total_probability += probability;
+                }
+            },
+        );
+        assert_eq!(num_classes, 10);
+        assert!((total_probability - 1.0).abs() < 1e-9);
+
+        // 3 色なら、4 色目を含むツモは試さない
+        let mut num_classes = 0;
+        let mut total_probability = 0.0;
+        Plan::iterate_available_plans_with_unknown_tumos(
+            &field,
+            &vec![],
+            1,
+            &OjamaStatus::default(),
+            3,
+            &mut |plan: &Plan, tumos: &[Kumipuyo], probability: f64| {
+                assert_ne!(tumos[0].axis(), PuyoColor::GREEN);
+                assert_ne!(tumos[0].child(), PuyoColor::GREEN);
+                if plan.first_decision() == &Decision::new(1, 2) {
+                    num_classes += 1;
+                    total_probability += probability;
+                }
+            },
+        );
+        assert_eq!(num_classes, 6);
+        assert!((total_probability - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_iterate_available_plans_with_unknown_tumos_after_fire() {
+        let field = CoreField::from_str("  RR  ");
+        let seq = vec![Kumipuyo::new(PuyoColor::RED, PuyoColor::RED)];
+
+        // 発火したプランは、その後のツモによらず1回だけ列挙される
+        let mut num_fired = 0;
+        let mut num_unfired = 0;
+        Plan::iterate_available_plans_with_unknown_tumos(
+            &field,
+            &seq,
+            2,
+            &OjamaStatus::default(),
+            4,
+            &mut |plan: &Plan, tumos: &[Kumipuyo], probability: f64| {
+                assert_eq!(tumos.len(), plan.decision_size());
+                assert_eq!(tumos[0], seq[0]);
+                if plan.decision_size() == 1 {
+                    assert!(plan.is_rensa_plan());
+                    assert_eq!(probability, 1.0);
+                    if plan.first_decision() == &Decision::new(3, 2) {
+                        num_fired += 1;
+                    }
+                } else if plan.decision(0) == &Decision::new(1, 2)
+                    && plan.decision(1) == &Decision::new(6, 2)
+                {
+                    num_unfired += 1;
+                }
+            },
+        );
+        assert_eq!(num_fired, 1);
+        assert_eq!(num_unfired, 10);
+    }
 }
@@ -1,6 +1,6 @@
 use clap::Parser;
-use cpu::bot::{BeamSearchAI, PlayerState, AI};
-use cpu::evaluator::Evaluator;
+use cpu::bot::{AISpec, PlayerState, AI};
+use cpu::evaluator::{Evaluator, Explanation};
 use ghoti_simulator::puyop_decoder::PuyopDecoder;
 use ghoti_simulator::puyop_parser::PuyopParser;
 use puyoai::{
@@ -36,10 +36,14 @@ struct Opts {
     #[clap(long, default_value = "10")]
     top_n: usize,
 
-    /// 評価値の詳細を表示
+    /// 評価値の詳細（特徴量ごとの内訳）を表示
     #[clap(long)]
     verbose: bool,
 
+    /// 読み深さが2以上のときに使うAIの指定（`Name[:key=value]*`、`evaluator=` や `shapes=` を指定すると評価値もその評価関数で出す）
+    #[clap(long, default_value = "BeamSearchAI")]
+    ai: String,
+
     /// AI読み深さ（デフォルトは1手のみ評価）
     #[clap(long, default_value = "1")]
     depth: usize,
@@ -51,6 +55,9 @@ struct Opts {
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     let opts = Opts::parse();
+    let ai_spec = AISpec::parse(&opts.ai)?;
+    let ai = ai_spec.create();
+    let evaluator = ai_spec.params().evaluator_with_shapes().unwrap_or_default();
 
     // 盤面とツモを取得
     let (mut field, mut tumos) = if let Some(url) = opts.url {
@@ -82,8 +89,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             }
 
             // 現在の盤面で最善手を計算
-            let ai = BeamSearchAI::new();
-            let candidates = analyze_all_moves(&ai, &field, &tumos, 1);
+            let candidates = analyze_all_moves(ai.as_ref(), &evaluator, &field, &tumos, 1);
 
             if candidates.is_empty() {
                 eprintln!("警告: 有効な手がありません（{}手目で終了）", move_num);
@@ -121,8 +127,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     println!();
 
     // 全候補を評価
-    let ai = BeamSearchAI::new();
-    let candidates = analyze_all_moves(&ai, &field, &tumos, opts.depth);
+    let candidates = analyze_all_moves(ai.as_ref(), &evaluator, &field, &tumos, opts.depth);
 
     // 上位N件を表示
     println!("=== Top {} Moves ===\n", opts.top_n);
@@ -142,6 +147,10 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 format_kumipuyo(&tumos[0]),
                 candidate.decision.axis_x()
             );
+            println!("    評価値の内訳（寄与の大きい順）:");
+            for term in candidate.explanation.nonzero_terms() {
+                println!("      {}", term);
+            }
         }
 
         if candidate.chain > 0 {
@@ -167,21 +176,23 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 struct Candidate {
     decision: Decision,
     eval_score: i32,
+    /// `eval_score` の特徴量ごとの内訳
+    explanation: Explanation,
     chain: usize,
     score: usize,
 }
 
 fn analyze_all_moves(
-    ai: &BeamSearchAI,
+    ai: &dyn AI,
+    evaluator: &Evaluator,
     field: &CoreField,
     tumos: &Vec<Kumipuyo>,
     depth: usize,
 ) -> Vec<Candidate> {
-    let evaluator = Evaluator::default();
     let mut candidates = Vec::new();
 
     if depth > 1 {
-        // BeamSearchAIを使う場合：depth手先まで読んで最善手を選択
+        // AIを使う場合：depth手先まで読んで最善手を選択
         let player_state = PlayerState::new(
             0,                  // frame
             field.clone(),      // field
@@ -190,6 +201,7 @@ fn analyze_all_moves(
             0,                  // carry_over
             0,                  // fixed_ojama
             0,                  // pending_ojama
+            false,              // zenkeshi
             0,                  // current_chain
             0,                  // tumo_index
             None,               // haipuyo
@@ -205,10 +217,11 @@ fn analyze_all_moves(
             Plan::iterate_available_plans(field, tumos, 1, &mut |plan: &Plan| {
                 if plan.first_decision().axis_x() == decision.axis_x()
                     && plan.first_decision().rot() == decision.rot() {
-                    let eval_score = evaluator.evaluate(plan);
+                    let explanation = evaluator.evaluate_explained(plan);
                     candidates.push(Candidate {
                         decision: decision.clone(),
-                        eval_score,
+                        eval_score: explanation.total,
+                        explanation,
                         chain: plan.chain(),
                         score: plan.score(),
                     });
@@ -224,10 +237,11 @@ fn analyze_all_moves(
                 .unwrap_or(false);
 
             if !is_ai_choice {
-                let eval_score = evaluator.evaluate(plan);
+                let explanation = evaluator.evaluate_explained(plan);
                 candidates.push(Candidate {
                     decision: decision.clone(),
-                    eval_score,
+                    eval_score: explanation.total,
+                    explanation,
                     chain: plan.chain(),
                     score: plan.score(),
                 });
@@ -243,13 +257,14 @@ fn analyze_all_moves(
         // depth=1の場合：全候補を1手先の評価値で評価
         Plan::iterate_available_plans(field, tumos, depth, &mut |plan: &Plan| {
             let decision = plan.first_decision().clone();
-            let eval_score = evaluator.evaluate(plan);
+            let explanation = evaluator.evaluate_explained(plan);
             let chain = plan.chain();
             let score = plan.score();
 
             candidates.push(Candidate {
                 decision,
-                eval_score,
+                eval_score: explanation.total,
+                explanation,
                 chain,
                 score,
             });
@@ -1,5 +1,5 @@
-use clap::Parser;
-use cpu::bot::{BeamSearchAI, RandomAI, TakaptAI, AI};
+use clap::{error::ErrorKind, CommandFactory, Parser};
+use cpu::bot::{create_ai, Rules};
 use ghoti_simulator::simulate_1p;
 use logger::*;
 
@@ -11,7 +11,7 @@ use logger::*;
     about = "とこぷよのシミュレータ"
 )]
 struct Opts {
-    /// AI の名前（`ai.name()`）
+    /// AI の指定（`Name[:key=value]*`、例: `BeamSearchAI:evaluator=evaluator.json`）
     #[clap(long, default_value = "BeamSearchAI")]
     ai: String,
 
@@ -38,20 +38,25 @@ struct Opts {
     /// この得点以上の連鎖が打たれたら終了
     #[clap(long)]
     required_chain_score: Option<usize>,
+
+    /// ルールを書いた JSON ファイル（省略時はぷよぷよ通のルール）
+    #[clap(long)]
+    rules: Option<String>,
 }
 
 fn main() -> Result<(), std::io::Error> {
     let opts = Opts::parse();
 
-    let ais: Vec<Box<dyn AI>> = vec![
-        Box::new(BeamSearchAI::new()),
-        Box::new(RandomAI::new()),
-        Box::new(TakaptAI::new()),
-    ];
-    let ai = ais
-        .iter()
-        .find(|&ai| ai.name() == opts.ai)
-        .expect(&format!("No AI found: {}", opts.ai));
+    let ai = create_ai(&opts.ai).unwrap_or_else(|e| panic!("{}", e));
+    let rules = match &opts.rules {
+        Some(path) => match Rules::from_json_file(path) {
+            Ok(rules) => rules,
+            Err(e) => Opts::command()
+                .error(ErrorKind::ValueValidation, format!("{}: {}", path, e))
+                .exit(),
+        },
+        None => Rules::default(),
+    };
 
     let mut logger: Box<dyn Logger> = match opts.pr_number {
         None => Box::new(FileLogger::new(
@@ -69,11 +74,12 @@ fn main() -> Result<(), std::io::Error> {
 
         let simulate_result_1p = simulate_1p(
             &mut logger,
-            ai,
+            &ai,
             opts.visible_tumos,
             opts.max_tumos,
             opts.haipuyo_margin,
             opts.required_chain_score,
+            rules,
         );
 
         // output JSON file
@@ -1,6 +1,6 @@
-use clap::Parser;
-use cpu::bot::{BeamSearchAI, RandomAI, AI};
-use ghoti_simulator::simulate_2p;
+use clap::{error::ErrorKind, CommandFactory, Parser};
+use cpu::bot::{create_ai, MarginTime, Rules};
+use ghoti_simulator::{simulate_2p, simulate_2p::RealTime, simulate_2p_paired, sprt::Sprt};
 use logger::*;
 
 #[derive(Parser)]
@@ -11,11 +11,11 @@ use logger::*;
     about = "2人対戦のシミュレータ"
 )]
 struct Opts {
-    /// AI の名前（1P）
+    /// 1P の AI の指定（`Name[:key=value]*`、例: `BeamSearchAI:evaluator=evaluator.json`）
     #[clap(long, default_value = "BeamSearchAI")]
     ai_1p: String,
 
-    /// AI の名前（2P）
+    /// 2P の AI の指定（`Name[:key=value]*`）
     #[clap(long, default_value = "RandomAI")]
     ai_2p: String,
 
@@ -23,6 +23,30 @@ struct Opts {
     #[clap(long, default_value = "30")]
     win_goal: usize,
 
+    /// 指定すると、同じ配ぷよで先後を入れ替えた 2 試合を 1 組として、この組数だけ対戦させる（`win_goal` は使わない）
+    #[clap(long)]
+    pairs: Option<usize>,
+
+    /// SPRT の H0 の Elo の差（`--elo1` と一緒に指定すると、判定がつくまで先後を入れ替えた組で対戦させる）
+    #[clap(long, requires = "elo1")]
+    elo0: Option<f64>,
+
+    /// SPRT の H1 の Elo の差
+    #[clap(long, requires = "elo0")]
+    elo1: Option<f64>,
+
+    /// SPRT で、H0 が正しいのに H1 を採択する確率
+    #[clap(long, default_value = "0.05")]
+    alpha: f64,
+
+    /// SPRT で、H1 が正しいのに H0 を採択する確率
+    #[clap(long, default_value = "0.05")]
+    beta: f64,
+
+    /// SPRT で判定がつかなくても打ち切る組数（`--pairs` を指定したらそちらを使う）
+    #[clap(long, default_value = "5000")]
+    sprt_max_pairs: usize,
+
     /// AI に何手読みさせるか
     #[clap(long, default_value = "2")]
     visible_tumos: usize,
@@ -34,20 +58,48 @@ struct Opts {
     /// 配ぷよ番号
     #[clap(long)]
     haipuyo_margin: Option<usize>,
+
+    /// ルールを書いた JSON ファイル（省略時はぷよぷよ通のルール）
+    #[clap(long)]
+    rules: Option<String>,
+
+    /// マージンタイムが始まるフレーム数（指定するとルールのマージンタイムを上書きする）
+    #[clap(long)]
+    margin_time_start: Option<usize>,
+
+    /// マージンタイム中に、おじゃまぷよのレートが下がる間隔（フレーム数）
+    #[clap(long, default_value = "960")]
+    margin_time_interval: usize,
+
+    /// 思考にかかった時間もフレーム数に含める（遅い AI はその分だけ置くのが遅れる）
+    #[clap(long)]
+    real_time: bool,
+
+    /// リアルタイムのとき、AI に渡す思考フレーム数
+    #[clap(long)]
+    think_frame: Option<usize>,
 }
 
 fn main() -> Result<(), std::io::Error> {
     let opts = Opts::parse();
 
-    let ais: Vec<Box<dyn AI>> = vec![Box::new(BeamSearchAI::new()), Box::new(RandomAI::new())];
-    let ai_1p = ais
-        .iter()
-        .find(|&ai| ai.name() == opts.ai_1p)
-        .expect(&format!("No AI found: {}", opts.ai_1p));
-    let ai_2p = ais
-        .iter()
-        .find(|&ai| ai.name() == opts.ai_2p)
-        .expect(&format!("No AI found: {}", opts.ai_2p));
+    let ai_1p = create_ai(&opts.ai_1p).unwrap_or_else(|e| panic!("{}", e));
+    let ai_2p = create_ai(&opts.ai_2p).unwrap_or_else(|e| panic!("{}", e));
+    let mut rules = match &opts.rules {
+        Some(path) => match Rules::from_json_file(path) {
+            Ok(rules) => rules,
+            Err(e) => Opts::command()
+                .error(ErrorKind::ValueValidation, format!("{}: {}", path, e))
+                .exit(),
+        },
+        None => Rules::default(),
+    };
+    if let Some(start_frame) = opts.margin_time_start {
+        rules.margin_time = Some(MarginTime::new(start_frame, opts.margin_time_interval));
+    }
+    if let Err(e) = rules.validate() {
+        Opts::command().error(ErrorKind::ValueValidation, e).exit();
+    }
 
     let mut logger: Box<dyn Logger> = if opts.pr_number.map(|x| x > 0).unwrap_or(false) {
         Box::new(NullLogger::new("", None)?)
@@ -58,14 +110,46 @@ fn main() -> Result<(), std::io::Error> {
         )?)
     };
 
-    let simulate_result_2p = simulate_2p(
-        &mut logger,
-        &Box::new(ai_1p),
-        &Box::new(ai_2p),
-        opts.win_goal,
-        opts.visible_tumos,
-        opts.haipuyo_margin,
-    );
+    let real_time = opts.real_time.then_some(RealTime {
+        think_frame: opts.think_frame,
+    });
+    let sprt = match (opts.elo0, opts.elo1) {
+        (Some(elo0), Some(elo1)) => Some(Sprt::new(elo0, elo1, opts.alpha, opts.beta)),
+        (None, None) => None,
+        _ => Opts::command()
+            .error(
+                ErrorKind::MissingRequiredArgument,
+                "--elo0 and --elo1 must be given together",
+            )
+            .exit(),
+    };
+    if let Some(Err(e)) = sprt.map(|sprt| sprt.validate()) {
+        Opts::command().error(ErrorKind::ValueValidation, e).exit();
+    }
+    let simulate_result_2p = match (opts.pairs, sprt) {
+        (None, None) => simulate_2p(
+            &mut logger,
+            &ai_1p,
+            &ai_2p,
+            opts.win_goal,
+            opts.visible_tumos,
+            opts.haipuyo_margin,
+            rules,
+            real_time,
+        ),
+        // SPRT だけなら、判定がつくまで続ける（最大 `sprt_max_pairs` 組）
+        (pairs, sprt) => simulate_2p_paired(
+            &mut logger,
+            &ai_1p,
+            &ai_2p,
+            pairs.unwrap_or(opts.sprt_max_pairs),
+            sprt,
+            opts.visible_tumos,
+            opts.haipuyo_margin,
+            rules,
+            real_time,
+        ),
+    };
 
     // output JSON file
     if let Some(pr_number) = opts.pr_number {
@@ -1,11 +1,12 @@
 use std::io::{self, Write};
 
-use cpu::bot::{BeamSearchAI, PlayerState, AI};
+use cpu::bot::{create_ai, BeamSearchAI, PlayerState, AI};
 use puyoai::{
     color::PuyoColor,
     decision::Decision,
     field::CoreField,
     kumipuyo::Kumipuyo,
+    plan::Plan,
 };
 
 use ghoti_simulator::haipuyo_detector::*;
@@ -68,19 +69,26 @@ struct ChainAnimation {
 }
 
 fn main() -> Result<(), std::io::Error> {
-    // コマンドライン引数をチェック
-    let args: Vec<String> = std::env::args().collect();
-    let initial_url = if args.len() > 1 {
-        Some(args[1].clone())
-    } else {
-        None
-    };
+    // コマンドライン引数をチェック（`[URL] [--ai Name[:key=value]*]`）
+    let mut initial_url = None;
+    let mut ai_spec = "BeamSearchAI".to_string();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--ai" {
+            ai_spec = args.next().unwrap_or(ai_spec);
+        } else {
+            initial_url = Some(arg);
+        }
+    }
+    // 最終判断を出す AI（候補手の一覧は BeamSearchAI の評価値で出す）
+    let recommender =
+        create_ai(&ai_spec).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
 
     // ターミナルをrawモードに設定
     terminal::enable_raw_mode()?;
     let mut stdout = io::stdout();
 
-    let result = run_game(&mut stdout, initial_url);
+    let result = run_game(&mut stdout, initial_url, recommender.as_ref());
 
     // rawモードを解除
     terminal::disable_raw_mode()?;
@@ -90,7 +98,11 @@ fn main() -> Result<(), std::io::Error> {
     result
 }
 
-fn run_game(stdout: &mut io::Stdout, initial_url: Option<String>) -> Result<(), std::io::Error> {
+fn run_game(
+    stdout: &mut io::Stdout,
+    initial_url: Option<String>,
+    recommender: &dyn AI,
+) -> Result<(), std::io::Error> {
     stdout.execute(terminal::Clear(ClearType::All))?;
     stdout.execute(cursor::MoveTo(0, 0))?;
 
@@ -181,7 +193,7 @@ fn run_game(stdout: &mut io::Stdout, initial_url: Option<String>) -> Result<(),
                         // AIのサジェストを表示
                         stdout.execute(terminal::Clear(ClearType::All))?;
                         stdout.execute(cursor::MoveTo(0, 0))?;
-                        show_ai_suggestions(&ai, &player_state, tumo_index, &mut suggestions_cache);
+                        show_ai_suggestions(&ai, recommender, &player_state, tumo_index, &mut suggestions_cache);
                         println!("\r\nPress any key to continue...\r");
                         stdout.flush()?;
                         event::read()?;
@@ -618,6 +630,7 @@ fn puyo_color_to_term_color(color: PuyoColor) -> Option<Color> {
 
 fn show_ai_suggestions(
     ai: &BeamSearchAI,
+    recommender: &dyn AI,
     player_state: &PlayerState,
     tumo_index: usize,
     suggestions_cache: &mut Option<(usize, Vec<(Decision, i32, String)>)>,
@@ -665,9 +678,26 @@ fn show_ai_suggestions(
         );
     }
 
+    // 1位の手を置いた直後の盤面の評価値の内訳も表示
+    let best_decision = &suggestions[0].0;
+    Plan::iterate_available_plans(&player_state.field, &player_state.seq, 1, &mut |plan: &Plan| {
+        let decision = plan.first_decision();
+        if decision.axis_x() != best_decision.axis_x() || decision.rot() != best_decision.rot() {
+            return;
+        }
+        let explanation = ai.evaluator().evaluate_explained(plan);
+        println!(
+            "\r\n📊 Evaluation breakdown of move 1 right after placing it (total: {}):\r",
+            explanation.total
+        );
+        for term in explanation.nonzero_terms() {
+            println!("   {}\r", term);
+        }
+    });
+
     // AIの最終判断も表示
-    let ai_decision = ai.think(player_state.clone(), None, Some(player_state.tumo_index));
-    println!("\r\n💡 AI's final recommendation:\r");
+    let ai_decision = recommender.think(player_state.clone(), None, None);
+    println!("\r\n💡 {}'s final recommendation:\r", recommender.name());
     println!(
         "   Column {}, Rotation {} ({}) - {}\r",
         ai_decision.decisions[0].axis_x(),
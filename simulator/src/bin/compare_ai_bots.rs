@@ -1,13 +1,21 @@
 use anyhow::Result;
 use clap::Parser;
-use cpu::bot::{BeamSearchAI, ChainFocusedAI, ChainPotentialAI, HybridAI, RandomAI, StableAI, TakaptAI, AI};
-use ghoti_simulator::simulate_1p::simulate_1p;
+use cpu::bot::{
+    monte_carlo::Aggregation,
+    worker_pool::{init_worker_pool, worker_pool},
+    AISpec, MoveKind, Rules, AI,
+};
+use ghoti_simulator::{
+    simulate_1p::simulate_1p,
+    sprt::{Sprt, SprtOutcome, SprtResult},
+};
 use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
 use logger::Logger;
+use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
 use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
-use std::thread;
 use std::time::Instant;
 
 /// 異なるAI Botの性能を比較するツール
@@ -18,9 +26,9 @@ struct Args {
     #[clap(short = 'n', long, default_value = "20")]
     num_games: usize,
 
-    /// 並列実行するスレッド数
-    #[clap(short = 'p', long, default_value = "4")]
-    parallel: usize,
+    /// 並列実行するスレッド数（AI の思考も含めた全体の上限、省略時は CPU のコア数）
+    #[clap(short = 'p', long)]
+    parallel: Option<usize>,
 
     /// 最大手数
     #[clap(long, default_value = "50")]
@@ -34,7 +42,7 @@ struct Args {
     #[clap(long, default_value = "20000")]
     required_chain_score: usize,
 
-    /// 比較するAI（カンマ区切り: BeamSearch,ChainFocused,ChainPotential,Stable,Hybrid,Takapt,Random）
+    /// 比較するAI（`Name[:key=value]*` のカンマ区切り、例: `BeamSearch,Takapt:width=200`）
     #[clap(long, default_value = "BeamSearch,ChainFocused,ChainPotential,Stable,Hybrid,Takapt")]
     ai_types: String,
 
@@ -42,64 +50,37 @@ struct Args {
     #[clap(long)]
     output_json: Option<String>,
 
-    /// シードの開始値（再現性のため）
+    /// シードの開始値（再現性のため、配ぷよと AI のモンテカルロの両方に使う）
     #[clap(long, default_value = "0")]
     seed_start: u32,
 
-    /// 詳細な結果を表示
+    /// モンテカルロの各試行の結果のまとめ方（votes, sum, mean, median, ucb、省略時は各AIの既定）
     #[clap(long)]
-    verbose: bool,
-}
+    aggregation: Option<String>,
 
-/// AIのタイプ
-#[derive(Debug, Clone)]
-enum AIType {
-    BeamSearch,
-    ChainFocused,
-    ChainPotential,
-    Stable,
-    Hybrid,
-    Takapt,
-    Random,
-}
+    /// SPRT の H0 の Elo の差（`--elo1` と一緒に指定すると、2 つの AI を同じシードで戦わせて判定がつくまで続ける）
+    #[clap(long, requires = "elo1")]
+    elo0: Option<f64>,
 
-impl AIType {
-    fn from_str(s: &str) -> Option<Self> {
-        match s.to_lowercase().as_str() {
-            "beamsearch" | "beam" => Some(AIType::BeamSearch),
-            "chainfocused" | "chain" => Some(AIType::ChainFocused),
-            "chainpotential" | "potential" => Some(AIType::ChainPotential),
-            "stable" => Some(AIType::Stable),
-            "hybrid" => Some(AIType::Hybrid),
-            "takapt" => Some(AIType::Takapt),
-            "random" => Some(AIType::Random),
-            _ => None,
-        }
-    }
+    /// SPRT の H1 の Elo の差
+    #[clap(long, requires = "elo0")]
+    elo1: Option<f64>,
 
-    fn name(&self) -> &str {
-        match self {
-            AIType::BeamSearch => "BeamSearchAI",
-            AIType::ChainFocused => "ChainFocusedAI",
-            AIType::ChainPotential => "ChainPotentialAI",
-            AIType::Stable => "StableAI",
-            AIType::Hybrid => "HybridAI",
-            AIType::Takapt => "TakaptAI",
-            AIType::Random => "RandomAI",
-        }
-    }
+    /// SPRT で、H0 が正しいのに H1 を採択する確率
+    #[clap(long, default_value = "0.05")]
+    alpha: f64,
 
-    fn create_ai(&self) -> Box<dyn AI> {
-        match self {
-            AIType::BeamSearch => Box::new(BeamSearchAI::new()),
-            AIType::ChainFocused => Box::new(ChainFocusedAI::new()),
-            AIType::ChainPotential => Box::new(ChainPotentialAI::new()),
-            AIType::Stable => Box::new(StableAI::new()),
-            AIType::Hybrid => Box::new(HybridAI::new()),
-            AIType::Takapt => Box::new(TakaptAI::new()),
-            AIType::Random => Box::new(RandomAI::new()),
-        }
-    }
+    /// SPRT で、H1 が正しいのに H0 を採択する確率
+    #[clap(long, default_value = "0.05")]
+    beta: f64,
+
+    /// SPRT で判定がつかなくても打ち切る試合数（各AIごと）
+    #[clap(long, default_value = "10000")]
+    sprt_max_games: usize,
+
+    /// 詳細な結果を表示
+    #[clap(long)]
+    verbose: bool,
 }
 
 /// 統計情報
@@ -210,7 +191,8 @@ impl Logger for SilentLogger {
 }
 
 fn run_single_game(
-    ai: Box<dyn AI>,
+    ai_spec: &AISpec,
+    aggregation: Option<Aggregation>,
     seed: u32,
     max_tumos: usize,
     visible_tumos: usize,
@@ -218,6 +200,15 @@ fn run_single_game(
 ) -> GameResult {
     let start = Instant::now();
 
+    let mut ai = ai_spec.create();
+    if let Some(aggregation) = aggregation {
+        ai.set_aggregation(aggregation);
+    }
+    // 同じシードなら同じ試合が再現されるようにする（`seed=N` が指定されていればそちらを使う）
+    if ai_spec.params().seed.is_none() {
+        ai.set_seed(seed as u64);
+    }
+
     let mut logger: Box<dyn Logger> = Box::new(SilentLogger::new("benchmark", None).unwrap());
 
     let result = simulate_1p(
@@ -227,20 +218,19 @@ fn run_single_game(
         max_tumos,
         Some(seed as usize),
         Some(required_chain_score),
+        Rules::default(),
     );
 
     let time_ms = start.elapsed().as_millis();
 
     let (score, moves, max_chain, puyop_url) = match result {
         Ok(r) => {
-            // 最大連鎖を推定（log_outputから）
+            // 発火した手の見込み連鎖数の最大
             let max_chain = r
                 .json_decisions
                 .iter()
-                .filter_map(|d| {
-                    // JSONのフィールドは見えないので、とりあえず0とする
-                    None
-                })
+                .filter(|d| d.diagnostics.kind == MoveKind::Fire)
+                .map(|d| d.diagnostics.chain)
                 .max()
                 .unwrap_or(0);
 
@@ -260,57 +250,92 @@ fn run_single_game(
 }
 
 fn benchmark_ai(
-    ai_type: &AIType,
+    ai_spec: &AISpec,
     args: &Args,
+    aggregation: Option<Aggregation>,
     progress: Arc<Mutex<ProgressBar>>,
 ) -> (String, Vec<GameResult>) {
-    let mut results = Vec::new();
-
-    // ゲームを並列実行
-    let chunk_size = (args.num_games + args.parallel - 1) / args.parallel;
-    let results_mutex = Arc::new(Mutex::new(Vec::new()));
-
-    let handles: Vec<_> = (0..args.parallel)
-        .map(|thread_id| {
-            let ai_type = ai_type.clone();
-            let results_mutex = Arc::clone(&results_mutex);
-            let progress = Arc::clone(&progress);
-            let start_idx = thread_id * chunk_size;
-            let end_idx = ((thread_id + 1) * chunk_size).min(args.num_games);
-            let max_tumos = args.max_tumos;
-            let visible_tumos = args.visible_tumos;
-            let required_chain_score = args.required_chain_score;
-            let seed_start = args.seed_start;
-
-            thread::spawn(move || {
-                for i in start_idx..end_idx {
-                    let seed = seed_start + i as u32;
-                    let ai = ai_type.create_ai();
-                    let result = run_single_game(
-                        ai,
-                        seed,
-                        max_tumos,
-                        visible_tumos,
-                        required_chain_score,
-                    );
-
-                    results_mutex.lock().unwrap().push(result);
-                    progress.lock().unwrap().inc(1);
-                }
+    // ゲームを共有のワーカープールで並列実行（AI のモンテカルロも同じプールで動く）
+    let results: Vec<GameResult> = worker_pool().install(|| {
+        (0..args.num_games)
+            .into_par_iter()
+            .map(|i| {
+                let seed = args.seed_start + i as u32;
+                let result = run_single_game(
+                    ai_spec,
+                    aggregation,
+                    seed,
+                    args.max_tumos,
+                    args.visible_tumos,
+                    args.required_chain_score,
+                );
+                progress.lock().unwrap().inc(1);
+                result
             })
-        })
-        .collect();
+            .collect()
+    });
 
-    for handle in handles {
-        handle.join().unwrap();
-    }
+    (ai_spec.label().to_string(), results)
+}
 
-    results = Arc::try_unwrap(results_mutex)
-        .unwrap()
-        .into_inner()
-        .unwrap();
+/// 2 つの AI を同じシードでとこぷよさせ、得点の高い方を勝ちとして SPRT の判定がつくまで続ける
+///
+/// 1 つ目の AI から見た勝ち・引き分け・負けで検定する。
+fn run_sprt(
+    ai_specs: &[AISpec],
+    args: &Args,
+    aggregation: Option<Aggregation>,
+    sprt: Sprt,
+) -> ([Vec<GameResult>; 2], SprtOutcome) {
+    let mut results: [Vec<GameResult>; 2] = [vec![], vec![]];
+    let (mut wins, mut draws, mut losses) = (0, 0, 0);
+    let mut outcome = sprt.outcome(0.0);
+
+    // スレッド数ずつまとめて実行し、その度に判定する
+    let batch_size = worker_pool().current_num_threads();
+    while outcome.result == SprtResult::Continue && results[0].len() < args.sprt_max_games {
+        let num_games = batch_size.min(args.sprt_max_games - results[0].len());
+        let seed_start = args.seed_start + results[0].len() as u32;
+        let batch: Vec<(GameResult, GameResult)> = worker_pool().install(|| {
+            (seed_start..seed_start + num_games as u32)
+                .into_par_iter()
+                .map(|seed| {
+                    let play = |ai_spec: &AISpec| {
+                        run_single_game(
+                            ai_spec,
+                            aggregation,
+                            seed,
+                            args.max_tumos,
+                            args.visible_tumos,
+                            args.required_chain_score,
+                        )
+                    };
+                    (play(&ai_specs[0]), play(&ai_specs[1]))
+                })
+                .collect()
+        });
+
+        for (result_0, result_1) in batch {
+            match result_0.score.cmp(&result_1.score) {
+                Ordering::Greater => wins += 1,
+                Ordering::Equal => draws += 1,
+                Ordering::Less => losses += 1,
+            }
+            results[0].push(result_0);
+            results[1].push(result_1);
+        }
+        outcome = sprt.outcome(sprt.llr_trinomial(wins, draws, losses));
+        println!(
+            "  Games: {:5}  W-D-L: {}-{}-{}  {}",
+            results[0].len(),
+            wins,
+            draws,
+            losses,
+            outcome
+        );
+    }
 
-    (ai_type.name().to_string(), results)
+    (results, outcome)
 }
 
 fn print_comparison_table(stats: &[Statistics]) {
@@ -411,33 +436,72 @@ fn print_comparison_table(stats: &[Statistics]) {
 
 fn main() -> Result<()> {
     let args = Args::parse();
+    init_worker_pool(args.parallel);
 
     println!("🎮 Puyo Puyo AI Bot Comparison Tool");
     println!("=====================================");
     println!("Games per AI: {}", args.num_games);
-    println!("Parallel threads: {}", args.parallel);
+    println!("Parallel threads: {}", worker_pool().current_num_threads());
     println!("Max moves: {}", args.max_tumos);
     println!("Visible tumos: {}", args.visible_tumos);
     println!("Target score: {}", args.required_chain_score);
 
+    // モンテカルロの結果のまとめ方
+    let aggregation = match &args.aggregation {
+        Some(name) => match Aggregation::from_str(name) {
+            Some(aggregation) => Some(aggregation),
+            None => {
+                eprintln!("Error: Unknown aggregation: {}", name);
+                return Ok(());
+            }
+        },
+        None => None,
+    };
+    if let Some(aggregation) = aggregation {
+        println!("Aggregation: {:?}", aggregation);
+    }
+
     // AI設定を作成
-    let ai_types: Vec<AIType> = args
-        .ai_types
-        .split(',')
-        .filter_map(|name| AIType::from_str(name.trim()))
-        .collect();
+    let mut ai_specs: Vec<AISpec> = vec![];
+    for spec in args.ai_types.split(',') {
+        match AISpec::parse(spec) {
+            Ok(ai_spec) => ai_specs.push(ai_spec),
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                return Ok(());
+            }
+        }
+    }
 
-    if ai_types.is_empty() {
+    if ai_specs.is_empty() {
         eprintln!("Error: No valid AI types specified");
         return Ok(());
     }
 
     println!(
         "\nComparing {} AI bots: {:?}",
-        ai_types.len(),
-        ai_types.iter().map(|t| t.name()).collect::<Vec<_>>()
+        ai_specs.len(),
+        ai_specs.iter().map(|t| t.label()).collect::<Vec<_>>()
     );
 
+    // SPRT で判定がつくまで続ける場合
+    let sprt = match (args.elo0, args.elo1) {
+        (Some(elo0), Some(elo1)) => Some(Sprt::new(elo0, elo1, args.alpha, args.beta)),
+        (None, None) => None,
+        _ => {
+            eprintln!("Error: --elo0 and --elo1 must be given together");
+            return Ok(());
+        }
+    };
+    if sprt.is_some() && ai_specs.len() != 2 {
+        eprintln!("Error: SPRT needs exactly 2 AI types");
+        return Ok(());
+    }
+    if let Some(Err(e)) = sprt.map(|sprt| sprt.validate()) {
+        eprintln!("Error: {}", e);
+        return Ok(());
+    }
+
     // プログレスバーの設定
     let multi_progress = MultiProgress::new();
     let style = ProgressStyle::default_bar()
@@ -449,57 +513,77 @@ fn main() -> Result<()> {
     let mut all_results = HashMap::new();
     let mut all_stats = Vec::new();
 
-    for ai_type in &ai_types {
-        println!("\n⚡ Benchmarking: {}", ai_type.name());
-
-        let progress = Arc::new(Mutex::new(
-            multi_progress.add(ProgressBar::new(args.num_games as u64)),
-        ));
-        progress.lock().unwrap().set_style(style.clone());
-        progress
-            .lock()
-            .unwrap()
-            .set_message(format!("Running {}", ai_type.name()));
-
-        let (name, results) = benchmark_ai(ai_type, &args, progress.clone());
-
-        progress
-            .lock()
-            .unwrap()
-            .finish_with_message(format!("✅ {} complete", ai_type.name()));
-
-        let stats = Statistics::from_results(name.clone(), &results, args.required_chain_score);
-
-        if args.verbose {
-            println!("\n  Results for {}:", ai_type.name());
-            println!(
-                "    Average Score: {:.0} ± {:.0}",
-                stats.avg_score, stats.std_dev
-            );
-            println!("    Max Score: {}", stats.max_score);
-            println!("    Success Rate: {:.1}%", stats.success_rate);
-
-            // 各試行のURLを表示
-            println!("\n    Trial URLs:");
-            for (idx, result) in results.iter().enumerate() {
-                if !result.puyop_url.is_empty() {
-                    println!(
-                        "      #{:2} (score: {:6}, seed: {:4}): {}",
-                        idx + 1,
-                        result.score,
-                        result.seed,
-                        result.puyop_url
-                    );
+    let mut sprt_outcome = None;
+    if let Some(sprt) = sprt {
+        println!("\n⚡ Running SPRT: {} vs {}", ai_specs[0].label(), ai_specs[1].label());
+
+        let (results, outcome) = run_sprt(&ai_specs, &args, aggregation, sprt);
+        for (ai_spec, results) in ai_specs.iter().zip(results) {
+            let name = ai_spec.label().to_string();
+            all_stats.push(Statistics::from_results(
+                name.clone(),
+                &results,
+                args.required_chain_score,
+            ));
+            all_results.insert(name, results);
+        }
+        sprt_outcome = Some(outcome);
+    } else {
+        for ai_spec in &ai_specs {
+            println!("\n⚡ Benchmarking: {}", ai_spec.label());
+
+            let progress = Arc::new(Mutex::new(
+                multi_progress.add(ProgressBar::new(args.num_games as u64)),
+            ));
+            progress.lock().unwrap().set_style(style.clone());
+            progress
+                .lock()
+                .unwrap()
+                .set_message(format!("Running {}", ai_spec.label()));
+
+            let (name, results) = benchmark_ai(ai_spec, &args, aggregation, progress.clone());
+
+            progress
+                .lock()
+                .unwrap()
+                .finish_with_message(format!("✅ {} complete", ai_spec.label()));
+
+            let stats = Statistics::from_results(name.clone(), &results, args.required_chain_score);
+
+            if args.verbose {
+                println!("\n  Results for {}:", ai_spec.label());
+                println!(
+                    "    Average Score: {:.0} ± {:.0}",
+                    stats.avg_score, stats.std_dev
+                );
+                println!("    Max Score: {}", stats.max_score);
+                println!("    Success Rate: {:.1}%", stats.success_rate);
+
+                // 各試行のURLを表示
+                println!("\n    Trial URLs:");
+                for (idx, result) in results.iter().enumerate() {
+                    if !result.puyop_url.is_empty() {
+                        println!(
+                            "      #{:2} (score: {:6}, seed: {:4}): {}",
+                            idx + 1,
+                            result.score,
+                            result.seed,
+                            result.puyop_url
+                        );
+                    }
                 }
             }
-        }
 
-        all_stats.push(stats);
-        all_results.insert(name, results);
+            all_stats.push(stats);
+            all_results.insert(name, results);
+        }
     }
 
     // 比較表を表示
     print_comparison_table(&all_stats);
+    if let Some(sprt_outcome) = sprt_outcome {
+        println!("\n📐 SPRT: {}", sprt_outcome);
+    }
 
     // JSON出力
     if let Some(output_path) = args.output_json {
@@ -508,6 +592,7 @@ fn main() -> Result<()> {
             timestamp: String,
             args: BenchmarkArgs,
             statistics: Vec<Statistics>,
+            sprt: Option<SprtOutcome>,
             detailed_results: Option<HashMap<String, Vec<GameResult>>>,
         }
 
@@ -518,18 +603,21 @@ fn main() -> Result<()> {
             max_tumos: usize,
             visible_tumos: usize,
             required_chain_score: usize,
+            aggregation: Option<String>,
         }
 
         let benchmark_result = BenchmarkResult {
             timestamp: chrono::Utc::now().to_rfc3339(),
             args: BenchmarkArgs {
                 num_games: args.num_games,
-                parallel: args.parallel,
+                parallel: worker_pool().current_num_threads(),
                 max_tumos: args.max_tumos,
                 visible_tumos: args.visible_tumos,
                 required_chain_score: args.required_chain_score,
+                aggregation: args.aggregation.clone(),
             },
             statistics: all_stats,
+            sprt: sprt_outcome,
             detailed_results: if args.verbose {
                 Some(all_results)
             } else {
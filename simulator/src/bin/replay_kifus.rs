@@ -70,10 +70,17 @@ fn show_2p_kifu(json_path: &str) -> Result<(), Box<dyn std::error::Error>> {
         .map(|json_match| {
             let last_json_event = json_match.json_events.iter().last().unwrap();
             format!(
-                "[{}] {:6} - {:6}",
-                if json_match.won_1p { "1P" } else { "2P" },
+                "[{}] {:6} - {:6}{}",
+                if json_match.draw {
+                    "--"
+                } else if json_match.won_1p {
+                    "1P"
+                } else {
+                    "2P"
+                },
                 last_json_event.json_state_1p.score,
-                last_json_event.json_state_2p.score
+                last_json_event.json_state_2p.score,
+                if json_match.swapped { " (swapped)" } else { "" }
             )
         })
         .collect::<Vec<String>>();
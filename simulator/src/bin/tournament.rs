@@ -0,0 +1,371 @@
+use std::{
+    collections::HashSet,
+    fs::create_dir_all,
+    io::{stdout, Write},
+    path::Path,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use chrono::{DateTime, Utc};
+use clap::{error::ErrorKind, CommandFactory, Parser};
+use cpu::bot::{
+    worker_pool::{init_worker_pool, worker_pool},
+    AISpec, Rules, AI,
+};
+use ghoti_simulator::{
+    simulate_2p,
+    simulate_2p::SimulateResult2P,
+    tournament::{elo_ratings, round_robin_pairings, swiss_pairings, PairingFormat, Rating},
+};
+use logger::{Logger, NullLogger};
+use rayon::prelude::*;
+use serde::Serialize;
+
+#[derive(Parser)]
+#[clap(
+    name = "Ghoti Tournament",
+    author = "morioprog",
+    version = "v0.0.1",
+    about = "複数の AI を総当たり・スイス式で対戦させて、レーティングを出す"
+)]
+struct Opts {
+    /// 参加させる AI の指定（`Name[:key=value]*`、カンマ区切り）
+    #[clap(long, value_delimiter = ',')]
+    ai: Vec<String>,
+
+    /// 参加させる評価関数の JSON ファイル（BeamSearchAI として参加する、カンマ区切り）
+    #[clap(long, value_delimiter = ',')]
+    evaluator: Vec<String>,
+
+    /// 組み合わせの決め方（round-robin, swiss）
+    #[clap(long, default_value = "round-robin")]
+    format: String,
+
+    /// スイス式のラウンド数
+    #[clap(long, default_value = "3")]
+    rounds: usize,
+
+    /// 1 つの組み合わせで、先後それぞれ何本先取か
+    #[clap(long, default_value = "10")]
+    win_goal: usize,
+
+    /// AI に何手読みさせるか
+    #[clap(long, default_value = "2")]
+    visible_tumos: usize,
+
+    /// 配ぷよ番号（どの組み合わせも、先後入れ替えてこの番号から同じ配ぷよを使う）
+    #[clap(long, default_value = "0")]
+    haipuyo_margin: usize,
+
+    /// ルールを書いた JSON ファイル（省略時はぷよぷよ通のルール）
+    #[clap(long)]
+    rules: Option<String>,
+
+    /// 何スレッドで対戦させるか（AI の思考も含めた全体の上限、省略時は CPU のコア数）
+    #[clap(long)]
+    parallel: Option<usize>,
+
+    /// 結果を出力する JSON ファイル（省略時は `kifus/tournament/` 以下）
+    #[clap(long)]
+    output: Option<String>,
+}
+
+/// 大会に参加する AI
+#[derive(Clone)]
+struct Entrant {
+    /// 表示用の名前（`--ai` ならその指定、`--evaluator` ならファイル名）
+    name: String,
+    spec: AISpec,
+}
+
+impl Entrant {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn create_ai(&self) -> Box<dyn AI> {
+        self.spec.create()
+    }
+}
+
+/// 1 つの組み合わせ・片方の先後での対戦結果
+#[derive(Clone, Serialize)]
+struct MatchRecord {
+    round: usize,
+    player_1p: String,
+    player_2p: String,
+    win_count_1p: usize,
+    win_count_2p: usize,
+    draw_count: usize,
+}
+
+/// 2 人の間の対戦成績（行のプレイヤーから見て）
+#[derive(Clone, Copy, Default, Serialize)]
+struct CrosstableEntry {
+    wins: usize,
+    losses: usize,
+    draws: usize,
+}
+
+#[derive(Serialize)]
+struct PlayerResult {
+    name: String,
+    wins: usize,
+    losses: usize,
+    draws: usize,
+    /// スイス式の勝ち点（組み合わせごとに、勝ち 1・引き分け 0.5・不戦勝 1）
+    points: f64,
+    rating: Rating,
+}
+
+#[derive(Serialize)]
+struct TournamentResult {
+    date: DateTime<Utc>,
+    format: PairingFormat,
+    win_goal: usize,
+    visible_tumos: usize,
+    haipuyo_margin: usize,
+    rules: Rules,
+    players: Vec<PlayerResult>,
+    /// `crosstable[i][j]` は i から見た j との対戦成績
+    crosstable: Vec<Vec<CrosstableEntry>>,
+    matches: Vec<MatchRecord>,
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let opts = Opts::parse();
+    init_worker_pool(opts.parallel);
+
+    let format = match PairingFormat::from_str(&opts.format) {
+        Some(format) => format,
+        None => Opts::command()
+            .error(
+                ErrorKind::InvalidValue,
+                format!("Unknown format: {}", opts.format),
+            )
+            .exit(),
+    };
+    let rules = match &opts.rules {
+        Some(path) => match Rules::from_json_file(path) {
+            Ok(rules) => rules,
+            Err(e) => Opts::command()
+                .error(ErrorKind::ValueValidation, format!("{}: {}", path, e))
+                .exit(),
+        },
+        None => Rules::default(),
+    };
+
+    let mut entrants: Vec<Entrant> = vec![];
+    for spec in &opts.ai {
+        entrants.push(Entrant {
+            name: spec.clone(),
+            spec: AISpec::parse(spec)?,
+        });
+    }
+    for path in &opts.evaluator {
+        let name = Path::new(path)
+            .file_stem()
+            .map_or(path.clone(), |stem| stem.to_string_lossy().into_owned());
+        entrants.push(Entrant {
+            name,
+            spec: AISpec::parse(&format!("BeamSearchAI:evaluator={}", path))?,
+        });
+    }
+    if entrants.len() < 2 {
+        Opts::command()
+            .error(
+                ErrorKind::TooFewValues,
+                "At least 2 entrants are required (use --ai or --evaluator)",
+            )
+            .exit();
+    }
+
+    let n = entrants.len();
+    let mut crosstable = vec![vec![CrosstableEntry::default(); n]; n];
+    let mut points = vec![0.0; n];
+    let mut played: HashSet<(usize, usize)> = HashSet::new();
+    let mut had_bye = vec![false; n];
+    let mut matches: Vec<MatchRecord> = vec![];
+
+    let rounds = match format {
+        PairingFormat::RoundRobin => 1,
+        PairingFormat::Swiss => opts.rounds,
+    };
+    for round in 1..=rounds {
+        let pairings = match format {
+            PairingFormat::RoundRobin => round_robin_pairings(n),
+            PairingFormat::Swiss => {
+                let (pairings, bye) = swiss_pairings(&points, &played, &had_bye);
+                if let Some(bye) = bye {
+                    println!("> Round {}: {} has a bye", round, entrants[bye].name());
+                    had_bye[bye] = true;
+                    points[bye] += 1.0;
+                }
+                pairings
+            }
+        };
+
+        // 先後を入れ替えて、同じ配ぷよで対戦させる
+        let games: Vec<(usize, usize)> = pairings
+            .iter()
+            .flat_map(|&(a, b)| [(a, b), (b, a)])
+            .collect();
+        println!("> Round {}: {} games", round, games.len());
+
+        let finished = AtomicUsize::new(0);
+        let results: Vec<Result<SimulateResult2P, std::io::Error>> = worker_pool().install(|| {
+            games
+                .par_iter()
+                .map(|&(p1, p2)| {
+                    let mut logger: Box<dyn Logger> = Box::new(NullLogger::new("", None)?);
+                    let result = simulate_2p(
+                        &mut logger,
+                        &entrants[p1].create_ai(),
+                        &entrants[p2].create_ai(),
+                        opts.win_goal,
+                        opts.visible_tumos,
+                        Some(opts.haipuyo_margin),
+                        rules,
+                        None,
+                    );
+                    print!("{} ", finished.fetch_add(1, Ordering::Relaxed) + 1);
+                    stdout().flush().unwrap();
+                    result
+                })
+                .collect()
+        });
+        println!();
+
+        // 組み合わせごとの勝ち数（勝ち点の計算用）
+        let mut pairing_wins = vec![(0, 0); pairings.len()];
+        for (index, (&(p1, p2), result)) in games.iter().zip(results).enumerate() {
+            let result = result?;
+            crosstable[p1][p2].wins += result.win_count_1p;
+            crosstable[p1][p2].losses += result.win_count_2p;
+            crosstable[p1][p2].draws += result.draw_count;
+            crosstable[p2][p1].wins += result.win_count_2p;
+            crosstable[p2][p1].losses += result.win_count_1p;
+            crosstable[p2][p1].draws += result.draw_count;
+
+            // `games` は (a, b), (b, a) の順に並んでいる
+            let (wins_a, wins_b) = &mut pairing_wins[index / 2];
+            if index % 2 == 0 {
+                *wins_a += result.win_count_1p;
+                *wins_b += result.win_count_2p;
+            } else {
+                *wins_a += result.win_count_2p;
+                *wins_b += result.win_count_1p;
+            }
+
+            matches.push(MatchRecord {
+                round,
+                player_1p: entrants[p1].name(),
+                player_2p: entrants[p2].name(),
+                win_count_1p: result.win_count_1p,
+                win_count_2p: result.win_count_2p,
+                draw_count: result.draw_count,
+            });
+        }
+        for (&(a, b), &(wins_a, wins_b)) in pairings.iter().zip(&pairing_wins) {
+            played.insert((a, b));
+            if wins_a > wins_b {
+                points[a] += 1.0;
+            } else if wins_a < wins_b {
+                points[b] += 1.0;
+            } else {
+                points[a] += 0.5;
+                points[b] += 0.5;
+            }
+        }
+    }
+
+    // 引き分けは半分ずつ勝ちとして数える
+    let wins: Vec<Vec<f64>> = crosstable
+        .iter()
+        .map(|row| {
+            row.iter()
+                .map(|entry| entry.wins as f64 + entry.draws as f64 * 0.5)
+                .collect()
+        })
+        .collect();
+    let ratings = elo_ratings(&wins);
+
+    let mut players: Vec<PlayerResult> = (0..n)
+        .map(|i| PlayerResult {
+            name: entrants[i].name(),
+            wins: crosstable[i].iter().map(|entry| entry.wins).sum(),
+            losses: crosstable[i].iter().map(|entry| entry.losses).sum(),
+            draws: crosstable[i].iter().map(|entry| entry.draws).sum(),
+            points: points[i],
+            rating: ratings[i],
+        })
+        .collect();
+
+    print_crosstable(&players, &crosstable);
+
+    // レーティング順に並べて出力する（`crosstable` は参加順のまま）
+    players.sort_by(|a, b| b.rating.elo.partial_cmp(&a.rating.elo).unwrap());
+    println!();
+    for (rank, player) in players.iter().enumerate() {
+        println!(
+            "{:2}. {:<20} {:7.1} ± {:5.1} ({} - {} - {}, {:.1} pts)",
+            rank + 1,
+            player.name,
+            player.rating.elo,
+            player.rating.elo_ci95,
+            player.wins,
+            player.losses,
+            player.draws,
+            player.points
+        );
+    }
+
+    let tournament_result = TournamentResult {
+        date: Utc::now(),
+        format,
+        win_goal: opts.win_goal,
+        visible_tumos: opts.visible_tumos,
+        haipuyo_margin: opts.haipuyo_margin,
+        rules,
+        players,
+        crosstable,
+        matches,
+    };
+    let output_path = match opts.output {
+        Some(path) => path,
+        None => {
+            create_dir_all("kifus/tournament")?;
+            format!(
+                "kifus/tournament/{}.json",
+                Utc::now().format("%Y%m%d_%H%M%S_%f")
+            )
+        }
+    };
+    std::fs::write(
+        &output_path,
+        serde_json::to_string_pretty(&tournament_result)?,
+    )?;
+    println!("\nResults saved to: {}", output_path);
+
+    Ok(())
+}
+
+/// 行のプレイヤーから見た「勝ち-負け」の表を出す
+fn print_crosstable(players: &[PlayerResult], crosstable: &[Vec<CrosstableEntry>]) {
+    print!("\n{:<20}", "");
+    for j in 0..players.len() {
+        print!(" {:>7}", j + 1);
+    }
+    println!();
+    for (i, player) in players.iter().enumerate() {
+        print!("{:>2}. {:<16}", i + 1, player.name);
+        for (j, entry) in crosstable[i].iter().enumerate() {
+            if i == j {
+                print!(" {:>7}", "-");
+            } else {
+                print!(" {:>7}", format!("{}-{}", entry.wins, entry.losses));
+            }
+        }
+        println!();
+    }
+}
@@ -0,0 +1,85 @@
+use std::io::{self, BufRead, Write};
+
+use clap::Parser;
+use cpu::bot::create_ai;
+use ghoti_simulator::upi::{format_move, UpiCommand, UpiPosition};
+use puyoai::kumipuyo::Kumipuyo;
+
+#[derive(Parser)]
+#[clap(
+    name = "Ghoti UPI Engine",
+    author = "morioprog",
+    version = "v0.0.1",
+    about = "UPI プロトコルで通信する思考エンジン"
+)]
+struct Opts {
+    /// AI の指定（`Name[:key=value]*`、例: `BeamSearchAI:evaluator=evaluator.json`）
+    #[clap(long, default_value = "BeamSearchAI")]
+    ai: String,
+
+    /// AI に何手読みさせるか
+    #[clap(long, default_value = "2")]
+    visible_tumos: usize,
+}
+
+fn main() -> Result<(), std::io::Error> {
+    let opts = Opts::parse();
+
+    let ai = create_ai(&opts.ai).unwrap_or_else(|e| panic!("{}", e));
+
+    let stdin = io::stdin();
+    let mut stdout = io::stdout();
+
+    let mut haipuyo: Vec<Kumipuyo> = vec![];
+    let mut position = UpiPosition::startpos();
+
+    for line in stdin.lock().lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let command = match UpiCommand::parse(&line) {
+            Ok(command) => command,
+            Err(err) => {
+                eprintln!("{}", err);
+                continue;
+            }
+        };
+
+        match command {
+            UpiCommand::Upi => {
+                writeln!(stdout, "id name ghoti ({})", ai.name())?;
+                writeln!(stdout, "id author morioprog")?;
+                writeln!(stdout, "upiok")?;
+            }
+            UpiCommand::IsReady => writeln!(stdout, "readyok")?,
+            UpiCommand::UpiNewGame => {
+                haipuyo.clear();
+                position = UpiPosition::startpos();
+            }
+            UpiCommand::Tsumo(tumos) => haipuyo = tumos,
+            UpiCommand::Position(new_position) => position = *new_position,
+            UpiCommand::Go(think_frame) => {
+                if haipuyo.is_empty() {
+                    eprintln!("No tsumo given");
+                    continue;
+                }
+
+                let (player_state_1p, player_state_2p) =
+                    position.to_player_states(&haipuyo, opts.visible_tumos);
+                let ai_decision = ai.think(player_state_1p, Some(player_state_2p), think_frame);
+                match ai_decision.decisions.first() {
+                    Some(decision) => writeln!(stdout, "bestmove {}", format_move(decision))?,
+                    None => writeln!(stdout, "bestmove resign")?,
+                }
+            }
+            // `go` の時点で手を返しているので、何もしない
+            UpiCommand::Stop => {}
+            UpiCommand::Quit => break,
+        }
+        stdout.flush()?;
+    }
+
+    Ok(())
+}
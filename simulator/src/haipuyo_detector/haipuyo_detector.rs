@@ -99,6 +99,23 @@ impl HaipuyoDetector {
 
         HaipuyoDetector::retrieve_haipuyo(key)
     }
+
+    /// 配ぷよを `num_colors` 色（4 色まで）のものに作り直す
+    ///
+    /// 4 色ならそのまま返す。同じ配ぷよからは同じ結果になるように、先頭 8 手のハッシュをシードにする。
+    pub fn restrict_colors(seq: Vec<Kumipuyo>, num_colors: usize) -> Vec<Kumipuyo> {
+        debug_assert!((1..=4).contains(&num_colors));
+        if num_colors >= 4 {
+            return seq;
+        }
+
+        let seed = HaipuyoDetector::hash_head_8(&seq[0..8].to_vec()) as u64;
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut color = || PuyoColor::from_u32(rng.gen_range(0..num_colors as u32) + 4);
+        (0..seq.len())
+            .map(|_| Kumipuyo::new(color(), color()))
+            .collect()
+    }
 }
 
 #[cfg(test)]
@@ -132,4 +149,22 @@ mod tests {
             assert_eq!(haipuyo[i], head[i]);
         }
     }
+
+    #[test]
+    fn test_restrict_colors() {
+        let haipuyo = HaipuyoDetector::retrieve_haipuyo(24858);
+        assert_eq!(
+            HaipuyoDetector::restrict_colors(haipuyo.clone(), 4),
+            haipuyo
+        );
+
+        let restricted = HaipuyoDetector::restrict_colors(haipuyo.clone(), 3);
+        assert_eq!(restricted.len(), haipuyo.len());
+        assert!(restricted
+            .iter()
+            .all(|kumipuyo| kumipuyo.axis() != PuyoColor::GREEN
+                && kumipuyo.child() != PuyoColor::GREEN));
+        // 同じ配ぷよからは同じ結果になる
+        assert_eq!(HaipuyoDetector::restrict_colors(haipuyo, 3), restricted);
+    }
 }
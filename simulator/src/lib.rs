@@ -3,8 +3,12 @@ pub mod simulate_2p;
 
 pub mod convert;
 pub mod haipuyo_detector;
+pub mod pentanomial;
 pub mod puyop_decoder;
 pub mod puyop_parser;
+pub mod sprt;
+pub mod tournament;
+pub mod upi;
 
 pub use simulate_1p::simulate_1p;
-pub use simulate_2p::simulate_2p;
+pub use simulate_2p::{simulate_2p, simulate_2p_paired};
@@ -0,0 +1,144 @@
+//! 同じ配ぷよで先後を入れ替えた 2 試合を 1 組として、組ごとに結果を集計する
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+/// 組ごとの結果の分布（0-2, 0.5-1.5, 1-1, 1.5-0.5, 2-0 の 5 通り）
+///
+/// 1 組を 1 つの標本として分散を見積もるので、配ぷよの運の偏りが打ち消し合った分だけ
+/// 試合ごとに数えるより信頼区間が狭くなる。
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Pentanomial {
+    /// `counts[k]` は、2 試合の得点（勝ち 2・引き分け 1・負け 0）の合計が `k` だった組の数
+    pub counts: [usize; 5],
+}
+
+impl Pentanomial {
+    /// 2 試合の得点の合計が `points` だった組を追加する
+    pub fn add(&mut self, points: usize) {
+        self.counts[points] += 1;
+    }
+
+    pub fn num_pairs(&self) -> usize {
+        self.counts.iter().sum()
+    }
+
+    /// 1 試合あたりの平均得点（勝ち 1・引き分け 0.5、組が無ければ 0.5）
+    pub fn score(&self) -> f64 {
+        let num_pairs = self.num_pairs();
+        if num_pairs == 0 {
+            return 0.5;
+        }
+        let points: usize = self.counts.iter().enumerate().map(|(k, &c)| k * c).sum();
+        points as f64 / (4 * num_pairs) as f64
+    }
+
+    /// 1 組の得点（1 試合あたりに直したもの）の分散
+    pub fn variance(&self) -> f64 {
+        let num_pairs = self.num_pairs();
+        if num_pairs == 0 {
+            return 0.0;
+        }
+        let score = self.score();
+        self.counts
+            .iter()
+            .enumerate()
+            .map(|(k, &c)| c as f64 * (k as f64 / 4.0 - score).powi(2))
+            .sum::<f64>()
+            / num_pairs as f64
+    }
+
+    /// 平均得点を Elo の差に直したもの
+    pub fn elo(&self) -> f64 {
+        score_to_elo(self.score())
+    }
+
+    /// Elo の差の 95% 信頼区間（下限, 上限）
+    pub fn elo_interval95(&self) -> (f64, f64) {
+        let num_pairs = self.num_pairs();
+        if num_pairs == 0 {
+            return (f64::NEG_INFINITY, f64::INFINITY);
+        }
+        let standard_error = (self.variance() / num_pairs as f64).sqrt();
+        (
+            score_to_elo(self.score() - 1.96 * standard_error),
+            score_to_elo(self.score() + 1.96 * standard_error),
+        )
+    }
+
+    /// 2 試合の得点の合計が `points` だった組の表記
+    pub fn pair_label(points: usize) -> &'static str {
+        ["0-2", "0.5-1.5", "1-1", "1.5-0.5", "2-0"][points]
+    }
+}
+
+impl fmt::Display for Pentanomial {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let counts: Vec<String> = self
+            .counts
+            .iter()
+            .enumerate()
+            .map(|(points, count)| format!("{}: {}", Pentanomial::pair_label(points), count))
+            .collect();
+        write!(f, "{}", counts.join(", "))
+    }
+}
+
+/// 1 試合あたりの平均得点を Elo の差に直す（0 や 1 なら無限大）
+pub fn score_to_elo(score: f64) -> f64 {
+    -400.0 * (1.0 / score.clamp(0.0, 1.0) - 1.0).log10()
+}
+
+/// Elo の差から、1 試合あたりの平均得点の期待値を求める
+pub fn elo_to_score(elo: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-elo / 400.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_score_and_variance() {
+        let mut pentanomial = Pentanomial::default();
+        assert_eq!(pentanomial.score(), 0.5);
+
+        // 2-0 と 1-1 が 1 組ずつなら 3/4
+        pentanomial.add(4);
+        pentanomial.add(2);
+        assert_eq!(pentanomial.num_pairs(), 2);
+        assert_eq!(pentanomial.score(), 0.75);
+        assert_eq!(pentanomial.variance(), 0.0625);
+        assert_eq!(
+            pentanomial.to_string(),
+            "0-2: 0, 0.5-1.5: 0, 1-1: 1, 1.5-0.5: 0, 2-0: 1"
+        );
+    }
+
+    #[test]
+    fn test_elo() {
+        assert_eq!(score_to_elo(0.5), 0.0);
+        assert!((score_to_elo(0.75) + score_to_elo(0.25)).abs() < 1e-9);
+        assert_eq!(score_to_elo(1.0), f64::INFINITY);
+        assert!((elo_to_score(score_to_elo(0.75)) - 0.75).abs() < 1e-9);
+
+        // 同じ勝率でも、組の数が多いほど信頼区間は狭い
+        let few = Pentanomial {
+            counts: [1, 0, 2, 0, 2],
+        };
+        let many = Pentanomial {
+            counts: [10, 0, 20, 0, 20],
+        };
+        assert_eq!(few.elo(), many.elo());
+        let (lower, upper) = many.elo_interval95();
+        assert!(lower < many.elo() && many.elo() < upper);
+        assert!(upper - lower < few.elo_interval95().1 - few.elo_interval95().0);
+
+        // 1-1 ばかりなら、ばらつきが無いので幅は 0
+        let even = Pentanomial {
+            counts: [0, 0, 10, 0, 0],
+        };
+        assert_eq!(even.elo_interval95(), (0.0, 0.0));
+    }
+}
@@ -13,7 +13,7 @@ use puyoai::{
 use serde::{Deserialize, Serialize};
 use serde_with::serde_as;
 
-use super::{convert::convert_kumipuyo_seq, haipuyo_detector::*};
+use super::{convert::convert_kumipuyo_seq, haipuyo_detector::*, simulate_2p::validate_rules};
 
 pub fn simulate_1p(
     logger: &mut Box<dyn Logger>,
@@ -22,7 +22,9 @@ pub fn simulate_1p(
     max_tumos: usize,
     haipuyo_margin: Option<usize>, // Noneならランダムに、Someならseed値
     required_chain_score: Option<usize>, // この得点以上の連鎖が打たれたら終了
+    rules: Rules,
 ) -> Result<SimulateResult1P, std::io::Error> {
+    validate_rules(&rules)?;
     logger.print(format!("> AI: {} ({:3}手読み)\n", ai.name(), visible_tumos))?;
 
     // TODO: フレームを更新する
@@ -30,7 +32,8 @@ pub fn simulate_1p(
         None => HaipuyoDetector::random_haipuyo(),
         Some(margin) => HaipuyoDetector::random_haipuyo_with_seed(margin as u64),
     };
-    let mut player_state = PlayerState::initial_state(vec![], Some(seq.clone()));
+    let seq = HaipuyoDetector::restrict_colors(seq, rules.num_colors);
+    let mut player_state = PlayerState::initial_state(vec![], Some(seq.clone())).with_rules(rules);
 
     let mut ai_decisions: Vec<AIDecision> = vec![];
     let mut decisions: Vec<Decision> = vec![];
@@ -77,7 +80,7 @@ pub fn simulate_1p(
         player_state.tumo_index += 1;
     }
 
-    let ret = SimulateResult1P::new(score, visible_tumos, &seq, &decisions, &ai_decisions);
+    let ret = SimulateResult1P::new(score, visible_tumos, rules, &seq, &decisions, &ai_decisions);
     logger.print(ret.url.clone())?;
 
     Ok(ret)
@@ -90,6 +93,9 @@ pub struct JsonDecision {
     log_output: String,
     #[serde_as(as = "Vec<DecisionDef>")]
     decisions: Vec<Decision>,
+    /// 古い棋譜には無いので、その場合は空にする
+    #[serde(default)]
+    pub diagnostics: AIDiagnostics,
 }
 
 #[derive(Clone, Serialize, Deserialize)]
@@ -97,6 +103,9 @@ pub struct SimulateResult1P {
     date: DateTime<Utc>,
     pub score: usize,
     visible_tumos: usize,
+    /// 古い棋譜には無いので、その場合はぷよぷよ通のルールにする
+    #[serde(default)]
+    rules: Rules,
     tumos: Vec<String>, // ["RR", "YG", ...]
     pub json_decisions: Vec<JsonDecision>,
     pub url: String,
@@ -106,6 +115,7 @@ impl SimulateResult1P {
     fn new(
         score: usize,
         visible_tumos: usize,
+        rules: Rules,
         seq: &Vec<Kumipuyo>,
         decisions: &Vec<Decision>,
         ai_decisions: &Vec<AIDecision>,
@@ -119,6 +129,7 @@ impl SimulateResult1P {
                     think_ms: ai_decision.elapsed.as_millis(),
                     log_output: ai_decision.log_output.clone(),
                     decisions: ai_decision.decisions.clone(),
+                    diagnostics: ai_decision.diagnostics.clone(),
                 });
             }
             json_decisions
@@ -128,6 +139,7 @@ impl SimulateResult1P {
             date: Utc::now(),
             score,
             visible_tumos,
+            rules,
             tumos,
             json_decisions,
             url,
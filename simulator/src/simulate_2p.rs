@@ -1,10 +1,3 @@
-/*
-   TODO
-   - 同フレームのやつが2個（1Pと2P）並んでたら、後にある方を処理する前に盤面の更新を行わないこと（同時なので）
-   - 同時に死んだら引き分けにするべき
-   - 全消しの考慮
-*/
-
 use std::{
     collections::BinaryHeap,
     fs::{create_dir_all, File},
@@ -12,22 +5,21 @@ use std::{
 };
 
 use chrono::{DateTime, Utc};
-use cpu::bot::{PlayerState, AI};
+use cpu::bot::{elapsed_frames, PlayerState, Rules, AI};
 use logger::Logger;
 use puyoai::{
     decision::Decision, es_field::EsCoreField, es_frame, field::CoreField, field_bit::FieldBit,
-    rensa_tracker::RensaNonTracker,
+    kumipuyo::Kumipuyo, rensa_tracker::RensaNonTracker,
 };
 use serde::{Deserialize, Serialize};
 
 use super::{
     convert::{convert_core_field, convert_kumipuyo_seq},
     haipuyo_detector::*,
+    pentanomial::Pentanomial,
+    sprt::{Sprt, SprtOutcome, SprtResult},
 };
 
-// TODO: マージンの実装
-const OJAMA_PUYO_RATE: usize = 70;
-
 pub fn simulate_2p(
     logger: &mut Box<dyn Logger>,
     ai_1p: &Box<dyn AI>,
@@ -36,10 +28,16 @@ pub fn simulate_2p(
     visible_tumos: usize,
     // FIXME: 序盤数手が同じになってしまう
     haipuyo_margin: Option<usize>, // Noneならランダムに、Someならその番号から順番に使う
+    rules: Rules,
+    real_time: Option<RealTime>, // Noneなら思考時間はフレーム数に含めない
 ) -> Result<SimulateResult2P, std::io::Error> {
-    // お互いの勝利数
+    validate_rules(&rules)?;
+    validate_real_time(real_time)?;
+
+    // お互いの勝利数と、引き分けの数
     let mut win_count_1p: usize = 0;
     let mut win_count_2p: usize = 0;
+    let mut draw_count: usize = 0;
 
     // 各試合の詳細
     let mut json_matches: Vec<JsonMatch> = vec![];
@@ -49,44 +47,272 @@ pub fn simulate_2p(
         let seq = match haipuyo_margin {
             None => HaipuyoDetector::random_haipuyo(),
             Some(margin) => HaipuyoDetector::retrieve_haipuyo(
-                (margin + win_count_1p + win_count_2p) % TUMO_PATTERN,
+                (margin + win_count_1p + win_count_2p + draw_count) % TUMO_PATTERN,
             ),
         };
+        let seq = HaipuyoDetector::restrict_colors(seq, rules.num_colors);
 
-        // 各プレイヤーの状態
-        let mut player_state_1p = PlayerState::initial_state(vec![], Some(seq.clone()));
-        let mut player_state_2p = PlayerState::initial_state(vec![], Some(seq.clone()));
-        player_state_1p.set_seq(visible_tumos);
-        player_state_2p.set_seq(visible_tumos);
-
-        // この試合で起きたイベント
-        let mut json_events: Vec<JsonEvent> = vec![];
-        // どっちが勝ったか
-        let winner_player: Option<Player>;
-
-        // 初期盤面をpush
-        json_events.push(JsonEvent {
-            frame: 0,
-            json_state_1p: player_state_1p.clone().into(),
-            json_state_2p: player_state_2p.clone().into(),
-        });
+        let match_result = play_match(
+            ai_1p,
+            ai_2p,
+            &seq,
+            win_count_1p + win_count_2p,
+            visible_tumos,
+            rules,
+            real_time,
+        );
 
-        // 処理すべき各イベント
-        let mut events = BinaryHeap::new();
-        events.push(Event::new(0, Player::One, None, false));
-        events.push(Event::new(0, Player::Two, None, false));
+        // 結果を更新
+        match match_result.winner {
+            Some(Player::One) => {
+                logger.print("1P won! ".into())?;
+                win_count_1p += 1;
+            }
+            Some(Player::Two) => {
+                logger.print("2P won! ".into())?;
+                win_count_2p += 1;
+            }
+            None => {
+                logger.print("Draw!   ".into())?;
+                draw_count += 1;
+            }
+        }
+        logger.print(format!(
+            "{:3} vs {:3} ({:6} - {:6})\n",
+            win_count_1p, win_count_2p, match_result.score_1p, match_result.score_2p
+        ))?;
+
+        // この試合の結果をpush
+        json_matches.push(JsonMatch {
+            won_1p: match_result.winner == Some(Player::One),
+            draw: match_result.winner.is_none(),
+            swapped: false,
+            tumos: convert_kumipuyo_seq(&seq),
+            json_events: match_result.json_events,
+        })
+    }
+
+    logger.print(format!(
+        "Result: {:3} vs {:3} (draws: {})\n",
+        win_count_1p, win_count_2p, draw_count
+    ))?;
+
+    Ok(SimulateResult2P::new(
+        win_count_1p,
+        win_count_2p,
+        draw_count,
+        visible_tumos,
+        rules,
+        json_matches,
+    ))
+}
+
+/// 同じ配ぷよで先後を入れ替えた 2 試合を 1 組として、`num_pairs` 組対戦させる
+///
+/// 配ぷよの運による偏りが 1 組の中で打ち消し合うので、少ない試合数で強さの差が分かる。
+/// `win_count_1p` / `win_count_2p` は席ではなく `ai_1p` / `ai_2p` の勝利数で、
+/// 組ごとの結果は `pentanomial` に入る。
+/// `sprt` を渡すと、`ai_1p` から見た検定の判定がついた時点で打ち切る。
+pub fn simulate_2p_paired(
+    logger: &mut Box<dyn Logger>,
+    ai_1p: &Box<dyn AI>,
+    ai_2p: &Box<dyn AI>,
+    num_pairs: usize,
+    sprt: Option<Sprt>,
+    visible_tumos: usize,
+    haipuyo_margin: Option<usize>, // Noneならランダムに、Someならその番号から順番に使う
+    rules: Rules,
+    real_time: Option<RealTime>, // Noneなら思考時間はフレーム数に含めない
+) -> Result<SimulateResult2P, std::io::Error> {
+    validate_rules(&rules)?;
+    validate_real_time(real_time)?;
+
+    let mut win_count_1p: usize = 0;
+    let mut win_count_2p: usize = 0;
+    let mut draw_count: usize = 0;
+    let mut pentanomial = Pentanomial::default();
+    let mut sprt_outcome: Option<SprtOutcome> = None;
+    let mut json_matches: Vec<JsonMatch> = vec![];
+
+    for pair_index in 0..num_pairs {
+        let seq = match haipuyo_margin {
+            None => HaipuyoDetector::random_haipuyo(),
+            Some(margin) => HaipuyoDetector::retrieve_haipuyo((margin + pair_index) % TUMO_PATTERN),
+        };
+        let seq = HaipuyoDetector::restrict_colors(seq, rules.num_colors);
 
-        // どちらかが死ぬまで続ける
-        'battle: loop {
-            let event = match events.pop() {
-                Some(event) => event,
-                None => unreachable!(),
+        // この組での `ai_1p` の得点（勝ち 2・引き分け 1）
+        let mut points = 0;
+        for swapped in [false, true] {
+            let (ai_first, ai_second) = if swapped {
+                (ai_2p, ai_1p)
+            } else {
+                (ai_1p, ai_2p)
             };
+            // おじゃまぷよの降り方も 2 試合で同じにする
+            let match_result = play_match(
+                ai_first,
+                ai_second,
+                &seq,
+                pair_index,
+                visible_tumos,
+                rules,
+                real_time,
+            );
+
+            // `ai_1p` から見た勝ち負け
+            match match_result.winner {
+                None => {
+                    draw_count += 1;
+                    points += 1;
+                }
+                Some(winner) if (winner == Player::One) != swapped => {
+                    win_count_1p += 1;
+                    points += 2;
+                }
+                Some(_) => win_count_2p += 1,
+            }
+
+            json_matches.push(JsonMatch {
+                won_1p: match_result.winner == Some(Player::One),
+                draw: match_result.winner.is_none(),
+                swapped,
+                tumos: convert_kumipuyo_seq(&seq),
+                json_events: match_result.json_events,
+            });
+        }
+        pentanomial.add(points);
 
-            let (player_state_myself, player_state_opponent) = if event.player == Player::One {
-                (&mut player_state_1p, &mut player_state_2p)
+        logger.print(format!(
+            "Pair {:3}: {:7} ({:3} vs {:3}, draws: {})\n",
+            pair_index + 1,
+            Pentanomial::pair_label(points),
+            win_count_1p,
+            win_count_2p,
+            draw_count
+        ))?;
+
+        if let Some(sprt) = sprt {
+            let outcome = sprt.outcome(sprt.llr_pentanomial(&pentanomial));
+            sprt_outcome = Some(outcome);
+            if outcome.result != SprtResult::Continue {
+                break;
+            }
+        }
+    }
+
+    if let Some(sprt_outcome) = sprt_outcome {
+        logger.print(format!("SPRT: {}\n", sprt_outcome))?;
+    }
+    let (elo_lower, elo_upper) = pentanomial.elo_interval95();
+    logger.print(format!(
+        "Result: {:3} vs {:3} (draws: {})\nPairs: {}\nElo: {:+.1} [{:+.1}, {:+.1}]\n",
+        win_count_1p,
+        win_count_2p,
+        draw_count,
+        pentanomial,
+        pentanomial.elo(),
+        elo_lower,
+        elo_upper
+    ))?;
+
+    let mut simulate_result = SimulateResult2P::new(
+        win_count_1p,
+        win_count_2p,
+        draw_count,
+        visible_tumos,
+        rules,
+        json_matches,
+    );
+    simulate_result.pentanomial = Some(pentanomial);
+    simulate_result.sprt = sprt_outcome;
+    Ok(simulate_result)
+}
+
+/// `seq` の配ぷよで 1 試合させる
+///
+/// `game_index` は、おじゃまぷよが降る位置の乱数に使う。
+fn play_match(
+    ai_1p: &Box<dyn AI>,
+    ai_2p: &Box<dyn AI>,
+    seq: &[Kumipuyo],
+    game_index: usize,
+    visible_tumos: usize,
+    rules: Rules,
+    real_time: Option<RealTime>,
+) -> MatchResult {
+    // 各プレイヤーの状態
+    let mut player_state_1p =
+        PlayerState::initial_state(vec![], Some(seq.to_vec())).with_rules(rules);
+    let mut player_state_2p =
+        PlayerState::initial_state(vec![], Some(seq.to_vec())).with_rules(rules);
+    player_state_1p.set_seq(visible_tumos);
+    player_state_2p.set_seq(visible_tumos);
+
+    // この試合で起きたイベント
+    let mut json_events: Vec<JsonEvent> = vec![];
+    // どっちが勝ったか（`None` なら引き分け）
+    let winner: Option<Player>;
+
+    // 初期盤面をpush
+    json_events.push(JsonEvent {
+        frame: 0,
+        json_state_1p: player_state_1p.clone().into(),
+        json_state_2p: player_state_2p.clone().into(),
+    });
+
+    // 処理すべき各イベント
+    let mut events = BinaryHeap::new();
+    events.push(Event::new(0, Player::One, None, false));
+    events.push(Event::new(0, Player::Two, None, false));
+
+    // 降っている途中のおじゃまぷよ
+    let mut falling_ojama_1p: Option<FallingOjama> = None;
+    let mut falling_ojama_2p: Option<FallingOjama> = None;
+
+    // どちらかが死ぬまで続ける
+    loop {
+        // 同じフレームのイベントをまとめて取り出す（同時に起きたものとして処理する）
+        let frame = match events.peek() {
+            Some(event) => event.frame,
+            None => unreachable!(),
+        };
+        let mut frame_events = vec![];
+        while events.peek().is_some_and(|event| event.frame == frame) {
+            frame_events.push(events.pop().unwrap());
+        }
+
+        // 相手の状態は、このフレームの処理を始める前のものを見る
+        // （おじゃまぷよが降っている途中なら、降る前の盤面しか見えない）
+        let snapshot_1p = visible_state(&player_state_1p, &falling_ojama_1p, frame);
+        let snapshot_2p = visible_state(&player_state_2p, &falling_ojama_2p, frame);
+        // 相手への影響は、このフレームのイベントを全部処理してから反映する
+        let mut effects_1p = FrameEffects::default();
+        let mut effects_2p = FrameEffects::default();
+
+        for event in frame_events {
+            let (
+                player_state_myself,
+                player_state_opponent,
+                snapshot_opponent,
+                effects,
+                falling_ojama_myself,
+            ) = if event.player == Player::One {
+                (
+                    &mut player_state_1p,
+                    &player_state_2p,
+                    &snapshot_2p,
+                    &mut effects_1p,
+                    &mut falling_ojama_1p,
+                )
             } else {
-                (&mut player_state_2p, &mut player_state_1p)
+                (
+                    &mut player_state_2p,
+                    &player_state_1p,
+                    &snapshot_1p,
+                    &mut effects_2p,
+                    &mut falling_ojama_2p,
+                )
             };
             let ai = if event.player == Player::One {
                 ai_1p
@@ -135,27 +361,10 @@ pub fn simulate_2p(
                     player_state_myself.current_chain,
                 );
                 if chain_score != 0 {
-                    // おじゃまを 1 連鎖分処理
-                    player_state_myself.carry_over += chain_score;
-                    let mut ojama = player_state_myself.carry_over / OJAMA_PUYO_RATE;
-                    player_state_myself.carry_over %= OJAMA_PUYO_RATE;
-
-                    // 相殺に当てる
-                    if ojama > 0 && player_state_myself.fixed_ojama > 0 {
-                        let pay = ojama.min(player_state_myself.fixed_ojama);
-                        ojama -= pay;
-                        player_state_myself.fixed_ojama -= pay;
-                    }
-                    if ojama > 0 && player_state_myself.pending_ojama > 0 {
-                        let pay = ojama.min(player_state_myself.pending_ojama);
-                        ojama -= pay;
-                        player_state_myself.pending_ojama -= pay;
-                    }
-
-                    // 余った分は相手に送る
-                    if ojama > 0 {
-                        player_state_opponent.pending_ojama += ojama;
-                    }
+                    // おじゃまを 1 連鎖分処理し、相殺して余った分は相手に送る
+                    let ojama_rate = rules.ojama_rate_at(event.frame);
+                    effects.sent_ojama +=
+                        offset_ojama(player_state_myself, chain_score, ojama_rate);
 
                     // 自身の状態を更新
                     player_state_myself.score += chain_score;
@@ -173,35 +382,38 @@ pub fn simulate_2p(
                 }
             }
 
+            // 連鎖が終わって盤面が空になっていたら、全消しボーナスを持たせる
+            if player_state_myself.current_chain > 1 && player_state_myself.field.is_zenkeshi() {
+                player_state_myself.zenkeshi = true;
+            }
+
             // 連鎖が発生していないので 0 に戻す
             player_state_myself.current_chain = 0;
 
             // おじゃまを降らせる
             if !event.force_think && player_state_myself.fixed_ojama > 0 {
-                // 30個以上だったら30個、そうでないならその分降らせる
-                let ojama = if player_state_myself.fixed_ojama >= 30 {
-                    30
-                } else {
-                    player_state_myself.fixed_ojama
-                };
+                // 一度に降る上限（通なら30個）以上だったら上限まで、そうでないならその分降らせる
+                let ojama = player_state_myself.fixed_ojama.min(rules.ojama_per_drop);
                 player_state_myself.fixed_ojama -= ojama;
 
+                let field_before = player_state_myself.field.clone();
                 let ojama_drop_frame = player_state_myself.field.es_drop_ojama(
                     ojama,
                     Some(
-                        ((win_count_1p
-                            + win_count_2p
-                            + player_state_myself.score
-                            + player_state_opponent.score
-                            + ojama)
+                        ((game_index + player_state_myself.score + snapshot_opponent.score + ojama)
                             & 0b11111111) as u8,
                     ),
                 );
 
                 // フレームを更新
-                // TODO: おじゃまの降る位置がかなり早い段階（降り始めたタイミング）で凝視できるようになってしまっている
                 player_state_myself.frame += ojama_drop_frame;
 
+                // 降り終わるまでは、相手には降る前の盤面を見せる
+                *falling_ojama_myself = Some(FallingOjama {
+                    until_frame: player_state_myself.frame,
+                    field_before,
+                });
+
                 events.push(Event::new(
                     player_state_myself.frame,
                     event.player,
@@ -215,74 +427,78 @@ pub fn simulate_2p(
                 continue;
             }
 
-            // ぷよを置いて / おじゃまが降って、自陣が死んでたら終了
-            // TODO: 同時に死ぬ場合がありうる
+            // ぷよを置いて / おじゃまが降って、自陣が死んでたら、このフレームの処理が終わってから判定する
             if player_state_myself.field.is_dead() {
-                winner_player = Some(event.player.opponent());
-                break 'battle;
+                effects.dead = true;
+                continue;
             }
 
             // 思考する前に相手の予告ぷよを確定させる
-            if player_state_opponent.pending_ojama > 0 {
-                player_state_opponent.fixed_ojama += player_state_opponent.pending_ojama;
-                player_state_opponent.pending_ojama = 0;
-            }
+            effects.fix_pending_ojama = true;
 
-            // AIで思考する
-            // TODO: 引数で `think_frame` を渡す？
+            // AIで思考する（相手の予告ぷよは確定させた状態で見せる）
+            let mut player_state_opponent_fixed = snapshot_opponent.clone();
+            effects.apply_to(&mut player_state_opponent_fixed);
             let ai_decision = ai.think(
                 player_state_myself.clone(),
-                Some(player_state_opponent.clone()),
-                None,
+                Some(player_state_opponent_fixed),
+                real_time.and_then(|real_time| real_time.think_frame),
             );
             let decision = ai_decision.decisions[0].clone();
+            // リアルタイムなら、考えている間もゲームは進む
+            let think_frames = if real_time.is_some() {
+                elapsed_frames(ai_decision.elapsed)
+            } else {
+                0
+            };
             events.push(Event::new(
                 // そこに置くのに必要なフレーム数を加算
-                event.frame + player_state_myself.field.es_frames_to_drop_next(&decision),
+                event.frame
+                    + think_frames
+                    + player_state_myself.field.es_frames_to_drop_next(&decision),
                 event.player,
                 Some(decision),
                 false,
             ));
         }
 
-        // 結果を更新
-        match winner_player {
-            Some(player) => match player {
-                Player::One => {
-                    logger.print("1P won! ".into())?;
-                    win_count_1p += 1;
-                }
-                Player::Two => {
-                    logger.print("2P won! ".into())?;
-                    win_count_2p += 1;
-                }
-            },
-            None => unreachable!(),
-        }
-        logger.print(format!(
-            "{:3} vs {:3} ({:6} - {:6})\n",
-            win_count_1p, win_count_2p, player_state_1p.score, player_state_2p.score
-        ))?;
+        // 相手への影響をまとめて反映する
+        effects_1p.apply_to(&mut player_state_2p);
+        effects_2p.apply_to(&mut player_state_1p);
 
-        // この試合の結果をpush
-        json_matches.push(JsonMatch {
-            won_1p: winner_player.unwrap() == Player::One,
-            tumos: convert_kumipuyo_seq(&seq),
-            json_events,
-        })
+        // 同時に死んだら引き分け
+        match (effects_1p.dead, effects_2p.dead) {
+            (false, false) => {}
+            (true, true) => {
+                winner = None;
+                break;
+            }
+            (true, false) => {
+                winner = Some(Player::Two);
+                break;
+            }
+            (false, true) => {
+                winner = Some(Player::One);
+                break;
+            }
+        }
     }
 
-    logger.print(format!(
-        "Result: {:3} vs {:3}\n",
-        win_count_1p, win_count_2p
-    ))?;
+    MatchResult {
+        winner,
+        score_1p: player_state_1p.score,
+        score_2p: player_state_2p.score,
+        json_events,
+    }
+}
 
-    Ok(SimulateResult2P::new(
-        win_count_1p,
-        win_count_2p,
-        visible_tumos,
-        json_matches,
-    ))
+/// 1 試合分の結果
+struct MatchResult {
+    /// どっちが勝ったか（`None` なら引き分け）
+    winner: Option<Player>,
+    score_1p: usize,
+    score_2p: usize,
+    json_events: Vec<JsonEvent>,
 }
 
 #[derive(Clone, Serialize, Deserialize)]
@@ -293,6 +509,8 @@ pub struct JsonState {
     pub ojama_fixed: usize,   // 確定おじゃまぷよ
     pub ojama_ongoing: usize, // 予告おじゃまぷよ
     pub current_chain: usize, // 現在の連鎖数
+    #[serde(default)]
+    pub zenkeshi: bool, // 全消しボーナスを持っているか
 }
 
 impl From<PlayerState> for JsonState {
@@ -304,6 +522,7 @@ impl From<PlayerState> for JsonState {
             ojama_fixed: player_state.fixed_ojama,
             ojama_ongoing: player_state.pending_ojama,
             current_chain: player_state.current_chain,
+            zenkeshi: player_state.zenkeshi,
         }
     }
 }
@@ -318,6 +537,12 @@ pub struct JsonEvent {
 #[derive(Clone, Serialize, Deserialize)]
 pub struct JsonMatch {
     pub won_1p: bool,
+    /// 同時に死んだ（このとき `won_1p` は `false`）
+    #[serde(default)]
+    pub draw: bool,
+    /// 先後を入れ替えた試合（`ai_2p` が 1P 側、`won_1p` は席から見た勝敗）
+    #[serde(default)]
+    pub swapped: bool,
     pub tumos: Vec<String>,
     pub json_events: Vec<JsonEvent>,
 }
@@ -327,7 +552,18 @@ pub struct SimulateResult2P {
     pub date: DateTime<Utc>,
     pub win_count_1p: usize,
     pub win_count_2p: usize,
+    #[serde(default)]
+    pub draw_count: usize,
     pub visible_tumos: usize,
+    /// 古い棋譜には無いので、その場合はぷよぷよ通のルールにする
+    #[serde(default)]
+    pub rules: Rules,
+    /// 先後を入れ替えて対戦させたときの、組ごとの結果（`ai_1p` から見たもの）
+    #[serde(default)]
+    pub pentanomial: Option<Pentanomial>,
+    /// SPRT で打ち切ったときの、最後の LLR と判定
+    #[serde(default)]
+    pub sprt: Option<SprtOutcome>,
     pub json_matches: Vec<JsonMatch>,
 }
 
@@ -335,14 +571,20 @@ impl SimulateResult2P {
     fn new(
         win_count_1p: usize,
         win_count_2p: usize,
+        draw_count: usize,
         visible_tumos: usize,
+        rules: Rules,
         json_matches: Vec<JsonMatch>,
     ) -> Self {
         SimulateResult2P {
             date: Utc::now(),
             win_count_1p,
             win_count_2p,
+            draw_count,
             visible_tumos,
+            rules,
+            pentanomial: None,
+            sprt: None,
             json_matches,
         }
     }
@@ -387,6 +629,81 @@ impl Player {
     }
 }
 
+/// あるフレームで、あるプレイヤーが相手に及ぼす影響（フレームの最後にまとめて反映する）
+#[derive(Default)]
+struct FrameEffects {
+    /// 相手に送ったおじゃまぷよ
+    sent_ojama: usize,
+    /// 相手の予告おじゃまぷよを確定させるか
+    fix_pending_ojama: bool,
+    /// 自陣が死んだか
+    dead: bool,
+}
+
+impl FrameEffects {
+    fn apply_to(&self, player_state_opponent: &mut PlayerState) {
+        player_state_opponent.pending_ojama += self.sent_ojama;
+        if self.fix_pending_ojama {
+            player_state_opponent.fixed_ojama += player_state_opponent.pending_ojama;
+            player_state_opponent.pending_ojama = 0;
+        }
+    }
+}
+
+/// シミュレータで使えないルールなら `InvalidInput` のエラーにする
+pub(crate) fn validate_rules(rules: &Rules) -> Result<(), std::io::Error> {
+    rules
+        .validate()
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))
+}
+
+/// リアルタイムの試合を、スレッドプールの中から走らせようとしていないか
+///
+/// プールの中で走らせると、他の試合と同時に走ってしまう。
+pub(crate) fn validate_real_time(real_time: Option<RealTime>) -> Result<(), std::io::Error> {
+    if real_time.is_some() && rayon::current_thread_index().is_some() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "real-time matches must be run one at a time, outside the worker pool",
+        ));
+    }
+    Ok(())
+}
+
+/// リアルタイムで対戦させる設定（思考にかかった時間をフレーム数に換算し、その分だけ置くのが遅れる）
+///
+/// 思考時間は壁時計で測り、AI のモンテカルロは共有のワーカープールで動く。
+/// 他の試合と同時に走らせるとプールの取り合いで思考時間が変わってしまうので、
+/// 呼び出し側で 1 試合ずつ、ワーカープールの外から走らせること（プールの中から呼ぶとエラーになる）。
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RealTime {
+    /// AI に渡す思考フレーム数（`None` なら AI に任せる）
+    pub think_frame: Option<usize>,
+}
+
+/// 降っている途中のおじゃまぷよ
+struct FallingOjama {
+    /// 降り終わるフレーム数
+    until_frame: usize,
+    /// 降り始める前の盤面
+    field_before: CoreField,
+}
+
+/// `frame` 時点で相手から見える状態（おじゃまぷよが降っている途中なら、盤面は降る前のもの）
+fn visible_state(
+    player_state: &PlayerState,
+    falling_ojama: &Option<FallingOjama>,
+    frame: usize,
+) -> PlayerState {
+    let mut player_state = player_state.clone();
+    if let Some(falling_ojama) = falling_ojama {
+        if frame < falling_ojama.until_frame {
+            player_state.field = falling_ojama.field_before.clone();
+        }
+    }
+    player_state
+}
+
 struct Event {
     /// 現在のフレーム数
     frame: usize,
@@ -427,6 +744,33 @@ impl PartialEq for Event {
 }
 impl Eq for Event {}
 
+/// 1 連鎖分の点数をおじゃまぷよに変換し、自分のおじゃまと相殺して余った（相手に送る）数を返す
+///
+/// 全消しボーナスを持っていたら、ここで点数に上乗せして使い切る。
+fn offset_ojama(player_state: &mut PlayerState, chain_score: usize, ojama_rate: usize) -> usize {
+    player_state.carry_over += chain_score;
+    if player_state.zenkeshi {
+        player_state.carry_over += player_state.rules.zenkeshi_bonus;
+        player_state.zenkeshi = false;
+    }
+    let mut ojama = player_state.carry_over / ojama_rate;
+    player_state.carry_over %= ojama_rate;
+
+    // 相殺に当てる
+    if ojama > 0 && player_state.fixed_ojama > 0 {
+        let pay = ojama.min(player_state.fixed_ojama);
+        ojama -= pay;
+        player_state.fixed_ojama -= pay;
+    }
+    if ojama > 0 && player_state.pending_ojama > 0 {
+        let pay = ojama.min(player_state.pending_ojama);
+        ojama -= pay;
+        player_state.pending_ojama -= pay;
+    }
+
+    ojama
+}
+
 /// 1 連鎖分進めて (点数, フレーム数) を返す
 fn vanish_single_chain(cf: &mut CoreField, current_chain: usize) -> (usize, usize) {
     let escaped = cf.field_mut().escape_invisible();
@@ -456,8 +800,157 @@ fn vanish_single_chain(cf: &mut CoreField, current_chain: usize) -> (usize, usiz
 
 #[cfg(test)]
 mod tests {
+    use cpu::bot::{worker_pool::worker_pool, MarginTime, RandomAI};
+    use logger::NullLogger;
+
     use super::*;
 
+    #[test]
+    fn test_simulate_2p_paired() {
+        let mut logger: Box<dyn Logger> = Box::new(NullLogger::new("", None).unwrap());
+        let ai: Box<dyn AI> = Box::new(RandomAI::new());
+        let simulate_result = simulate_2p_paired(
+            &mut logger,
+            &ai,
+            &ai,
+            2,
+            None,
+            2,
+            Some(0),
+            Rules::default(),
+            None,
+        )
+        .unwrap();
+
+        // 1 組 2 試合で、2 試合目は同じ配ぷよで先後を入れ替える
+        let json_matches = &simulate_result.json_matches;
+        assert_eq!(
+            json_matches.iter().map(|m| m.swapped).collect::<Vec<_>>(),
+            vec![false, true, false, true]
+        );
+        assert_eq!(json_matches[0].tumos, json_matches[1].tumos);
+        assert_eq!(
+            simulate_result.win_count_1p
+                + simulate_result.win_count_2p
+                + simulate_result.draw_count,
+            4
+        );
+        assert_eq!(simulate_result.pentanomial.unwrap().num_pairs(), 2);
+    }
+
+    #[test]
+    fn test_real_time_in_worker_pool() {
+        // リアルタイムの試合は、ワーカープールの中からは走らせない
+        let simulate_result = worker_pool().install(|| {
+            let mut logger: Box<dyn Logger> = Box::new(NullLogger::new("", None).unwrap());
+            let ai: Box<dyn AI> = Box::new(RandomAI::new());
+            simulate_2p(
+                &mut logger,
+                &ai,
+                &ai,
+                1,
+                2,
+                Some(0),
+                Rules::default(),
+                Some(RealTime::default()),
+            )
+        });
+        assert_eq!(
+            simulate_result.unwrap_err().kind(),
+            std::io::ErrorKind::InvalidInput
+        );
+    }
+
+    #[test]
+    fn test_frame_effects() {
+        let mut player_state = PlayerState::zero();
+        player_state.fixed_ojama = 3;
+        player_state.pending_ojama = 5;
+
+        // 送っただけなら予告のまま
+        FrameEffects {
+            sent_ojama: 10,
+            ..Default::default()
+        }
+        .apply_to(&mut player_state);
+        assert_eq!(player_state.fixed_ojama, 3);
+        assert_eq!(player_state.pending_ojama, 15);
+
+        // 確定させると予告が全部確定する
+        FrameEffects {
+            fix_pending_ojama: true,
+            ..Default::default()
+        }
+        .apply_to(&mut player_state);
+        assert_eq!(player_state.fixed_ojama, 18);
+        assert_eq!(player_state.pending_ojama, 0);
+    }
+
+    #[test]
+    fn test_offset_ojama() {
+        let mut player_state = PlayerState::zero();
+        player_state.fixed_ojama = 3;
+        player_state.pending_ojama = 5;
+
+        // 端数は持ち越し、確定 → 予告の順に相殺する
+        assert_eq!(offset_ojama(&mut player_state, 500, 70), 0);
+        assert_eq!(player_state.carry_over, 10);
+        assert_eq!(player_state.fixed_ojama, 0);
+        assert_eq!(player_state.pending_ojama, 1);
+
+        // 全消しボーナスは次の連鎖で上乗せされ、使い切る
+        player_state.zenkeshi = true;
+        assert_eq!(offset_ojama(&mut player_state, 60, 70), 30);
+        assert_eq!(player_state.carry_over, 0);
+        assert_eq!(player_state.pending_ojama, 0);
+        assert!(!player_state.zenkeshi);
+    }
+
+    #[test]
+    fn test_offset_ojama_with_margin_time() {
+        let rules = Rules {
+            margin_time: Some(MarginTime::new(100, 10)),
+            ..Rules::tsu()
+        };
+        let mut player_state = PlayerState::zero().with_rules(rules);
+
+        // レートが下がると同じ点数でも多く送れる
+        assert_eq!(
+            offset_ojama(&mut player_state, 780, rules.ojama_rate_at(0)),
+            11
+        );
+        assert_eq!(
+            offset_ojama(&mut player_state, 780, rules.ojama_rate_at(110)),
+            20
+        );
+    }
+
+    #[test]
+    fn test_visible_state() {
+        let mut player_state = PlayerState::zero();
+        let field_before = player_state.field.clone();
+        let ojama_drop_frame = player_state.field.es_drop_ojama(6, Some(0));
+        let falling_ojama = Some(FallingOjama {
+            until_frame: 100 + ojama_drop_frame,
+            field_before: field_before.clone(),
+        });
+
+        // 降り終わるまでは降る前の盤面が見える
+        assert_eq!(
+            visible_state(&player_state, &falling_ojama, 100).field,
+            field_before
+        );
+        // 降り終わったら降った後の盤面が見える
+        assert_eq!(
+            visible_state(&player_state, &falling_ojama, 100 + ojama_drop_frame).field,
+            player_state.field
+        );
+        assert_eq!(
+            visible_state(&player_state, &None, 100).field,
+            player_state.field
+        );
+    }
+
     #[test]
     fn test_vanish_single_chain() {
         let mut cf = CoreField::from_str(concat!(
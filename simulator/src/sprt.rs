@@ -0,0 +1,236 @@
+//! 逐次確率比検定（SPRT）で、2 つの AI の強さの差を必要なだけの試合数で判定する
+//!
+//! 「Elo の差が `elo0`」（H0）と「`elo1`」（H1）のどちらがもっともらしいかを、
+//! 対数尤度比（LLR）が上下の境界を越えるまで試合を続けて決める。
+//! LLR は、1 標本あたりの得点を正規分布で近似したもの（一般化 SPRT）を使う。
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use super::pentanomial::{elo_to_score, Pentanomial};
+
+/// 1 標本あたりの得点の分散の下限
+///
+/// 全部同じ結果（毎組 2-0 など）だと分散が 0 になって LLR が決まらないので、
+/// これより小さくは見積もらない（普通の対戦の分散はこれより十分大きい）。
+const MIN_VARIANCE: f64 = 1e-2;
+
+/// 検定の設定
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Sprt {
+    /// H0 の Elo の差
+    pub elo0: f64,
+    /// H1 の Elo の差
+    pub elo1: f64,
+    /// H0 が正しいのに H1 を採択する確率
+    pub alpha: f64,
+    /// H1 が正しいのに H0 を採択する確率
+    pub beta: f64,
+}
+
+/// 検定の結果
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SprtResult {
+    /// H0 を採択（`elo0` 程度の差しかない）
+    AcceptH0,
+    /// H1 を採択（`elo1` 以上の差がある）
+    AcceptH1,
+    /// まだ決まらない
+    Continue,
+}
+
+/// 打ち切ったときの LLR と結果
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SprtOutcome {
+    pub sprt: Sprt,
+    pub llr: f64,
+    pub result: SprtResult,
+}
+
+impl Sprt {
+    /// 設定がおかしくないかは `validate` で確かめる
+    pub fn new(elo0: f64, elo1: f64, alpha: f64, beta: f64) -> Self {
+        Sprt {
+            elo0,
+            elo1,
+            alpha,
+            beta,
+        }
+    }
+
+    /// 検定できる設定か確かめる
+    pub fn validate(&self) -> Result<(), String> {
+        if !(self.elo0 < self.elo1) {
+            return Err(format!(
+                "elo0 must be less than elo1: {} >= {}",
+                self.elo0, self.elo1
+            ));
+        }
+        for (name, p) in [("alpha", self.alpha), ("beta", self.beta)] {
+            if !(0.0 < p && p < 1.0) {
+                return Err(format!("{} must be between 0 and 1: {}", name, p));
+            }
+        }
+        Ok(())
+    }
+
+    /// LLR の (下限, 上限)（下限を下回れば H0、上限を上回れば H1 を採択）
+    pub fn bounds(&self) -> (f64, f64) {
+        (
+            (self.beta / (1.0 - self.alpha)).ln(),
+            ((1.0 - self.beta) / self.alpha).ln(),
+        )
+    }
+
+    /// 先後を入れ替えた組ごとの結果から LLR を計算する
+    pub fn llr_pentanomial(&self, pentanomial: &Pentanomial) -> f64 {
+        self.llr(&pentanomial.counts, &[0.0, 0.25, 0.5, 0.75, 1.0])
+    }
+
+    /// 1 試合ずつの勝ち・引き分け・負けの数から LLR を計算する
+    pub fn llr_trinomial(&self, wins: usize, draws: usize, losses: usize) -> f64 {
+        self.llr(&[losses, draws, wins], &[0.0, 0.5, 1.0])
+    }
+
+    /// 得点が `scores[i]` だった標本が `counts[i]` 個あるときの LLR
+    fn llr(&self, counts: &[usize], scores: &[f64]) -> f64 {
+        let n: usize = counts.iter().sum();
+        if n == 0 {
+            return 0.0;
+        }
+        let n = n as f64;
+        let mean = counts
+            .iter()
+            .zip(scores)
+            .map(|(&c, &s)| c as f64 * s)
+            .sum::<f64>()
+            / n;
+        let variance = counts
+            .iter()
+            .zip(scores)
+            .map(|(&c, &s)| c as f64 * (s - mean).powi(2))
+            .sum::<f64>()
+            / n;
+        // 全部同じ結果でも判定が進むように、分散を小さく見積もりすぎない
+        let variance = variance.max(MIN_VARIANCE);
+
+        let score0 = elo_to_score(self.elo0);
+        let score1 = elo_to_score(self.elo1);
+        n * (score1 - score0) * (2.0 * mean - score0 - score1) / (2.0 * variance)
+    }
+
+    /// LLR から結果を判定する
+    pub fn judge(&self, llr: f64) -> SprtResult {
+        let (lower, upper) = self.bounds();
+        if llr <= lower {
+            SprtResult::AcceptH0
+        } else if llr >= upper {
+            SprtResult::AcceptH1
+        } else {
+            SprtResult::Continue
+        }
+    }
+
+    pub fn outcome(&self, llr: f64) -> SprtOutcome {
+        SprtOutcome {
+            sprt: *self,
+            llr,
+            result: self.judge(llr),
+        }
+    }
+}
+
+impl fmt::Display for SprtOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (lower, upper) = self.sprt.bounds();
+        write!(
+            f,
+            "LLR {:.2} [{:.2}, {:.2}] (elo0: {}, elo1: {}) => {}",
+            self.llr,
+            lower,
+            upper,
+            self.sprt.elo0,
+            self.sprt.elo1,
+            match self.result {
+                SprtResult::AcceptH0 => "H0 accepted",
+                SprtResult::AcceptH1 => "H1 accepted",
+                SprtResult::Continue => "inconclusive",
+            }
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bounds() {
+        let (lower, upper) = Sprt::new(0.0, 5.0, 0.05, 0.05).bounds();
+        assert!((lower + 2.944).abs() < 1e-3);
+        assert!((upper - 2.944).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_llr() {
+        let sprt = Sprt::new(0.0, 20.0, 0.05, 0.05);
+        assert_eq!(sprt.llr_trinomial(0, 0, 0), 0.0);
+
+        // 勝ち越していれば H1 寄り、負け越していれば H0 寄り
+        assert!(sprt.llr_trinomial(60, 0, 40) > 0.0);
+        assert!(sprt.llr_trinomial(40, 0, 60) < 0.0);
+        // 同じ比率なら試合数に比例する
+        let llr = sprt.llr_trinomial(60, 0, 40);
+        assert!((sprt.llr_trinomial(600, 0, 400) - 10.0 * llr).abs() < 1e-9);
+
+        assert_eq!(
+            sprt.judge(sprt.llr_trinomial(600, 0, 400)),
+            SprtResult::AcceptH1
+        );
+        assert_eq!(
+            sprt.judge(sprt.llr_trinomial(400, 0, 600)),
+            SprtResult::AcceptH0
+        );
+        assert_eq!(sprt.judge(llr), SprtResult::Continue);
+    }
+
+    #[test]
+    fn test_llr_pentanomial() {
+        let sprt = Sprt::new(0.0, 20.0, 0.05, 0.05);
+
+        // 同じ勝率でも、組ごとのばらつきが小さい方が早く決まる
+        let paired = Pentanomial {
+            counts: [0, 0, 40, 20, 0],
+        };
+        let unpaired = Pentanomial {
+            counts: [15, 0, 20, 0, 25],
+        };
+        assert_eq!(paired.score(), unpaired.score());
+        assert!(sprt.llr_pentanomial(&paired) > sprt.llr_pentanomial(&unpaired));
+    }
+
+    #[test]
+    fn test_llr_one_sided() {
+        let sprt = Sprt::new(0.0, 20.0, 0.05, 0.05);
+
+        // 毎組 2-0 なら、いずれ H1 を採択する（分散が 0 でも止まる）
+        let llr = |counts: [usize; 5]| sprt.llr_pentanomial(&Pentanomial { counts });
+        assert!(llr([0, 0, 0, 0, 1]) > 0.0);
+        assert_eq!(sprt.judge(llr([0, 0, 0, 0, 1])), SprtResult::Continue);
+        assert_eq!(sprt.judge(llr([0, 0, 0, 0, 100])), SprtResult::AcceptH1);
+        assert_eq!(sprt.judge(llr([100, 0, 0, 0, 0])), SprtResult::AcceptH0);
+        // 毎組 1-1 なら、差がない方を採択する
+        assert_eq!(sprt.judge(llr([0, 0, 1000, 0, 0])), SprtResult::AcceptH0);
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(Sprt::new(0.0, 5.0, 0.05, 0.05).validate().is_ok());
+        assert!(Sprt::new(5.0, 5.0, 0.05, 0.05).validate().is_err());
+        assert!(Sprt::new(5.0, 0.0, 0.05, 0.05).validate().is_err());
+        assert!(Sprt::new(f64::NAN, 5.0, 0.05, 0.05).validate().is_err());
+        assert!(Sprt::new(0.0, 5.0, 0.0, 0.05).validate().is_err());
+        assert!(Sprt::new(0.0, 5.0, 0.05, 1.0).validate().is_err());
+    }
+}
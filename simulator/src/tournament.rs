@@ -0,0 +1,216 @@
+//! 複数の AI を総当たり・スイス式で対戦させるための、組み合わせとレーティングの計算
+
+use std::collections::HashSet;
+
+use serde::{Deserialize, Serialize};
+
+/// 組み合わせの決め方
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PairingFormat {
+    /// 総当たり
+    RoundRobin,
+    /// スイス式（成績の近い相手と、まだ対戦していなければ当たる）
+    Swiss,
+}
+
+impl PairingFormat {
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "round-robin" | "roundrobin" | "rr" => Some(PairingFormat::RoundRobin),
+            "swiss" => Some(PairingFormat::Swiss),
+            _ => None,
+        }
+    }
+}
+
+/// 総当たりの組み合わせ（`i < j` の全ペア）
+pub fn round_robin_pairings(num_players: usize) -> Vec<(usize, usize)> {
+    (0..num_players)
+        .flat_map(|i| ((i + 1)..num_players).map(move |j| (i, j)))
+        .collect()
+}
+
+/// スイス式の 1 ラウンド分の組み合わせと、不戦勝になるプレイヤーを返す
+///
+/// 勝ち点の高い順に、まだ当たっていない中で一番勝ち点の近い相手と組ませる
+/// （全員と当たり済みなら再戦させる）。奇数人なら、まだ不戦勝になっていない中で一番下の人を休ませる。
+pub fn swiss_pairings(
+    points: &[f64],
+    played: &HashSet<(usize, usize)>,
+    had_bye: &[bool],
+) -> (Vec<(usize, usize)>, Option<usize>) {
+    // 勝ち点の高い順（同点なら番号順）
+    let mut order: Vec<usize> = (0..points.len()).collect();
+    order.sort_by(|&a, &b| points[b].partial_cmp(&points[a]).unwrap().then(a.cmp(&b)));
+
+    let bye = if order.len() % 2 == 1 {
+        let bye = order
+            .iter()
+            .rev()
+            .find(|&&i| !had_bye[i])
+            .copied()
+            .unwrap_or(*order.last().unwrap());
+        order.retain(|&i| i != bye);
+        Some(bye)
+    } else {
+        None
+    };
+
+    let has_played = |a: usize, b: usize| played.contains(&(a.min(b), a.max(b)));
+    let mut pairings = vec![];
+    while let Some(a) = order.first().copied() {
+        order.remove(0);
+        let index = order.iter().position(|&b| !has_played(a, b)).unwrap_or(0);
+        let b = order.remove(index);
+        pairings.push((a.min(b), a.max(b)));
+    }
+
+    (pairings, bye)
+}
+
+/// Bradley–Terry モデルの強さ（幾何平均が 1 になるように正規化）
+///
+/// `wins[i][j]` は i が j に勝った数（引き分けは 0.5 ずつ足しておく）。
+/// 全勝・全敗でも発散しないように、対戦したペアには 1 試合分の引き分けを足してから MM 法で解く。
+pub fn bradley_terry(wins: &[Vec<f64>]) -> Vec<f64> {
+    let n = wins.len();
+    let wins: Vec<Vec<f64>> = (0..n)
+        .map(|i| {
+            (0..n)
+                .map(|j| {
+                    if i != j && wins[i][j] + wins[j][i] > 0.0 {
+                        wins[i][j] + 0.5
+                    } else {
+                        wins[i][j]
+                    }
+                })
+                .collect()
+        })
+        .collect();
+
+    let mut strengths = vec![1.0; n];
+    for _ in 0..10000 {
+        let mut next = vec![0.0; n];
+        for i in 0..n {
+            let total_wins: f64 = wins[i].iter().sum();
+            let denominator: f64 = (0..n)
+                .filter(|&j| j != i)
+                .map(|j| (wins[i][j] + wins[j][i]) / (strengths[i] + strengths[j]))
+                .sum();
+            next[i] = if denominator > 0.0 {
+                total_wins / denominator
+            } else {
+                strengths[i]
+            };
+        }
+
+        // 幾何平均で正規化
+        let log_mean = next.iter().map(|p: &f64| p.ln()).sum::<f64>() / n as f64;
+        for p in next.iter_mut() {
+            *p /= log_mean.exp();
+        }
+
+        let diff = (0..n)
+            .map(|i| (next[i] - strengths[i]).abs())
+            .fold(0.0, f64::max);
+        strengths = next;
+        if diff < 1e-9 {
+            break;
+        }
+    }
+    strengths
+}
+
+/// Elo 形式のレーティングと、その 95% 信頼区間の幅
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Rating {
+    /// Bradley–Terry の強さ（幾何平均が 1）
+    pub strength: f64,
+    /// Elo 形式のレーティング（平均が 1500）
+    pub elo: f64,
+    /// 95% 信頼区間の幅（`elo ± elo_ci95`）
+    pub elo_ci95: f64,
+}
+
+/// `wins` から Elo 形式のレーティングを計算する
+///
+/// 信頼区間は Fisher 情報量の対角成分だけを使った近似。
+pub fn elo_ratings(wins: &[Vec<f64>]) -> Vec<Rating> {
+    const ELO_SCALE: f64 = 400.0 / std::f64::consts::LN_10;
+
+    let n = wins.len();
+    let strengths = bradley_terry(wins);
+    (0..n)
+        .map(|i| {
+            let information: f64 = (0..n)
+                .filter(|&j| j != i)
+                .map(|j| {
+                    let games = wins[i][j] + wins[j][i];
+                    let p = strengths[i] / (strengths[i] + strengths[j]);
+                    games * p * (1.0 - p)
+                })
+                .sum();
+            Rating {
+                strength: strengths[i],
+                elo: 1500.0 + ELO_SCALE * strengths[i].ln(),
+                elo_ci95: if information > 0.0 {
+                    1.96 * ELO_SCALE / information.sqrt()
+                } else {
+                    f64::INFINITY
+                },
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_robin_pairings() {
+        assert_eq!(round_robin_pairings(3), vec![(0, 1), (0, 2), (1, 2)]);
+        assert_eq!(round_robin_pairings(6).len(), 15);
+    }
+
+    #[test]
+    fn test_swiss_pairings() {
+        let points = vec![2.0, 0.0, 1.0, 1.0, 0.5];
+        let mut played = HashSet::new();
+        played.insert((0, 2));
+
+        let (pairings, bye) = swiss_pairings(&points, &played, &[false; 5]);
+        // 一番下の 1 が休み、0 は当たり済みの 2 を避けて 3 と当たる
+        assert_eq!(bye, Some(1));
+        assert_eq!(pairings, vec![(0, 3), (2, 4)]);
+
+        // 不戦勝になったことがある人は休ませない
+        let (_, bye) = swiss_pairings(&points, &played, &[false, true, false, false, false]);
+        assert_eq!(bye, Some(4));
+    }
+
+    #[test]
+    fn test_elo_ratings() {
+        // 互角なら同じレーティング
+        let ratings = elo_ratings(&[vec![0.0, 5.0], vec![5.0, 0.0]]);
+        assert!((ratings[0].elo - 1500.0).abs() < 1e-6);
+        assert!((ratings[1].elo - 1500.0).abs() < 1e-6);
+
+        // 強い順に並び、全勝でも有限になる
+        let ratings = elo_ratings(&[
+            vec![0.0, 10.0, 10.0],
+            vec![0.0, 0.0, 7.0],
+            vec![0.0, 3.0, 0.0],
+        ]);
+        assert!(ratings[0].elo > ratings[1].elo);
+        assert!(ratings[1].elo > ratings[2].elo);
+        assert!(ratings
+            .iter()
+            .all(|r| r.elo.is_finite() && r.elo_ci95.is_finite()));
+
+        // 試合数が増えると信頼区間は狭くなる
+        let few = elo_ratings(&[vec![0.0, 3.0], vec![1.0, 0.0]]);
+        let many = elo_ratings(&[vec![0.0, 30.0], vec![10.0, 0.0]]);
+        assert!(many[0].elo_ci95 < few[0].elo_ci95);
+    }
+}
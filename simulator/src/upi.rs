@@ -0,0 +1,306 @@
+//! UPI プロトコルのコマンドの解釈と応答の生成
+//! （UPI: https://github.com/TukamotoRyuzo/upi-protocol/blob/master/README.md）
+//!
+//! 受け付けるコマンドは以下の通り
+//! - `upi` / `isready` / `upinewgame` / `stop` / `quit`
+//! - `tsumo <ツモ> <ツモ> ...`: 配ぷよ全体（例: `tsumo rb yy gr ...`）
+//! - `position startpos` / `position pfen <1P盤面> <2P盤面> <1Pツモ番号> <2Pツモ番号> <1P確定おじゃま> <2P確定おじゃま>`
+//! - `go [frame <思考フレーム数>]`
+//!
+//! 盤面は `convert_core_field` と同じ pfen 形式（列ごとに下から、`/` 区切り）で表す。
+//! 手は `bestmove <軸ぷよの列><子ぷよの向き>` の形式で返す（向きは `u`/`r`/`d`/`l` が回転 0/1/2/3 に対応）。
+
+use std::{error::Error, fmt};
+
+use cpu::bot::PlayerState;
+use puyoai::{decision::Decision, field::CoreField, kumipuyo::Kumipuyo};
+
+use super::convert::{revert_core_field, revert_kumipuyo_seq};
+
+#[derive(Debug)]
+pub enum UpiParseError {
+    InvalidCommand(String),
+    InvalidPosition(String),
+    InvalidTsumo(String),
+}
+
+impl fmt::Display for UpiParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            UpiParseError::InvalidCommand(msg) => write!(f, "Invalid command: {}", msg),
+            UpiParseError::InvalidPosition(msg) => write!(f, "Invalid position: {}", msg),
+            UpiParseError::InvalidTsumo(msg) => write!(f, "Invalid tsumo: {}", msg),
+        }
+    }
+}
+
+impl Error for UpiParseError {}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum UpiCommand {
+    Upi,
+    IsReady,
+    UpiNewGame,
+    Tsumo(Vec<Kumipuyo>),
+    Position(Box<UpiPosition>),
+    /// 思考フレーム数（`AI::think` の `think_frame`）
+    Go(Option<usize>),
+    Stop,
+    Quit,
+}
+
+/// `position` コマンドで渡される局面
+#[derive(Clone, Debug, PartialEq)]
+pub struct UpiPosition {
+    pub field_1p: CoreField,
+    pub field_2p: CoreField,
+    pub tumo_index_1p: usize,
+    pub tumo_index_2p: usize,
+    pub fixed_ojama_1p: usize,
+    pub fixed_ojama_2p: usize,
+}
+
+impl UpiPosition {
+    pub fn startpos() -> Self {
+        UpiPosition {
+            field_1p: CoreField::new(),
+            field_2p: CoreField::new(),
+            tumo_index_1p: 0,
+            tumo_index_2p: 0,
+            fixed_ojama_1p: 0,
+            fixed_ojama_2p: 0,
+        }
+    }
+
+    /// 配ぷよを元に、(自分, 相手) の `PlayerState` を作る
+    pub fn to_player_states(
+        &self,
+        haipuyo: &[Kumipuyo],
+        visible_tumos: usize,
+    ) -> (PlayerState, PlayerState) {
+        let player_state = |field: &CoreField, tumo_index: usize, fixed_ojama: usize| {
+            let mut player_state = PlayerState::new(
+                0,
+                field.clone(),
+                vec![],
+                0,
+                0,
+                fixed_ojama,
+                0,
+                false,
+                0,
+                tumo_index,
+                Some(haipuyo.to_vec()),
+            );
+            player_state.set_seq(visible_tumos);
+            player_state
+        };
+
+        (
+            player_state(&self.field_1p, self.tumo_index_1p, self.fixed_ojama_1p),
+            player_state(&self.field_2p, self.tumo_index_2p, self.fixed_ojama_2p),
+        )
+    }
+}
+
+impl UpiCommand {
+    pub fn parse(line: &str) -> Result<Self, UpiParseError> {
+        let mut tokens = line.split_whitespace();
+        let command = tokens
+            .next()
+            .ok_or_else(|| UpiParseError::InvalidCommand("empty line".to_string()))?;
+        let args: Vec<&str> = tokens.collect();
+
+        match command {
+            "upi" => Ok(UpiCommand::Upi),
+            "isready" => Ok(UpiCommand::IsReady),
+            "upinewgame" => Ok(UpiCommand::UpiNewGame),
+            "tsumo" => Ok(UpiCommand::Tsumo(parse_tsumo(&args)?)),
+            "position" => Ok(UpiCommand::Position(Box::new(parse_position(&args)?))),
+            "go" => Ok(UpiCommand::Go(parse_go(&args)?)),
+            "stop" => Ok(UpiCommand::Stop),
+            "quit" => Ok(UpiCommand::Quit),
+            _ => Err(UpiParseError::InvalidCommand(command.to_string())),
+        }
+    }
+}
+
+/// 手を UPI の形式に変換（例: `Decision::new(3, 1)` -> `"3r"`）
+pub fn format_move(decision: &Decision) -> String {
+    let direction = match decision.rot() {
+        0 => 'u',
+        1 => 'r',
+        2 => 'd',
+        3 => 'l',
+        _ => unreachable!(),
+    };
+    format!("{}{}", decision.axis_x(), direction)
+}
+
+fn parse_tsumo(args: &[&str]) -> Result<Vec<Kumipuyo>, UpiParseError> {
+    if args.is_empty() {
+        return Err(UpiParseError::InvalidTsumo("no tsumo given".to_string()));
+    }
+
+    let mut tumos = vec![];
+    for tumo in args {
+        if tumo.len() != 2 || !tumo.chars().all(|c| "rbygRBYG".contains(c)) {
+            return Err(UpiParseError::InvalidTsumo(tumo.to_string()));
+        }
+        tumos.push(tumo.to_ascii_uppercase());
+    }
+
+    Ok(revert_kumipuyo_seq(&tumos))
+}
+
+fn parse_position(args: &[&str]) -> Result<UpiPosition, UpiParseError> {
+    match args.first() {
+        Some(&"startpos") => Ok(UpiPosition::startpos()),
+        Some(&"pfen") => {
+            if args.len() != 7 {
+                return Err(UpiParseError::InvalidPosition(format!(
+                    "pfen needs 6 fields, got {}",
+                    args.len() - 1
+                )));
+            }
+
+            let number = |s: &str| {
+                s.parse::<usize>()
+                    .map_err(|_| UpiParseError::InvalidPosition(format!("Invalid number: {}", s)))
+            };
+
+            Ok(UpiPosition {
+                field_1p: parse_pfen_field(args[1])?,
+                field_2p: parse_pfen_field(args[2])?,
+                tumo_index_1p: number(args[3])?,
+                tumo_index_2p: number(args[4])?,
+                fixed_ojama_1p: number(args[5])?,
+                fixed_ojama_2p: number(args[6])?,
+            })
+        }
+        _ => Err(UpiParseError::InvalidPosition(args.join(" "))),
+    }
+}
+
+fn parse_pfen_field(pfen: &str) -> Result<CoreField, UpiParseError> {
+    let columns = pfen.split('/').collect::<Vec<&str>>();
+    // 末尾にも `/` が付くので 7 個に分かれる
+    if columns.len() != 7 || !columns[6].is_empty() {
+        return Err(UpiParseError::InvalidPosition(format!(
+            "Invalid pfen: {}",
+            pfen
+        )));
+    }
+    if columns
+        .iter()
+        .any(|column| column.len() > 14 || !column.chars().all(|c| "rbygo".contains(c)))
+    {
+        return Err(UpiParseError::InvalidPosition(format!(
+            "Invalid pfen: {}",
+            pfen
+        )));
+    }
+
+    Ok(revert_core_field(pfen))
+}
+
+fn parse_go(args: &[&str]) -> Result<Option<usize>, UpiParseError> {
+    match args {
+        [] => Ok(None),
+        ["frame", frame] => frame
+            .parse::<usize>()
+            .map(Some)
+            .map_err(|_| UpiParseError::InvalidCommand(format!("Invalid frame: {}", frame))),
+        _ => Err(UpiParseError::InvalidCommand(format!(
+            "go {}",
+            args.join(" ")
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use puyoai::color::PuyoColor;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_simple_commands() {
+        assert_eq!(UpiCommand::parse("upi").unwrap(), UpiCommand::Upi);
+        assert_eq!(UpiCommand::parse("isready").unwrap(), UpiCommand::IsReady);
+        assert_eq!(UpiCommand::parse(" quit ").unwrap(), UpiCommand::Quit);
+        assert_eq!(UpiCommand::parse("go").unwrap(), UpiCommand::Go(None));
+        assert_eq!(
+            UpiCommand::parse("go frame 8").unwrap(),
+            UpiCommand::Go(Some(8))
+        );
+        assert!(UpiCommand::parse("").is_err());
+        assert!(UpiCommand::parse("usi").is_err());
+    }
+
+    #[test]
+    fn test_parse_tsumo() {
+        assert_eq!(
+            UpiCommand::parse("tsumo rb yy").unwrap(),
+            UpiCommand::Tsumo(vec![
+                Kumipuyo::new(PuyoColor::RED, PuyoColor::BLUE),
+                Kumipuyo::new(PuyoColor::YELLOW, PuyoColor::YELLOW),
+            ])
+        );
+        assert!(UpiCommand::parse("tsumo ro").is_err());
+        assert!(UpiCommand::parse("tsumo rbg").is_err());
+    }
+
+    #[test]
+    fn test_parse_position() {
+        assert_eq!(
+            UpiCommand::parse("position startpos").unwrap(),
+            UpiCommand::Position(Box::new(UpiPosition::startpos()))
+        );
+
+        let position = match UpiCommand::parse("position pfen rr/b///// ////oo// 2 3 0 12").unwrap()
+        {
+            UpiCommand::Position(position) => position,
+            _ => unreachable!(),
+        };
+        assert_eq!(position.field_1p, revert_core_field("rr/b/////"));
+        assert_eq!(position.field_2p, revert_core_field("////oo//"));
+        assert_eq!(position.tumo_index_1p, 2);
+        assert_eq!(position.tumo_index_2p, 3);
+        assert_eq!(position.fixed_ojama_1p, 0);
+        assert_eq!(position.fixed_ojama_2p, 12);
+
+        assert!(UpiCommand::parse("position pfen rr/b///// 2 3 0 12").is_err());
+        assert!(UpiCommand::parse("position pfen rr/x///// ////// 0 0 0 0").is_err());
+    }
+
+    #[test]
+    fn test_to_player_states() {
+        let haipuyo = vec![
+            Kumipuyo::new(PuyoColor::RED, PuyoColor::BLUE),
+            Kumipuyo::new(PuyoColor::YELLOW, PuyoColor::YELLOW),
+            Kumipuyo::new(PuyoColor::GREEN, PuyoColor::RED),
+        ];
+        let position = UpiPosition {
+            tumo_index_2p: 2,
+            fixed_ojama_2p: 5,
+            ..UpiPosition::startpos()
+        };
+
+        let (player_state_1p, player_state_2p) = position.to_player_states(&haipuyo, 2);
+        assert_eq!(player_state_1p.seq, haipuyo[0..2].to_vec());
+        assert_eq!(
+            player_state_2p.seq,
+            vec![haipuyo[2].clone(), haipuyo[0].clone()]
+        );
+        assert_eq!(player_state_2p.fixed_ojama, 5);
+        assert_eq!(player_state_2p.tumo_index, 2);
+    }
+
+    #[test]
+    fn test_format_move() {
+        assert_eq!(format_move(&Decision::new(3, 0)), "3u");
+        assert_eq!(format_move(&Decision::new(1, 1)), "1r");
+        assert_eq!(format_move(&Decision::new(6, 3)), "6l");
+    }
+}